@@ -20,6 +20,7 @@ export interface PeerInfo {
   alias: string;
   pubkey: string;  // duplicate: same as id in our build (kept for compat)
   last_seen_ms?: number;
+  away?: boolean;  // quiet for a while; dropped if it stays quiet
 }
 
 /**
@@ -29,8 +30,14 @@ export interface PeerInfo {
 export interface ChatBody {
   from: string;
   to?: string | null;
+  to_many?: string[];     // multi-recipient direct chat (`to` is null)
   text: string;
   ts_ms: number;
+  id?: string;            // display id (see backend ChatSigned::message_id)
+  expires_at_ms?: number; // hidden from history after this
+  reply_to?: string;      // id of the parent chat
+  edited?: boolean;       // text is the author's latest edit
+  from_name?: string;     // sender's name in our contacts
 }
 
 /**
@@ -42,6 +49,20 @@ export interface GroupInfo {
   name?: string;     // optional group name
 }
 
+/** What every command rejects with; `message` is the readable text. */
+export interface WiChainError {
+  kind: 'PeerNotFound' | 'NotFound' | 'NetworkSend' | 'Encryption' | 'Io' | 'InvalidInput' | 'NotPermitted';
+  message: string;
+}
+
+/** Readable text of a rejected command (or anything else thrown). */
+export function errorMessage(err: unknown): string {
+  if (err && typeof err === 'object' && 'message' in err) {
+    return String((err as { message: unknown }).message);
+  }
+  return String(err);
+}
+
 /* ------------------------------------------------------------------ */
 /* Identity                                                           */
 /* ------------------------------------------------------------------ */
@@ -63,6 +84,69 @@ export async function apiSetAlias(newAlias: string): Promise<boolean> {
   }
 }
 
+export interface ProfileInfo {
+  name: string;
+  alias: string;
+  public_key_b64: string;
+  active: boolean;
+}
+
+/** Local identity profiles, the active one flagged. */
+export async function apiListProfiles(): Promise<ProfileInfo[]> {
+  try {
+    return await invoke<ProfileInfo[]>('list_profiles');
+  } catch (err) {
+    console.error('list_profiles failed', err);
+    return [];
+  }
+}
+
+/** Create a profile with a fresh identity; resolves to its name. */
+export async function apiCreateProfile(alias: string): Promise<string> {
+  return invoke<string>('create_profile', { alias });
+}
+
+/** Switch to another profile (restarts networking under its identity). */
+export async function apiSwitchProfile(name: string): Promise<void> {
+  await invoke('switch_profile', { name });
+}
+
+export interface Fingerprint {
+  code: string;    // e.g. "3FA9 1C0D 77E2"
+  words: string[];
+}
+
+/** Safety code for a pubkey, to compare aloud with the other person. */
+export async function apiGetFingerprint(pubkey: string): Promise<Fingerprint | null> {
+  try {
+    return await invoke<Fingerprint>('get_fingerprint', { pubkey });
+  } catch (err) {
+    console.error('get_fingerprint failed', err);
+    return null;
+  }
+}
+
+/**
+ * Encrypt the identity key on disk under a password (min 8 chars). Later
+ * starts need it in WICHAIN_IDENTITY_PASSWORD. Rejects with a readable error.
+ */
+export async function apiSetIdentityPassword(password: string): Promise<void> {
+  await invoke('set_identity_password', { password });
+}
+
+/** 24-word backup phrase for the identity key. Keep it secret. */
+export async function apiExportSeedPhrase(): Promise<string> {
+  return invoke<string>('export_seed_phrase');
+}
+
+/**
+ * Restore the identity from a backup phrase; resolves to the restored pubkey
+ * (applied on next restart). Rejects with a readable error for a bad phrase.
+ */
+export async function apiRestoreFromSeedPhrase(phrase: string): Promise<string> {
+  return invoke<string>('restore_from_seed_phrase', { phrase });
+}
+
 
 /* ------------------------------------------------------------------ */
 /* Peers                                                              */
@@ -78,6 +162,75 @@ export async function apiGetPeers(): Promise<PeerInfo[]> {
   }
 }
 
+/**
+ * Add the peer listening on `ip:port` (its discovery port) when discovery
+ * can't find it. Rejects with a readable error if nothing answers there.
+ */
+export async function apiConnectToAddress(ip: string, port: number): Promise<PeerInfo> {
+  return invoke<PeerInfo>('connect_to_address', { ip, port });
+}
+
+export interface PeerCapabilities {
+  ciphers: string[] | null; // from the peer's announce; null if it sent none
+  selected: 'xor-v1' | 'aes-256-gcm' | 'x25519-eph-aes-256-gcm';
+}
+
+/** Ciphers a peer announced and the one its messages are sealed with. */
+export async function apiGetPeerCapabilities(peerId: string): Promise<PeerCapabilities> {
+  return invoke<PeerCapabilities>('get_peer_capabilities', { peer_id: peerId, peerId });
+}
+
+export interface Contact {
+  pubkey: string;
+  display_name: string;
+  added_ms: number;
+}
+
+/** Contacts, sorted by name. Their names replace peers' advertised aliases. */
+export async function apiListContacts(): Promise<Contact[]> {
+  try {
+    return await invoke<Contact[]>('list_contacts');
+  } catch (err) {
+    console.error('list_contacts failed', err);
+    return [];
+  }
+}
+
+/** Save (or rename) a contact. Rejects with a readable error (bad key, empty name). */
+export async function apiAddContact(pubkey: string, displayName: string): Promise<Contact> {
+  return invoke<Contact>('add_contact', { pubkey, display_name: displayName, displayName });
+}
+
+export async function apiRemoveContact(pubkey: string): Promise<boolean> {
+  try {
+    return await invoke<boolean>('remove_contact', { pubkey });
+  } catch (err) {
+    console.error('remove_contact failed', err);
+    return false;
+  }
+}
+
+export interface NetworkMetrics {
+  datagrams_sent: number;
+  datagrams_received: number;
+  bytes_sent: number;
+  bytes_received: number;
+  tcp_messages_sent: number;
+  tcp_messages_received: number;
+  parse_failures: number;
+  peer_count: number;
+}
+
+/** Traffic counters since the node started (diagnostics panel). */
+export async function apiGetNetworkMetrics(): Promise<NetworkMetrics | null> {
+  try {
+    return await invoke<NetworkMetrics>('get_network_metrics');
+  } catch (err) {
+    console.error('get_network_metrics failed', err);
+    return null;
+  }
+}
+
 /* ------------------------------------------------------------------ */
 /* Groups                                                             */
 /* ------------------------------------------------------------------ */
@@ -115,25 +268,150 @@ export async function apiListGroups(): Promise<GroupInfo[]> {
 export async function apiAddPeerMessage(
   text: string,
   peerId: string,
+  replyTo?: string,
 ): Promise<boolean> {
+  return (await apiSendPeerMessage(text, peerId, replyTo)) !== null;
+}
+
+/** Like `apiAddPeerMessage`, but resolves to the new message's id (null on failure). */
+export async function apiSendPeerMessage(
+  text: string,
+  peerId: string,
+  replyTo?: string,
+): Promise<string | null> {
   try {
     const pid = peerId?.trim();
     if (!pid) {
-      console.warn('apiAddPeerMessage: empty peerId');
-      return false;
+      console.warn('apiSendPeerMessage: empty peerId');
+      return null;
     }
-    await invoke('add_chat_message', {
+    return await invoke<string>('add_chat_message', {
       content: text,
       to_peer: pid, // new backend
       toPeer: pid,  // older backend
+      reply_to: replyTo ?? null,
+      replyTo: replyTo ?? null,
     });
-    return true;
   } catch (err) {
     console.error('add_chat_message failed', err);
+    return null;
+  }
+}
+
+export type DeliveryStatus = 'failed' | 'queued' | 'sent' | 'delivered';
+
+/**
+ * Overall delivery status of a message sent this session (least advanced
+ * recipient wins); null if unknown. Live changes arrive as `message_status`
+ * events `{ id, status }`.
+ */
+export async function apiGetMessageStatus(msgId: string): Promise<DeliveryStatus | null> {
+  try {
+    return await invoke<DeliveryStatus | null>('get_message_status', { msg_id: msgId, msgId });
+  } catch (err) {
+    console.error('get_message_status failed', err);
+    return null;
+  }
+}
+
+/** Progress of a chain resync, emitted as `resync_update`. */
+export interface ResyncUpdate {
+  peer: string;
+  imported: number;
+  done: boolean;
+  error?: string;
+}
+
+/** Ask a peer for the chats on its chain we're missing (progress via `resync_update`). */
+export async function apiResyncWithPeer(peerId: string): Promise<void> {
+  return invoke<void>('resync_with_peer', { peer_id: peerId, peerId });
+}
+
+/** Send one message to several peers (signed once, sealed per peer). */
+export async function apiAddMultiMessage(
+  text: string,
+  peerIds: string[],
+): Promise<boolean> {
+  try {
+    await invoke('add_multi_chat_message', {
+      content: text,
+      to_peers: peerIds,
+      toPeers: peerIds,
+    });
+    return true;
+  } catch (err) {
+    console.error('add_multi_chat_message failed', err);
     return false;
   }
 }
 
+/** Edit one of our own messages. */
+export async function apiEditMessage(id: string, newText: string): Promise<boolean> {
+  try {
+    await invoke('edit_message', { id, new_text: newText, newText });
+    return true;
+  } catch (err) {
+    console.error('edit_message failed', err);
+    return false;
+  }
+}
+
+/** Unsend one of our own messages. */
+export async function apiDeleteMessage(id: string): Promise<boolean> {
+  try {
+    await invoke('delete_message', { id });
+    return true;
+  } catch (err) {
+    console.error('delete_message failed', err);
+    return false;
+  }
+}
+
+/** Toggle our emoji reaction on a message. */
+export async function apiReactToMessage(msgId: string, emoji: string): Promise<boolean> {
+  try {
+    await invoke('react_to_message', { msg_id: msgId, msgId, emoji });
+    return true;
+  } catch (err) {
+    console.error('react_to_message failed', err);
+    return false;
+  }
+}
+
+/** [emoji, count, reactor pubkeys], most popular first. */
+export type ReactionCount = [string, number, string[]];
+
+/** Reactions to a message. */
+export async function apiGetReactions(msgId: string): Promise<ReactionCount[]> {
+  try {
+    return await invoke<ReactionCount[]>('get_reactions', { msg_id: msgId, msgId });
+  } catch (err) {
+    console.error('get_reactions failed', err);
+    return [];
+  }
+}
+
+/** Tell the author of a received message that we have read it. */
+export async function apiMarkRead(msgId: string): Promise<boolean> {
+  try {
+    await invoke('mark_read', { msg_id: msgId, msgId });
+    return true;
+  } catch (err) {
+    console.error('mark_read failed', err);
+    return false;
+  }
+}
+
+/** When one of our messages was first read (ms since epoch), or null if unread. */
+export async function apiGetReadStatus(msgId: string): Promise<number | null> {
+  try {
+    return await invoke<number | null>('get_read_status', { msg_id: msgId, msgId });
+  } catch (err) {
+    console.error('get_read_status failed', err);
+    return null;
+  }
+}
+
 /** Send *group* message. */
 export async function apiAddGroupMessage(
   text: string,
@@ -238,6 +516,16 @@ export async function apiGetConnectionStats(peerId: string): Promise<ConnectionS
   }
 }
 
+/** Get a peer's totals across every TCP connection (kept across restarts). */
+export async function apiGetConnectionHistory(peerId: string): Promise<ConnectionHistory | null> {
+  try {
+    return await invoke<ConnectionHistory | null>('get_connection_history', { peer_id: peerId, peerId });
+  } catch (err) {
+    console.error('get_connection_history failed', err);
+    return null;
+  }
+}
+
 /** Update all peer connection types based on actual status. */
 export async function apiUpdateAllConnectionTypes(): Promise<boolean> {
   try {
@@ -258,7 +546,7 @@ export async function apiTestEncryptionWithPeer(peerId: string, testMessage: str
     });
   } catch (err) {
     console.error('test_encryption_with_peer failed', err);
-    return `❌ Encryption test failed: ${err}`;
+    return `❌ Encryption test failed: ${errorMessage(err)}`;
   }
 }
 
@@ -274,7 +562,8 @@ export async function apiGetNetworkStatus(): Promise<NetworkStatus> {
       tcp_port: 0,
       total_peers: 0,
       peer_statuses: [],
-      encryption_algorithm: 'Unknown'
+      encryption_algorithm: 'Unknown',
+      network_id: ''
     };
   }
 }
@@ -338,6 +627,35 @@ export async function apiExportMessagesToJson(): Promise<string> {
   }
 }
 
+/**
+ * Write chat history (one conversation, or all when `peerOrGroup` is null)
+ * to `path` as JSON or CSV. Resolves to the number of messages written;
+ * rejects with a readable error (e.g. unwritable path).
+ */
+export async function apiExportHistory(
+  peerOrGroup: string | null,
+  format: 'json' | 'csv',
+  path: string,
+): Promise<number> {
+  return invoke<number>('export_history', {
+    peer_or_group: peerOrGroup,
+    peerOrGroup,
+    format,
+    path,
+  });
+}
+
+export interface ImportReport {
+  imported: number;
+  duplicates: number; // already in history
+  skipped: number;    // malformed entries
+}
+
+/** Append the messages of a JSON history export. Rejects with a readable error. */
+export async function apiImportHistory(path: string): Promise<ImportReport> {
+  return invoke<ImportReport>('import_history', { path });
+}
+
 /** Test message sending with detailed logging. */
 export async function apiTestMessageSending(peerId: string, testMessage: string): Promise<string> {
   try {
@@ -347,7 +665,7 @@ export async function apiTestMessageSending(peerId: string, testMessage: string)
     });
   } catch (err) {
     console.error('test_message_sending failed', err);
-    return `❌ Message sending test failed: ${err}`;
+    return `❌ Message sending test failed: ${errorMessage(err)}`;
   }
 }
 
@@ -363,6 +681,17 @@ export interface ConnectionStats {
   last_test_time_ms?: number;
 }
 
+export interface ConnectionHistory {
+  peer_id: string;
+  messages_sent: number;
+  messages_received: number;
+  connects: number;
+  disconnects: number;
+  avg_rtt_ms?: number | null;
+  uptime_ms: number;
+  is_connected: boolean;
+}
+
 export interface NetworkStatus {
   my_id: string;
   udp_port: number;
@@ -370,6 +699,8 @@ export interface NetworkStatus {
   total_peers: number;
   peer_statuses: PeerStatus[];
   encryption_algorithm: string;
+  /** Network of this node's ledger; resyncs only merge chains on it. */
+  network_id: string;
 }
 
 export interface PeerStatus {
@@ -0,0 +1,128 @@
+//! AES-256-GCM primitives behind the transport, group and storage encryption.
+//!
+//! Every derived AES key comes from HKDF-SHA256 under a `KeyContext` naming
+//! its use, so the same input key material never gives two uses the same
+//! key. The contexts carry the labels each scheme has always used, so keys
+//! (and with them existing ledgers and older peers) are unchanged. Nonces
+//! are 12 fresh bytes from the OS RNG, sent alongside the ciphertext.
+
+use aes_gcm::aead::{generic_array::GenericArray, Aead, KeyInit};
+use aes_gcm::Aes256Gcm;
+use hkdf::Hkdf;
+use rand::rngs::OsRng;
+use rand::RngCore;
+use sha2::Sha256;
+
+pub const NONCE_LEN: usize = 12;
+
+/// One use of derived AES keys: HKDF-SHA256 with `salt`, and an `info` of
+/// `label` followed by that use's binding data (public keys).
+#[derive(Debug, Clone, Copy)]
+pub struct KeyContext {
+    salt: Option<&'static [u8]>,
+    label: &'static [u8],
+}
+
+/// Static pairwise transport key, bound to both public keys.
+pub const TRANSPORT: KeyContext = KeyContext { salt: None, label: b"wichain-aes256gcm-v1" };
+
+/// Per-message envelope key, bound to the ephemeral and recipient keys.
+pub const EPHEMERAL: KeyContext = KeyContext { salt: None, label: b"wichain-eph-v1" };
+
+/// Ledger storage key. It predates the labels, so its context is the salt
+/// and its `info` is just the identity's public key.
+pub const STORAGE: KeyContext = KeyContext { salt: Some(b"wichain-storage-v2"), label: b"" };
+
+/// Ledger key for one group's chats: from a group key, bound to the group's
+/// sorted member set.
+pub const GROUP_STORAGE: KeyContext = KeyContext { salt: None, label: b"wichain-group-storage-v2" };
+
+impl KeyContext {
+    pub fn derive(&self, ikm: &[u8], binding: &[&[u8]]) -> [u8; 32] {
+        let mut info = self.label.to_vec();
+        for part in binding {
+            info.extend_from_slice(part);
+        }
+        let mut key = [0u8; 32];
+        Hkdf::<Sha256>::new(self.salt, ikm)
+            .expand(&info, &mut key)
+            .expect("32 bytes is a valid HKDF-SHA256 output length");
+        key
+    }
+}
+
+/// A fresh random AES-GCM nonce.
+pub fn generate_nonce() -> [u8; NONCE_LEN] {
+    let mut nonce = [0u8; NONCE_LEN];
+    OsRng.fill_bytes(&mut nonce);
+    // All zeros means the RNG handed back nothing; reusing it would be fatal.
+    debug_assert_ne!(nonce, [0u8; NONCE_LEN], "OS RNG produced an all-zero nonce");
+    nonce
+}
+
+/// Encrypt `plaintext` under `key` with a fresh nonce; returns both.
+pub fn encrypt(key: &[u8; 32], plaintext: &[u8]) -> Result<([u8; NONCE_LEN], Vec<u8>), String> {
+    let nonce = generate_nonce();
+    let ciphertext = Aes256Gcm::new(GenericArray::from_slice(key))
+        .encrypt(GenericArray::from_slice(&nonce), plaintext)
+        .map_err(|e| format!("Encryption failed: {}", e))?;
+    Ok((nonce, ciphertext))
+}
+
+/// Decrypt and authenticate `ciphertext` under `key` and `nonce`.
+pub fn decrypt(key: &[u8; 32], nonce: &[u8], ciphertext: &[u8]) -> Result<Vec<u8>, String> {
+    if nonce.len() != NONCE_LEN {
+        return Err("nonce must be 12 bytes".into());
+    }
+    Aes256Gcm::new(GenericArray::from_slice(key))
+        .decrypt(GenericArray::from_slice(nonce), ciphertext)
+        .map_err(|e| format!("Decryption failed: {}", e))
+}
+
+/// `encrypt`, as nonce followed by ciphertext.
+pub fn seal(key: &[u8; 32], plaintext: &[u8]) -> Result<Vec<u8>, String> {
+    let (nonce, ciphertext) = encrypt(key, plaintext)?;
+    let mut combined = Vec::with_capacity(NONCE_LEN + ciphertext.len());
+    combined.extend_from_slice(&nonce);
+    combined.extend_from_slice(&ciphertext);
+    Ok(combined)
+}
+
+/// Undo `seal`.
+pub fn open(key: &[u8; 32], combined: &[u8]) -> Result<Vec<u8>, String> {
+    if combined.len() < NONCE_LEN {
+        return Err("Invalid encrypted payload: too short".to_string());
+    }
+    let (nonce, ciphertext) = combined.split_at(NONCE_LEN);
+    decrypt(key, nonce, ciphertext)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn contexts_separate_keys_for_the_same_inputs() {
+        let (secret, pubkey) = ([7u8; 32], [9u8; 32]);
+        let transport = TRANSPORT.derive(&secret, &[&pubkey]);
+        let storage = STORAGE.derive(&secret, &[&pubkey]);
+        let ephemeral = EPHEMERAL.derive(&secret, &[&pubkey]);
+        let group = GROUP_STORAGE.derive(&secret, &[&pubkey]);
+        assert_ne!(transport, storage);
+        assert_ne!(transport, ephemeral);
+        assert_ne!(storage, ephemeral);
+        assert_ne!(storage, group);
+        assert_eq!(transport, TRANSPORT.derive(&secret, &[&pubkey]), "deterministic");
+
+        // A key from one context doesn't open what another sealed.
+        let sealed = seal(&storage, b"at rest").unwrap();
+        assert_eq!(open(&storage, &sealed).unwrap(), b"at rest");
+        assert!(open(&transport, &sealed).is_err());
+        assert!(open(&storage, &sealed[..NONCE_LEN - 1]).is_err());
+    }
+
+    #[test]
+    fn nonces_are_fresh() {
+        assert_ne!(generate_nonce(), generate_nonce());
+    }
+}
@@ -0,0 +1,108 @@
+//! Which payload scheme to use with a peer.
+//!
+//! Every node lists the schemes it can open in its `Peer` announce (see
+//! `NetworkNode::with_capabilities`), and direct payloads to a peer use the
+//! strongest scheme both sides list. A peer that hasn't announced any (an
+//! older build, or one we haven't heard from yet), or that shares none with
+//! us, gets static-key AES-256-GCM, which every build since AES can open.
+//!
+//! The same list carries `DEFLATE` for peers that can take compressed
+//! payloads (see `compression`).
+
+use serde::Serialize;
+
+/// Payload schemes, weakest first.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize)]
+pub enum Cipher {
+    /// `wichain_core::obfuscation`; not confidential.
+    #[serde(rename = "xor-v1")]
+    Obfuscation,
+    /// AES-256-GCM under the static pairwise X25519 key.
+    #[serde(rename = "aes-256-gcm")]
+    Aes256Gcm,
+    /// AES-256-GCM under a one-off X25519 key per message (`EphemeralEnvelope`).
+    #[serde(rename = "x25519-eph-aes-256-gcm")]
+    EphemeralAes256Gcm,
+}
+
+impl Cipher {
+    pub const ALL: [Cipher; 3] = [Cipher::Obfuscation, Cipher::Aes256Gcm, Cipher::EphemeralAes256Gcm];
+
+    /// Used when nothing better is agreed.
+    pub const FALLBACK: Cipher = Cipher::Aes256Gcm;
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Cipher::Obfuscation => "xor-v1",
+            Cipher::Aes256Gcm => "aes-256-gcm",
+            Cipher::EphemeralAes256Gcm => "x25519-eph-aes-256-gcm",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|c| c.name() == name)
+    }
+}
+
+/// Announced by peers that can open compressed payloads.
+pub const DEFLATE: &str = "deflate";
+
+/// Names of the schemes this build announces.
+pub fn supported() -> Vec<String> {
+    let mut names: Vec<String> = Cipher::ALL.iter().map(|c| c.name().to_string()).collect();
+    names.push(DEFLATE.to_string());
+    names
+}
+
+/// Whether a peer announcing `theirs` can open compressed payloads.
+pub fn inflates(theirs: Option<&[String]>) -> bool {
+    theirs.is_some_and(|names| names.iter().any(|n| n == DEFLATE))
+}
+
+/// The strongest of `ours` that the peer announced (`theirs`), or
+/// `Cipher::FALLBACK` if it announced nothing or nothing we share.
+pub fn negotiate(ours: &[Cipher], theirs: Option<&[String]>) -> Cipher {
+    theirs
+        .into_iter()
+        .flatten()
+        .filter_map(|name| Cipher::from_name(name))
+        .filter(|c| ours.contains(c))
+        .max()
+        .unwrap_or(Cipher::FALLBACK)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn names(ciphers: &[&str]) -> Vec<String> {
+        ciphers.iter().map(|c| c.to_string()).collect()
+    }
+
+    #[test]
+    fn picks_the_strongest_cipher_both_support() {
+        let everything = supported();
+        assert_eq!(negotiate(&Cipher::ALL, Some(&everything)), Cipher::EphemeralAes256Gcm);
+        assert_eq!(negotiate(&Cipher::ALL, Some(&names(&["aes-256-gcm", "xor-v1"]))), Cipher::Aes256Gcm);
+        // Our side limits the choice too.
+        assert_eq!(negotiate(&[Cipher::Obfuscation, Cipher::Aes256Gcm], Some(&everything)), Cipher::Aes256Gcm);
+        // A peer that can't do AES.
+        assert_eq!(negotiate(&Cipher::ALL, Some(&names(&["xor-v1"]))), Cipher::Obfuscation);
+    }
+
+    #[test]
+    fn falls_back_without_a_shared_cipher() {
+        assert_eq!(negotiate(&Cipher::ALL, None), Cipher::Aes256Gcm, "nothing announced");
+        assert_eq!(negotiate(&Cipher::ALL, Some(&[])), Cipher::Aes256Gcm);
+        assert_eq!(negotiate(&Cipher::ALL, Some(&names(&["rot13", "chacha"]))), Cipher::Aes256Gcm, "unknown names");
+        assert_eq!(negotiate(&[Cipher::EphemeralAes256Gcm], Some(&names(&["xor-v1"]))), Cipher::Aes256Gcm);
+    }
+
+    #[test]
+    fn names_round_trip() {
+        for cipher in Cipher::ALL {
+            assert_eq!(Cipher::from_name(cipher.name()), Some(cipher));
+            assert_eq!(serde_json::to_value(cipher).unwrap(), cipher.name());
+        }
+    }
+}
@@ -0,0 +1,78 @@
+//! Optional deflate compression of payload plaintext, applied before
+//! encryption.
+//!
+//! A compressed plaintext is `DEFLATE_FLAG` followed by the raw deflate
+//! stream. An uncompressed one is the JSON itself, exactly as builds without
+//! compression send it; JSON never starts with `DEFLATE_FLAG`, so receivers
+//! tell the two apart without a header on every message. Senders only
+//! compress for peers that announced `ciphers::DEFLATE`, and only when that
+//! actually shrinks the payload.
+
+use std::io::{Read as _, Write as _};
+
+use flate2::read::DeflateDecoder;
+use flate2::write::DeflateEncoder;
+use flate2::Compression;
+
+/// First byte of a compressed plaintext.
+pub const DEFLATE_FLAG: u8 = 0x01;
+
+/// Decompressed payloads larger than this are rejected (deflate bombs).
+const MAX_INFLATED: u64 = 16 * 1024 * 1024;
+
+/// Plaintext bytes to encrypt for `clear_json`: compressed if `compress` and
+/// it comes out smaller, otherwise the JSON as is.
+pub fn pack(clear_json: &str, compress: bool) -> Vec<u8> {
+    if compress {
+        let mut encoder = DeflateEncoder::new(vec![DEFLATE_FLAG], Compression::default());
+        if let Ok(packed) = encoder.write_all(clear_json.as_bytes()).and_then(|_| encoder.finish()) {
+            if packed.len() < clear_json.len() {
+                return packed;
+            }
+        }
+    }
+    clear_json.as_bytes().to_vec()
+}
+
+/// Undo `pack` on decrypted plaintext.
+pub fn unpack(plaintext: Vec<u8>) -> Result<String, String> {
+    let bytes = match plaintext.split_first() {
+        Some((&DEFLATE_FLAG, deflated)) => {
+            let mut inflated = Vec::new();
+            DeflateDecoder::new(deflated)
+                .take(MAX_INFLATED + 1)
+                .read_to_end(&mut inflated)
+                .map_err(|e| format!("Decompression failed: {e}"))?;
+            if inflated.len() as u64 > MAX_INFLATED {
+                return Err("Decompressed payload too large".into());
+            }
+            inflated
+        }
+        _ => plaintext,
+    };
+    String::from_utf8(bytes).map_err(|e| format!("UTF-8 decode failed: {}", e))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn compresses_only_when_it_helps() {
+        let repetitive = format!("{{\"members\":[{}]}}", "\"QUxJQ0VBTElDRUFMSUNF\",".repeat(100));
+        let packed = pack(&repetitive, true);
+        assert_eq!(packed[0], DEFLATE_FLAG);
+        assert!(packed.len() < repetitive.len() / 4);
+        assert_eq!(unpack(packed).unwrap(), repetitive);
+
+        // Too short to gain anything, or not allowed: sent as plain JSON.
+        assert_eq!(pack("{\"x\":1}", true), b"{\"x\":1}");
+        assert_eq!(pack(&repetitive, false), repetitive.as_bytes());
+        assert_eq!(unpack(b"{\"x\":1}".to_vec()).unwrap(), "{\"x\":1}");
+    }
+
+    #[test]
+    fn rejects_corrupt_streams() {
+        assert!(unpack(vec![DEFLATE_FLAG, 0xff, 0xff, 0xff]).is_err());
+    }
+}
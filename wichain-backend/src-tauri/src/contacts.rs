@@ -0,0 +1,107 @@
+//! Persisted address book.
+//!
+//! Peer aliases come from network announces, and any peer can advertise any
+//! alias, so names the user assigns here take precedence where peers and
+//! their chats are shown. Contacts are keyed by pubkey and stored in
+//! `contacts.json`.
+
+use std::collections::HashMap;
+use std::fs;
+use std::path::PathBuf;
+use std::sync::Mutex;
+
+use serde::{Deserialize, Serialize};
+
+use crate::error::WiChainError;
+
+/// Longest accepted display name, in characters.
+pub const MAX_DISPLAY_NAME_CHARS: usize = 64;
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Contact {
+    pub pubkey: String,
+    pub display_name: String,
+    pub added_ms: u64,
+}
+
+#[derive(Debug)]
+pub struct ContactStore {
+    path: PathBuf,
+    inner: Mutex<Vec<Contact>>,
+}
+
+impl ContactStore {
+    /// Load contacts from `path` (missing or unreadable file = none).
+    pub fn load(path: PathBuf) -> std::sync::Arc<Self> {
+        let contacts = fs::read_to_string(&path)
+            .ok()
+            .and_then(|data| serde_json::from_str::<Vec<Contact>>(&data).ok())
+            .unwrap_or_default();
+        std::sync::Arc::new(Self {
+            path,
+            inner: Mutex::new(contacts),
+        })
+    }
+
+    fn save(&self, contacts: &[Contact]) -> Result<(), WiChainError> {
+        let json = serde_json::to_string_pretty(contacts).map_err(|e| WiChainError::Io(format!("serialize contacts: {e}")))?;
+        fs::write(&self.path, json).map_err(|e| WiChainError::Io(format!("write contacts: {e}")))
+    }
+
+    /// Add a contact, or rename an existing one. The name is trimmed and
+    /// must be 1..=`MAX_DISPLAY_NAME_CHARS` characters.
+    pub fn upsert(&self, pubkey: &str, display_name: &str, now_ms: u64) -> Result<Contact, WiChainError> {
+        let display_name = display_name.trim();
+        if display_name.is_empty() {
+            return Err(WiChainError::InvalidInput("display name required".into()));
+        }
+        if display_name.chars().count() > MAX_DISPLAY_NAME_CHARS {
+            return Err(WiChainError::InvalidInput(format!(
+                "display name longer than {MAX_DISPLAY_NAME_CHARS} characters"
+            )));
+        }
+        let mut guard = self.inner.lock().unwrap();
+        let contact = match guard.iter_mut().find(|c| c.pubkey == pubkey) {
+            Some(existing) => {
+                existing.display_name = display_name.to_string();
+                existing.clone()
+            }
+            None => {
+                let contact = Contact {
+                    pubkey: pubkey.to_string(),
+                    display_name: display_name.to_string(),
+                    added_ms: now_ms,
+                };
+                guard.push(contact.clone());
+                contact
+            }
+        };
+        self.save(&guard)?;
+        Ok(contact)
+    }
+
+    /// Remove a contact. Returns `true` if it existed.
+    pub fn remove(&self, pubkey: &str) -> Result<bool, WiChainError> {
+        let mut guard = self.inner.lock().unwrap();
+        let before = guard.len();
+        guard.retain(|c| c.pubkey != pubkey);
+        if guard.len() == before {
+            return Ok(false);
+        }
+        self.save(&guard)?;
+        Ok(true)
+    }
+
+    /// All contacts, sorted by display name.
+    pub fn list(&self) -> Vec<Contact> {
+        let mut contacts = self.inner.lock().unwrap().clone();
+        contacts.sort_by_key(|c| c.display_name.to_lowercase());
+        contacts
+    }
+
+    /// Display name per contact pubkey.
+    pub fn names(&self) -> HashMap<String, String> {
+        let guard = self.inner.lock().unwrap();
+        guard.iter().map(|c| (c.pubkey.clone(), c.display_name.clone())).collect()
+    }
+}
@@ -0,0 +1,99 @@
+//! Per-message delivery status for chats we sent.
+//!
+//! Each recipient of a chat gets a status: `Queued` (peer offline, held in the
+//! outbox), `Sent` (written to its TCP stream), `Delivered` (acknowledged over
+//! UDP, directly or when the outbox is flushed) or `Failed`. A message's
+//! overall status is the least advanced of its recipients'. Kept in memory
+//! only; the oldest messages are forgotten beyond `CAPACITY`.
+
+use std::collections::{HashMap, VecDeque};
+use std::sync::Mutex;
+
+use serde::{Deserialize, Serialize};
+use wichain_network::Delivery;
+
+const CAPACITY: usize = 2000;
+
+/// Ordered from least to most advanced.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum DeliveryStatus {
+    Failed,
+    Queued,
+    Sent,
+    Delivered,
+}
+
+impl From<Delivery> for DeliveryStatus {
+    fn from(delivery: Delivery) -> Self {
+        match delivery {
+            Delivery::Tcp => DeliveryStatus::Sent,
+            Delivery::Udp { .. } => DeliveryStatus::Delivered,
+            Delivery::Queued { .. } => DeliveryStatus::Queued,
+        }
+    }
+}
+
+#[derive(Debug, Default)]
+struct Inner {
+    /// Status per recipient, by message id.
+    by_msg: HashMap<String, HashMap<String, DeliveryStatus>>,
+    /// Message ids in the order they were first seen, for eviction.
+    order: VecDeque<String>,
+    /// Ids queued per peer, in outbox order.
+    queued: HashMap<String, VecDeque<String>>,
+}
+
+impl Inner {
+    fn overall(&self, msg_id: &str) -> Option<DeliveryStatus> {
+        self.by_msg.get(msg_id)?.values().min().copied()
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct DeliveryTracker {
+    inner: Mutex<Inner>,
+}
+
+impl DeliveryTracker {
+    /// Set `peer_id`'s status for `msg_id`; returns the message's overall status.
+    pub fn record(&self, msg_id: &str, peer_id: &str, status: DeliveryStatus) -> DeliveryStatus {
+        let mut inner = self.inner.lock().unwrap();
+        if !inner.by_msg.contains_key(msg_id) {
+            inner.order.push_back(msg_id.to_string());
+            if inner.order.len() > CAPACITY {
+                if let Some(old) = inner.order.pop_front() {
+                    inner.by_msg.remove(&old);
+                }
+            }
+        }
+        inner.by_msg.entry(msg_id.to_string()).or_default().insert(peer_id.to_string(), status);
+        if status == DeliveryStatus::Queued {
+            inner.queued.entry(peer_id.to_string()).or_default().push_back(msg_id.to_string());
+        }
+        inner.overall(msg_id).unwrap_or(status)
+    }
+
+    /// The first `delivered` messages queued for `peer_id` were flushed and
+    /// acknowledged. Returns their ids with their new overall status.
+    pub fn queue_flushed(&self, peer_id: &str, delivered: usize) -> Vec<(String, DeliveryStatus)> {
+        let mut inner = self.inner.lock().unwrap();
+        let flushed: Vec<String> = match inner.queued.get_mut(peer_id) {
+            Some(queue) => queue.drain(..delivered.min(queue.len())).collect(),
+            None => return Vec::new(),
+        };
+        flushed
+            .into_iter()
+            .filter_map(|msg_id| {
+                let peers = inner.by_msg.get_mut(&msg_id)?;
+                peers.insert(peer_id.to_string(), DeliveryStatus::Delivered);
+                let overall = inner.overall(&msg_id)?;
+                Some((msg_id, overall))
+            })
+            .collect()
+    }
+
+    pub fn status(&self, msg_id: &str) -> Option<DeliveryStatus> {
+        self.inner.lock().unwrap().overall(msg_id)
+    }
+}
@@ -0,0 +1,107 @@
+//! Error type returned by every Tauri command.
+//!
+//! The frontend receives `{ "kind": "PeerNotFound", "message": "..." }`, so it
+//! can branch (or localize) on `kind` and still show `message`, which is the
+//! same text the commands returned as plain strings before.
+
+use std::fmt;
+
+use serde::Serialize;
+use wichain_network::UnknownPeer;
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(tag = "kind", content = "message")]
+pub enum WiChainError {
+    /// No such peer, contact or pubkey we know of.
+    PeerNotFound(String),
+    /// No such message, group, profile or record.
+    NotFound(String),
+    /// The network refused or failed to carry a message or request.
+    NetworkSend(String),
+    /// Key handling, encryption or decryption failed.
+    Encryption(String),
+    /// Reading or writing local files failed.
+    Io(String),
+    /// The arguments were rejected before anything was done.
+    InvalidInput(String),
+    /// Allowed for someone else, not us (another sender's message, a group
+    /// we aren't in).
+    NotPermitted(String),
+}
+
+impl WiChainError {
+    /// `message` for a failed network call: `PeerNotFound` if the node
+    /// didn't know the peer, `NetworkSend` otherwise.
+    pub fn network(err: &anyhow::Error, message: String) -> Self {
+        if err.downcast_ref::<UnknownPeer>().is_some() {
+            WiChainError::PeerNotFound(message)
+        } else {
+            WiChainError::NetworkSend(message)
+        }
+    }
+
+    /// The same kind of error with `prefix` in front of its message.
+    pub fn prefixed(self, prefix: &str) -> Self {
+        let with = |m: String| format!("{prefix}{m}");
+        match self {
+            WiChainError::PeerNotFound(m) => WiChainError::PeerNotFound(with(m)),
+            WiChainError::NotFound(m) => WiChainError::NotFound(with(m)),
+            WiChainError::NetworkSend(m) => WiChainError::NetworkSend(with(m)),
+            WiChainError::Encryption(m) => WiChainError::Encryption(with(m)),
+            WiChainError::Io(m) => WiChainError::Io(with(m)),
+            WiChainError::InvalidInput(m) => WiChainError::InvalidInput(with(m)),
+            WiChainError::NotPermitted(m) => WiChainError::NotPermitted(with(m)),
+        }
+    }
+
+    pub fn message(&self) -> &str {
+        match self {
+            WiChainError::PeerNotFound(m)
+            | WiChainError::NotFound(m)
+            | WiChainError::NetworkSend(m)
+            | WiChainError::Encryption(m)
+            | WiChainError::Io(m)
+            | WiChainError::InvalidInput(m)
+            | WiChainError::NotPermitted(m) => m,
+        }
+    }
+}
+
+impl fmt::Display for WiChainError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.message())
+    }
+}
+
+impl std::error::Error for WiChainError {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn serializes_kind_and_the_plain_message() {
+        let err = WiChainError::PeerNotFound("Peer not found: abc".into());
+        assert_eq!(err.to_string(), "Peer not found: abc");
+        assert_eq!(
+            serde_json::to_value(&err).unwrap(),
+            serde_json::json!({ "kind": "PeerNotFound", "message": "Peer not found: abc" })
+        );
+    }
+
+    #[tokio::test]
+    async fn unknown_peers_are_told_apart_from_send_failures() {
+        let node = wichain_network::NetworkNode::new(0, "me".into(), "me".into(), "me".into());
+        let err = node.request_tcp_connection("nobody").await.unwrap_err();
+        assert_eq!(
+            WiChainError::network(&err, format!("Failed to request TCP connection: {err}")),
+            WiChainError::PeerNotFound("Failed to request TCP connection: Peer not found: nobody".into())
+        );
+        let other = anyhow::anyhow!("socket closed");
+        assert!(matches!(WiChainError::network(&other, other.to_string()), WiChainError::NetworkSend(_)));
+        assert_eq!(
+            WiChainError::Encryption("bad tag".into()).prefixed("❌ Self-test failed: "),
+            WiChainError::Encryption("❌ Self-test failed: bad tag".into())
+        );
+    }
+}
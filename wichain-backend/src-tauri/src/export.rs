@@ -0,0 +1,119 @@
+//! Chat history export and import.
+//!
+//! JSON is a pretty-printed array of `ChatBody`, so it round-trips; CSV has
+//! one `timestamp,from,to,text` row per message for spreadsheets, quoted per
+//! RFC 4180. A multi-recipient chat lists its recipients in `to`, separated
+//! by `;`.
+//!
+//! Importing a JSON export appends each body as an unsigned, storage-encrypted
+//! chat block (the same shape legacy unsigned chats have), keeping its
+//! message id so replies and reactions still line up.
+
+use std::collections::HashSet;
+use std::fs;
+use std::path::Path;
+
+use serde::{Deserialize, Serialize};
+use wichain_blockchain::Blockchain;
+
+use super::{decode_stored_chat, encrypt_for_storage, ChatBody, StorageKey, WiChainError};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ExportFormat {
+    Json,
+    Csv,
+}
+
+/// Quote `field` if it holds a comma, quote or line break (quotes doubled).
+fn csv_field(field: &str) -> String {
+    if field.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", field.replace('"', "\"\""))
+    } else {
+        field.to_string()
+    }
+}
+
+pub fn to_csv(chats: &[ChatBody]) -> String {
+    let mut out = String::from("timestamp,from,to,text\n");
+    for chat in chats {
+        let to = match &chat.to {
+            Some(to) => to.clone(),
+            None => chat.to_many.join(";"),
+        };
+        let row = [chat.ts_ms.to_string(), chat.from.clone(), to, chat.text.clone()];
+        out.push_str(&row.iter().map(|f| csv_field(f)).collect::<Vec<_>>().join(","));
+        out.push('\n');
+    }
+    out
+}
+
+/// Write `chats` to `path` in `format`; returns how many were written.
+pub fn write_history(path: &Path, format: ExportFormat, chats: &[ChatBody]) -> Result<usize, WiChainError> {
+    let contents = match format {
+        ExportFormat::Json => serde_json::to_string_pretty(chats).map_err(|e| WiChainError::Io(format!("serialize history: {e}")))?,
+        ExportFormat::Csv => to_csv(chats),
+    };
+    fs::write(path, contents).map_err(|e| WiChainError::Io(format!("cannot write {}: {e}", path.display())))?;
+    Ok(chats.len())
+}
+
+/// Outcome of `import_into`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize)]
+pub struct ImportReport {
+    pub imported: usize,
+    /// Already on the chain (or repeated in the file).
+    pub duplicates: usize,
+    /// Entries that aren't a valid `ChatBody`.
+    pub skipped: usize,
+}
+
+/// Identity of a chat for de-duplication: `(from, to, ts_ms, text)`.
+type ChatKey = (String, Option<String>, u64, String);
+
+fn chat_key(body: &ChatBody) -> ChatKey {
+    (body.from.clone(), body.to.clone(), body.ts_ms, body.text.clone())
+}
+
+/// Append the chats of an exported JSON array to `chain`, leaving out ones
+/// already there (same `ChatKey` or message id). The file must be an array;
+/// entries that aren't chat bodies are skipped and counted.
+pub fn import_into(chain: &mut Blockchain, key: &StorageKey, contents: &str) -> Result<ImportReport, WiChainError> {
+    let entries = match serde_json::from_str(contents) {
+        Ok(serde_json::Value::Array(entries)) => entries,
+        Ok(_) => {
+            return Err(WiChainError::InvalidInput(
+                "not a history export: expected a JSON array of messages".into(),
+            ))
+        }
+        Err(e) => return Err(WiChainError::InvalidInput(format!("not a history export: {e}"))),
+    };
+    let existing: Vec<_> = chain.chain.iter().filter_map(|b| decode_stored_chat(&b.data, key)).collect();
+    let mut seen: HashSet<ChatKey> = existing.iter().map(|c| chat_key(&c.body)).collect();
+    // An edited chat exports its latest text, so also match on message id.
+    let mut ids: HashSet<String> = existing
+        .iter()
+        .map(|c| c.body.id.clone().unwrap_or_else(|| c.message_id()))
+        .collect();
+    let mut report = ImportReport::default();
+    for entry in entries {
+        let Ok(mut body) = serde_json::from_value::<ChatBody>(entry) else {
+            report.skipped += 1;
+            continue;
+        };
+        if body.from.is_empty() {
+            report.skipped += 1;
+            continue;
+        }
+        let known_id = body.id.as_ref().is_some_and(|id| !ids.insert(id.clone()));
+        if !seen.insert(chat_key(&body)) || known_id {
+            report.duplicates += 1;
+            continue;
+        }
+        body.from_name = None;
+        body.text = encrypt_for_storage(&body.text, key);
+        chain.add_text_block(serde_json::to_string(&body).map_err(|e| WiChainError::Io(format!("encode chat: {e}")))?);
+        report.imported += 1;
+    }
+    Ok(report)
+}
@@ -3,11 +3,25 @@
 //! Groups are *ephemeral* (not persisted). A group is identified by a
 //! deterministic ID derived from the **sorted list of member pubkeys**.
 //!
-//! Transport "confidentiality" in the current build is **per‑member SHA3‑512 XOR
-//! obfuscation** that happens in `add_group_message` inside `main.rs`; we do *not*
-//! derive or store a persistent group key here. We *only* provide:
+//! We provide:
 //!   • deterministic group IDs
 //!   • membership tracking for UI / history filtering
+//!   • the group keys messages are sealed under (see `add_group_message` in
+//!     `main.rs`): random AES keys, sent to each member wrapped for them when
+//!     the group is made, and looked up by key id. Like groups, they are
+//!     not persisted. A key received for a group we don't know yet is parked
+//!     until the group is, and only used for sending once its sender turns
+//!     out to be a member.
+//!
+//! Adding or removing a member re-keys the group under the ID of its new member
+//! set. The old ID is kept in `previous_ids`, and lookups by an old ID resolve
+//! to the current group, so earlier history (and chats still addressed to the
+//! old ID by peers that haven't seen the change) stays attached to it.
+//!
+//! The member set behind every ID is remembered too, along with every key
+//! held for it: group chats are stored under a key derived from both
+//! (`StorageKey::for_group` in `main.rs`), so chats sent before a membership
+//! change still open.
 
 use std::collections::HashMap;
 use std::sync::Mutex;
@@ -20,17 +34,55 @@ pub struct GroupInfo {
     pub id: String,
     pub members: Vec<String>, // b64 pubkeys (sorted)
     pub name: Option<String>, // Optional group name
+    /// IDs this group had before membership changes, oldest first.
+    #[serde(default)]
+    pub previous_ids: Vec<String>,
+}
+
+/// A symmetric key for one member set.
+#[derive(Clone)]
+pub struct GroupKey {
+    /// Group (member set) the key was made for.
+    pub group_id: String,
+    pub key: [u8; 32],
+}
+
+impl GroupInfo {
+    /// Is `gid` this group's current or a former ID?
+    pub fn answers_to(&self, gid: &str) -> bool {
+        self.id == gid || self.previous_ids.iter().any(|p| p == gid)
+    }
 }
 
 #[derive(Debug)]
 pub struct GroupManager {
     inner: Mutex<HashMap<String, GroupInfo>>,
+    keys: Mutex<GroupKeys>,
+    /// Sorted member set of every group ID seen, current or former.
+    member_sets: Mutex<HashMap<String, Vec<String>>>,
+}
+
+#[derive(Default)]
+struct GroupKeys {
+    by_id: HashMap<String, GroupKey>,
+    /// Key id new messages to a group are sealed under.
+    current: HashMap<String, String>,
+    /// Received keys for groups not known yet: group id -> (sender, key id).
+    parked: HashMap<String, Vec<(String, String)>>,
+}
+
+impl std::fmt::Debug for GroupKeys {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("GroupKeys").field("count", &self.by_id.len()).finish_non_exhaustive()
+    }
 }
 
 impl GroupManager {
     pub fn new() -> std::sync::Arc<Self> {
         std::sync::Arc::new(Self {
             inner: Mutex::new(HashMap::new()),
+            keys: Mutex::new(GroupKeys::default()),
+            member_sets: Mutex::new(HashMap::new()),
         })
     }
 
@@ -65,25 +117,87 @@ impl GroupManager {
         let mut sorted = members;
         sorted.sort_unstable();
         let gid = Self::compute_group_id(&sorted);
-        let mut guard = self.inner.lock().unwrap();
-        guard.entry(gid.clone()).or_insert(GroupInfo {
+        self.remember_members(&gid, &sorted);
+        self.inner.lock().unwrap().entry(gid.clone()).or_insert(GroupInfo {
             id: gid.clone(),
             members: sorted.clone(),
             name,
+            previous_ids: Vec::new(),
         });
+        self.promote_parked_key(&gid);
         gid
     }
 
+    /// Add `pubkey` to group `gid` (current or former ID). Returns the group's
+    /// new ID, or `None` if the group is unknown.
+    pub fn add_member(&self, gid: &str, pubkey: &str) -> Option<String> {
+        self.rekey(gid, |members| {
+            if !members.iter().any(|m| m == pubkey) {
+                members.push(pubkey.to_string());
+            }
+        })
+    }
+
+    /// Remove `pubkey` from group `gid` (current or former ID). Returns the
+    /// group's new ID, or `None` if the group is unknown or would be left empty.
+    pub fn remove_member(&self, gid: &str, pubkey: &str) -> Option<String> {
+        let members = self.get_members(gid)?;
+        if members.iter().all(|m| m == pubkey) {
+            return None;
+        }
+        self.rekey(gid, |members| members.retain(|m| m != pubkey))
+    }
+
+    /// Apply `change` to the member list and move the entry to the ID of the
+    /// resulting set. If a group with that set already exists, the two merge.
+    fn rekey(&self, gid: &str, change: impl FnOnce(&mut Vec<String>)) -> Option<String> {
+        let mut guard = self.inner.lock().unwrap();
+        let current = guard.values().find(|g| g.answers_to(gid))?.id.clone();
+        let mut group = guard.remove(&current)?;
+
+        change(&mut group.members);
+        group.members.sort_unstable();
+        let new_id = Self::compute_group_id(&group.members);
+        if new_id == current {
+            guard.insert(current.clone(), group);
+            return Some(current);
+        }
+
+        self.remember_members(&new_id, &group.members);
+        let mut previous = std::mem::take(&mut group.previous_ids);
+        previous.push(current);
+        let entry = guard.entry(new_id.clone()).or_insert(GroupInfo {
+            id: new_id.clone(),
+            members: group.members,
+            name: None,
+            previous_ids: Vec::new(),
+        });
+        if entry.name.is_none() {
+            entry.name = group.name;
+        }
+        for old in previous {
+            if old != new_id && !entry.previous_ids.contains(&old) {
+                entry.previous_ids.push(old);
+            }
+        }
+        drop(guard);
+        self.promote_parked_key(&new_id);
+        Some(new_id)
+    }
+
     /// List all local groups.
     pub fn list_groups(&self) -> Vec<GroupInfo> {
         let guard = self.inner.lock().unwrap();
         guard.values().cloned().collect()
     }
 
-    /// Get full group info.
+    /// Get full group info; a former ID resolves to the group it became.
     pub fn get_group(&self, gid: &str) -> Option<GroupInfo> {
         let guard = self.inner.lock().unwrap();
-        guard.get(gid).cloned()
+        guard
+            .get(gid)
+            .or_else(|| guard.values().find(|g| g.answers_to(gid)))
+            .cloned()
     }
 
     /// Just the member list.
@@ -91,6 +205,20 @@ impl GroupManager {
         self.get_group(gid).map(|g| g.members)
     }
 
+    /// Every group ID seen with the sorted member set it was made from.
+    /// Unlike `get_members`, a former ID gives the members it had.
+    pub fn member_sets(&self) -> HashMap<String, Vec<String>> {
+        self.member_sets.lock().unwrap().clone()
+    }
+
+    fn remember_members(&self, gid: &str, sorted_members: &[String]) {
+        self.member_sets
+            .lock()
+            .unwrap()
+            .entry(gid.to_string())
+            .or_insert_with(|| sorted_members.to_vec());
+    }
+
     /// Membership test.
     pub fn is_member(&self, gid: &str, member: &str) -> bool {
         self.get_group(gid)
@@ -115,4 +243,108 @@ impl GroupManager {
         }
     }
 
+    /// Key id = hex(SHA3_256("gkey|" + key))[..16]; names the key without revealing it.
+    pub fn key_id(key: &[u8; 32]) -> String {
+        let mut hasher = Sha3_256::new();
+        hasher.update(b"gkey|");
+        hasher.update(key);
+        hex::encode(&hasher.finalize()[..8])
+    }
+
+    /// Store `key` for group `gid` and make it the one new messages use.
+    /// Returns its key id.
+    pub fn add_key(&self, gid: &str, key: [u8; 32]) -> String {
+        let key_id = Self::key_id(&key);
+        let mut keys = self.keys.lock().unwrap();
+        keys.by_id.insert(key_id.clone(), GroupKey { group_id: gid.to_string(), key });
+        keys.current.insert(gid.to_string(), key_id.clone());
+        key_id
+    }
+
+    /// Store `key` that `from` handed out for group `gid`; returns its key id,
+    /// or `None` if `from` isn't a member. It opens messages right away, but
+    /// only becomes the one new messages use once the group is known (a
+    /// key for an unknown group is parked until then, see
+    /// `promote_parked_key`) and only for the group's current ID.
+    pub fn add_received_key(&self, gid: &str, from: &str, key: [u8; 32]) -> Option<String> {
+        let group = self.get_group(gid);
+        if group.as_ref().is_some_and(|g| !g.members.iter().any(|m| m == from)) {
+            return None;
+        }
+        let key_id = Self::key_id(&key);
+        let mut keys = self.keys.lock().unwrap();
+        keys.by_id.insert(key_id.clone(), GroupKey { group_id: gid.to_string(), key });
+        match group {
+            Some(g) if g.id == gid => {
+                keys.current.insert(gid.to_string(), key_id.clone());
+            }
+            Some(_) => {}
+            None => keys.parked.entry(gid.to_string()).or_default().push((from.to_string(), key_id.clone())),
+        }
+        Some(key_id)
+    }
+
+    /// Now that `gid` is known, make the latest key parked for it by one of
+    /// its members the current one (unless it already has one).
+    fn promote_parked_key(&self, gid: &str) {
+        let Some(members) = self.inner.lock().unwrap().get(gid).map(|g| g.members.clone()) else {
+            return;
+        };
+        let mut keys = self.keys.lock().unwrap();
+        let Some(parked) = keys.parked.remove(gid) else {
+            return;
+        };
+        if let Some((_, key_id)) = parked.into_iter().rev().find(|(from, _)| members.contains(from)) {
+            keys.current.entry(gid.to_string()).or_insert(key_id);
+        }
+    }
+
+    /// The key id and key to seal new messages to `gid` with. Keys belong to
+    /// a member set, so a group whose membership changed has none until a
+    /// new one is added.
+    pub fn current_key(&self, gid: &str) -> Option<(String, [u8; 32])> {
+        let current = self.get_group(gid).map_or_else(|| gid.to_string(), |g| g.id);
+        let keys = self.keys.lock().unwrap();
+        let key_id = keys.current.get(&current)?;
+        Some((key_id.clone(), keys.by_id.get(key_id)?.key))
+    }
+
+    /// Every key held for each group ID (current or former), the current one first.
+    pub fn keys_by_group(&self) -> HashMap<String, Vec<[u8; 32]>> {
+        let keys = self.keys.lock().unwrap();
+        let mut by_group: HashMap<String, Vec<[u8; 32]>> = HashMap::new();
+        for (key_id, k) in &keys.by_id {
+            let held = by_group.entry(k.group_id.clone()).or_default();
+            if keys.current.get(&k.group_id) == Some(key_id) {
+                held.insert(0, k.key);
+            } else {
+                held.push(k.key);
+            }
+        }
+        by_group
+    }
+
+    /// Any key we were given, by key id.
+    pub fn key(&self, key_id: &str) -> Option<GroupKey> {
+        self.keys.lock().unwrap().by_id.get(key_id).cloned()
+    }
+
+    /// Replace every group with `groups`, returning the previous set (used to
+    /// keep each local profile's groups apart when switching).
+    pub fn swap_all(&self, groups: Vec<GroupInfo>) -> Vec<GroupInfo> {
+        for g in &groups {
+            self.remember_members(&g.id, &g.members);
+        }
+        let ids: Vec<String> = groups.iter().map(|g| g.id.clone()).collect();
+        let previous = {
+            let mut guard = self.inner.lock().unwrap();
+            let previous = guard.drain().map(|(_, g)| g).collect();
+            guard.extend(groups.into_iter().map(|g| (g.id.clone(), g)));
+            previous
+        };
+        for gid in &ids {
+            self.promote_parked_key(gid);
+        }
+        previous
+    }
 }
\ No newline at end of file
@@ -0,0 +1,57 @@
+//! Aggregated health report for dashboards / support tooling.
+//!
+//! `health_check` in `main.rs` runs each sub-check independently (with its own
+//! timeout) and folds the results into a `HealthReport`; this module only holds
+//! the report types and the per-subsystem "last error" log that feeds it.
+
+use std::collections::HashMap;
+use std::sync::Mutex;
+
+use serde::Serialize;
+
+/// Outcome of one sub-check.
+#[derive(Debug, Clone, Serialize)]
+pub struct CheckResult {
+    pub ok: bool,
+    pub detail: String,
+}
+
+impl CheckResult {
+    pub fn pass(detail: impl Into<String>) -> Self {
+        Self { ok: true, detail: detail.into() }
+    }
+
+    pub fn fail(detail: impl Into<String>) -> Self {
+        Self { ok: false, detail: detail.into() }
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct HealthReport {
+    /// All sub-checks passed.
+    pub ok: bool,
+    pub network: CheckResult,
+    pub peer_count: usize,
+    pub chain: CheckResult,
+    pub identity: CheckResult,
+    pub storage: CheckResult,
+    /// Unix ms of the most recent error per subsystem.
+    pub last_errors: HashMap<String, u64>,
+    pub checked_at_ms: u64,
+}
+
+/// Most recent error timestamp per subsystem ("storage", "network", ...).
+#[derive(Debug, Default)]
+pub struct ErrorLog {
+    inner: Mutex<HashMap<String, u64>>,
+}
+
+impl ErrorLog {
+    pub fn record(&self, subsystem: &str, at_ms: u64) {
+        self.inner.lock().unwrap().insert(subsystem.to_string(), at_ms);
+    }
+
+    pub fn snapshot(&self) -> HashMap<String, u64> {
+        self.inner.lock().unwrap().clone()
+    }
+}
@@ -0,0 +1,47 @@
+//! OS secret-store backend for the identity private key.
+//!
+//! Uses the platform keychain (macOS Keychain, Windows Credential Manager,
+//! Secret Service on Linux) through the `keyring` crate. Entries are keyed by
+//! the identity's base64 pubkey so several identities can coexist.
+//!
+//! Every call is fallible: headless Linux boxes frequently have no Secret
+//! Service running, so callers must keep a usable fallback.
+
+use keyring::Entry;
+
+const SERVICE: &str = "wichain";
+
+fn entry(public_key_b64: &str) -> Result<Entry, String> {
+    Entry::new(SERVICE, public_key_b64).map_err(|e| format!("keychain unavailable: {e}"))
+}
+
+/// Store (or overwrite) the base64 private key for `public_key_b64`.
+///
+/// Reads the secret back before returning so a silently broken store is
+/// caught *before* the caller drops its plaintext copy.
+pub fn store_private_key(public_key_b64: &str, private_key_b64: &str) -> Result<(), String> {
+    let entry = entry(public_key_b64)?;
+    entry
+        .set_password(private_key_b64)
+        .map_err(|e| format!("keychain write failed: {e}"))?;
+    match entry.get_password() {
+        Ok(read_back) if read_back == private_key_b64 => Ok(()),
+        Ok(_) => Err("keychain read-back mismatch".into()),
+        Err(e) => Err(format!("keychain read-back failed: {e}")),
+    }
+}
+
+/// Fetch the base64 private key for `public_key_b64`.
+pub fn load_private_key(public_key_b64: &str) -> Result<String, String> {
+    entry(public_key_b64)?
+        .get_password()
+        .map_err(|e| format!("keychain read failed: {e}"))
+}
+
+/// Remove the stored key (missing entries are not an error).
+pub fn delete_private_key(public_key_b64: &str) -> Result<(), String> {
+    match entry(public_key_b64)?.delete_credential() {
+        Ok(()) | Err(keyring::Error::NoEntry) => Ok(()),
+        Err(e) => Err(format!("keychain delete failed: {e}")),
+    }
+}
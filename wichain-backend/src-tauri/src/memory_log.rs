@@ -0,0 +1,47 @@
+//! Chats kept in memory only, while ledger persistence is switched off.
+//!
+//! Entries are what a ledger block would hold (encoded chats, edits,
+//! reactions). Only the newest `capacity` are kept, and all of them are gone
+//! when the app exits.
+
+use std::collections::VecDeque;
+use std::sync::Mutex;
+
+pub const DEFAULT_CAPACITY: usize = 1000;
+
+#[derive(Debug)]
+pub struct MemoryLog {
+    capacity: usize,
+    inner: Mutex<VecDeque<String>>,
+}
+
+impl MemoryLog {
+    pub fn new(capacity: usize) -> std::sync::Arc<Self> {
+        std::sync::Arc::new(Self {
+            capacity: capacity.max(1),
+            inner: Mutex::new(VecDeque::new()),
+        })
+    }
+
+    /// Keep `data`, dropping the oldest entry when full.
+    pub fn push(&self, data: String) {
+        let mut guard = self.inner.lock().unwrap();
+        if guard.len() == self.capacity {
+            guard.pop_front();
+        }
+        guard.push_back(data);
+    }
+
+    /// Entries oldest first.
+    pub fn entries(&self) -> Vec<String> {
+        self.inner.lock().unwrap().iter().cloned().collect()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.inner.lock().unwrap().is_empty()
+    }
+
+    pub fn clear(&self) {
+        self.inner.lock().unwrap().clear();
+    }
+}
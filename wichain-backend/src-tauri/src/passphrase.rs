@@ -0,0 +1,72 @@
+//! Passphrase protection for the identity private key.
+//!
+//! The key is sealed with AES-256-GCM under a key stretched from the user's
+//! passphrase with Argon2id; salt and nonce are stored next to the ciphertext
+//! in `identity.json`. A wrong passphrase fails the GCM tag check, so it is
+//! reported as such rather than yielding a garbage key.
+
+use aes_gcm::aead::{generic_array::GenericArray, Aead, KeyInit};
+use aes_gcm::Aes256Gcm;
+use argon2::Argon2;
+use base64::{engine::general_purpose, Engine as _};
+use serde::{Deserialize, Serialize};
+
+/// Shortest passphrase accepted by `seal`.
+pub const MIN_PASSPHRASE_CHARS: usize = 8;
+
+const SALT_LEN: usize = 16;
+const NONCE_LEN: usize = 12;
+
+/// Private key encrypted under a passphrase (all fields base64).
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SealedKey {
+    pub salt_b64: String,
+    pub nonce_b64: String,
+    pub ciphertext_b64: String,
+}
+
+fn derive_key(passphrase: &str, salt: &[u8]) -> Result<[u8; 32], String> {
+    let mut key = [0u8; 32];
+    Argon2::default()
+        .hash_password_into(passphrase.as_bytes(), salt, &mut key)
+        .map_err(|e| format!("derive key: {e}"))?;
+    Ok(key)
+}
+
+/// Encrypt `private_key_b64` under `passphrase` with a fresh salt and nonce.
+pub fn seal(private_key_b64: &str, passphrase: &str) -> Result<SealedKey, String> {
+    if passphrase.chars().count() < MIN_PASSPHRASE_CHARS {
+        return Err(format!("password must be at least {MIN_PASSPHRASE_CHARS} characters"));
+    }
+    let salt: [u8; SALT_LEN] = rand::random();
+    let nonce: [u8; NONCE_LEN] = rand::random();
+    let key = derive_key(passphrase, &salt)?;
+    let ciphertext = Aes256Gcm::new(GenericArray::from_slice(&key))
+        .encrypt(GenericArray::from_slice(&nonce), private_key_b64.as_bytes())
+        .map_err(|_| "encrypt identity key failed".to_string())?;
+    Ok(SealedKey {
+        salt_b64: general_purpose::STANDARD.encode(salt),
+        nonce_b64: general_purpose::STANDARD.encode(nonce),
+        ciphertext_b64: general_purpose::STANDARD.encode(ciphertext),
+    })
+}
+
+/// Decrypt a sealed key back to `private_key_b64`.
+pub fn open(sealed: &SealedKey, passphrase: &str) -> Result<String, String> {
+    let decode = |field: &str, value: &str| {
+        general_purpose::STANDARD
+            .decode(value)
+            .map_err(|e| format!("sealed key {field}: {e}"))
+    };
+    let salt = decode("salt", &sealed.salt_b64)?;
+    let nonce = decode("nonce", &sealed.nonce_b64)?;
+    let ciphertext = decode("ciphertext", &sealed.ciphertext_b64)?;
+    if nonce.len() != NONCE_LEN {
+        return Err("sealed key nonce must be 12 bytes".into());
+    }
+    let key = derive_key(passphrase, &salt)?;
+    let clear = Aes256Gcm::new(GenericArray::from_slice(&key))
+        .decrypt(GenericArray::from_slice(&nonce), ciphertext.as_slice())
+        .map_err(|_| "wrong identity password".to_string())?;
+    String::from_utf8(clear).map_err(|_| "sealed key is not valid UTF-8".into())
+}
@@ -0,0 +1,84 @@
+//! Persisted set of pinned chat messages.
+//!
+//! Pins reference messages by their deterministic message id (see
+//! `ChatSigned::message_id` in `main.rs`) and remember which conversation
+//! (peer pubkey or group id) they belong to, so per-conversation lookups don't
+//! need to rescan the chain. Stored in `pins.json`, separate from the ledger.
+
+use std::fs;
+use std::path::PathBuf;
+use std::sync::Mutex;
+
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PinEntry {
+    pub id: String,
+    pub conversation_id: String,
+    pub pinned_at_ms: u64,
+}
+
+#[derive(Debug)]
+pub struct PinStore {
+    path: PathBuf,
+    inner: Mutex<Vec<PinEntry>>,
+}
+
+impl PinStore {
+    /// Load pins from `path` (missing or unreadable file = no pins).
+    pub fn load(path: PathBuf) -> std::sync::Arc<Self> {
+        let pins = fs::read_to_string(&path)
+            .ok()
+            .and_then(|data| serde_json::from_str::<Vec<PinEntry>>(&data).ok())
+            .unwrap_or_default();
+        std::sync::Arc::new(Self {
+            path,
+            inner: Mutex::new(pins),
+        })
+    }
+
+    fn save(&self, pins: &[PinEntry]) -> Result<(), String> {
+        let json = serde_json::to_string_pretty(pins).map_err(|e| format!("serialize pins: {e}"))?;
+        fs::write(&self.path, json).map_err(|e| format!("write pins: {e}"))
+    }
+
+    /// Pin a message; pinning twice is a no-op. Returns `true` if newly pinned.
+    pub fn pin(&self, entry: PinEntry) -> Result<bool, String> {
+        let mut guard = self.inner.lock().unwrap();
+        if guard.iter().any(|p| p.id == entry.id) {
+            return Ok(false);
+        }
+        guard.push(entry);
+        self.save(&guard)?;
+        Ok(true)
+    }
+
+    /// Unpin a message. Returns `true` if it was pinned.
+    pub fn unpin(&self, id: &str) -> Result<bool, String> {
+        let mut guard = self.inner.lock().unwrap();
+        let before = guard.len();
+        guard.retain(|p| p.id != id);
+        if guard.len() == before {
+            return Ok(false);
+        }
+        self.save(&guard)?;
+        Ok(true)
+    }
+
+    /// Pins for one conversation, oldest pin first.
+    pub fn for_conversation(&self, conversation_id: &str) -> Vec<PinEntry> {
+        let guard = self.inner.lock().unwrap();
+        guard
+            .iter()
+            .filter(|p| p.conversation_id == conversation_id)
+            .cloned()
+            .collect()
+    }
+
+    /// Forget every pin.
+    pub fn clear(&self) -> Result<(), String> {
+        let mut guard = self.inner.lock().unwrap();
+        guard.clear();
+        self.save(&guard)
+    }
+}
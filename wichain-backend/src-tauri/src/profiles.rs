@@ -0,0 +1,116 @@
+//! Local identity profiles.
+//!
+//! A profile is an identity file plus its own ledger: `identities/<name>.json`
+//! and `identities/<name>.blockchain.json` under the data dir. The original
+//! single-identity files (`identity.json`, `blockchain.json`) are the
+//! `default` profile, so existing installs keep working unchanged. The name of
+//! the profile to load at startup is kept in `identities/active`.
+
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use serde::Serialize;
+
+use super::{BLOCKCHAIN_FILE, IDENTITY_FILE};
+
+pub const DEFAULT_PROFILE: &str = "default";
+const PROFILES_DIR: &str = "identities";
+const ACTIVE_FILE: &str = "active";
+const MAX_NAME_CHARS: usize = 32;
+
+/// Files backing one profile.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ProfilePaths {
+    pub name: String,
+    pub identity: PathBuf,
+    pub blockchain: PathBuf,
+}
+
+/// One profile as listed to the UI.
+#[derive(Debug, Clone, Serialize)]
+pub struct ProfileInfo {
+    pub name: String,
+    pub alias: String,
+    pub public_key_b64: String,
+    pub active: bool,
+}
+
+/// Profile names are file stems: ASCII letters, digits, `-` and `_`.
+pub fn is_valid_name(name: &str) -> bool {
+    !name.is_empty()
+        && name.len() <= MAX_NAME_CHARS
+        && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+}
+
+pub fn paths(data_dir: &Path, name: &str) -> ProfilePaths {
+    if name == DEFAULT_PROFILE {
+        return ProfilePaths {
+            name: name.to_string(),
+            identity: data_dir.join(IDENTITY_FILE),
+            blockchain: data_dir.join(BLOCKCHAIN_FILE),
+        };
+    }
+    let dir = data_dir.join(PROFILES_DIR);
+    ProfilePaths {
+        name: name.to_string(),
+        identity: dir.join(format!("{name}.json")),
+        blockchain: dir.join(format!("{name}.blockchain.json")),
+    }
+}
+
+pub fn exists(data_dir: &Path, name: &str) -> bool {
+    is_valid_name(name) && paths(data_dir, name).identity.exists()
+}
+
+/// Names of every profile with an identity file, `default` first.
+pub fn list(data_dir: &Path) -> Vec<String> {
+    let mut names: Vec<String> = fs::read_dir(data_dir.join(PROFILES_DIR))
+        .into_iter()
+        .flatten()
+        .filter_map(|entry| {
+            let file_name = entry.ok()?.file_name().into_string().ok()?;
+            let stem = file_name.strip_suffix(".json")?;
+            (is_valid_name(stem) && stem != DEFAULT_PROFILE).then(|| stem.to_string())
+        })
+        .collect();
+    names.sort();
+    if exists(data_dir, DEFAULT_PROFILE) {
+        names.insert(0, DEFAULT_PROFILE.to_string());
+    }
+    names
+}
+
+/// Profile to load at startup (`default` if unset or gone).
+pub fn active(data_dir: &Path) -> String {
+    fs::read_to_string(data_dir.join(PROFILES_DIR).join(ACTIVE_FILE))
+        .ok()
+        .map(|name| name.trim().to_string())
+        .filter(|name| exists(data_dir, name))
+        .unwrap_or_else(|| DEFAULT_PROFILE.to_string())
+}
+
+pub fn set_active(data_dir: &Path, name: &str) -> Result<(), String> {
+    let dir = data_dir.join(PROFILES_DIR);
+    fs::create_dir_all(&dir).map_err(|e| format!("create profiles dir: {e}"))?;
+    fs::write(dir.join(ACTIVE_FILE), name).map_err(|e| format!("write active profile: {e}"))
+}
+
+/// A free profile name derived from `alias` (`alice`, `alice-2`, ...).
+pub fn unused_name(data_dir: &Path, alias: &str) -> String {
+    let slug: String = alias
+        .trim()
+        .to_lowercase()
+        .chars()
+        .map(|c| if c.is_ascii_alphanumeric() { c } else { '-' })
+        .take(MAX_NAME_CHARS - 4)
+        .collect();
+    let slug = slug.trim_matches('-');
+    let base = if slug.is_empty() { "profile" } else { slug };
+    let mut name = base.to_string();
+    let mut n = 2;
+    while name == DEFAULT_PROFILE || paths(data_dir, &name).identity.exists() {
+        name = format!("{base}-{n}");
+        n += 1;
+    }
+    name
+}
@@ -0,0 +1,106 @@
+//! Persisted holding area for chats from peers below the trust threshold, and
+//! for chats that fail the sender check (bad signature, or signed by someone
+//! other than the peer that delivered them) when strict mode is off.
+//!
+//! Quarantined chats are kept out of the ledger (and so out of history and
+//! search) until the user releases them, which appends them to the chain as if
+//! they had just arrived. Entries hold the chat exactly as it would be stored
+//! in a block (text encrypted for storage). Stored in `quarantine.json`; the
+//! oldest entries are dropped beyond `CAPACITY` so a noisy peer can't grow the
+//! file without bound.
+//!
+//! This complements the network blocklist rather than replacing it: blocked
+//! peers' traffic is dropped before decryption and never reaches quarantine.
+//! Releasing a chat doesn't change its sender's trust.
+
+use std::fs;
+use std::path::PathBuf;
+use std::sync::Mutex;
+
+use serde::{Deserialize, Serialize};
+
+const CAPACITY: usize = 500;
+
+/// Why a chat was held.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum HoldReason {
+    /// Sender was below `min_trust_to_accept`.
+    #[default]
+    LowTrust,
+    /// Unsigned, or the signature doesn't match the claimed author.
+    BadSignature,
+    /// Validly signed, but not by the peer whose key delivered it.
+    SenderMismatch,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct QuarantineEntry {
+    /// Message id of the held chat.
+    pub id: String,
+    /// Peer whose key delivered it.
+    pub sender: String,
+    /// Sender's trust when it arrived.
+    pub trust_score: f64,
+    pub received_ms: u64,
+    /// Block data to append on release.
+    pub data: String,
+    /// Entries from before reasons were recorded were all low-trust holds.
+    #[serde(default)]
+    pub reason: HoldReason,
+}
+
+#[derive(Debug)]
+pub struct QuarantineStore {
+    path: PathBuf,
+    inner: Mutex<Vec<QuarantineEntry>>,
+}
+
+impl QuarantineStore {
+    /// Load entries from `path` (missing or unreadable file = empty).
+    pub fn load(path: PathBuf) -> std::sync::Arc<Self> {
+        let entries = fs::read_to_string(&path)
+            .ok()
+            .and_then(|data| serde_json::from_str::<Vec<QuarantineEntry>>(&data).ok())
+            .unwrap_or_default();
+        std::sync::Arc::new(Self {
+            path,
+            inner: Mutex::new(entries),
+        })
+    }
+
+    fn save(&self, entries: &[QuarantineEntry]) -> Result<(), String> {
+        let json = serde_json::to_string_pretty(entries).map_err(|e| format!("serialize quarantine: {e}"))?;
+        fs::write(&self.path, json).map_err(|e| format!("write quarantine: {e}"))
+    }
+
+    /// Hold a chat; a message id already held is ignored.
+    pub fn hold(&self, entry: QuarantineEntry) -> Result<(), String> {
+        let mut guard = self.inner.lock().unwrap();
+        if guard.iter().any(|e| e.id == entry.id) {
+            return Ok(());
+        }
+        guard.push(entry);
+        if guard.len() > CAPACITY {
+            let excess = guard.len() - CAPACITY;
+            guard.drain(..excess);
+        }
+        self.save(&guard)
+    }
+
+    /// Everything held, oldest first.
+    pub fn list(&self) -> Vec<QuarantineEntry> {
+        self.inner.lock().unwrap().clone()
+    }
+
+    /// Remove and return the entry for message `id`.
+    pub fn release(&self, id: &str) -> Result<Option<QuarantineEntry>, String> {
+        let mut guard = self.inner.lock().unwrap();
+        let Some(pos) = guard.iter().position(|e| e.id == id) else {
+            return Ok(None);
+        };
+        let entry = guard.remove(pos);
+        self.save(&guard)?;
+        Ok(Some(entry))
+    }
+}
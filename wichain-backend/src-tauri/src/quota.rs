@@ -0,0 +1,128 @@
+//! On-disk footprint of the local data files and an optional size cap.
+//!
+//! The ledger is rewritten whole on every message, so on small devices it is
+//! the file that grows without bound. `DiskQuota` measures it (plus the
+//! identity file), persists the user's cap in `quota.json`, and classifies the
+//! current usage so callers can warn or prune. Pruning itself lives with the
+//! chat code in `main.rs`, which knows what a chat block looks like.
+
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+
+use serde::{Deserialize, Serialize};
+
+/// Fraction of the cap at which `disk_usage_warning` fires.
+pub const WARN_RATIO: f64 = 0.9;
+
+#[derive(Debug, Clone, Serialize)]
+pub struct DiskUsage {
+    pub blockchain_bytes: u64,
+    pub identity_bytes: u64,
+    pub total_bytes: u64,
+    pub cap_bytes: Option<u64>,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct QuotaSettings {
+    /// `None` = unlimited.
+    pub cap_bytes: Option<u64>,
+    /// User consented to dropping the oldest messages automatically when over the cap.
+    #[serde(default)]
+    pub auto_prune: bool,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum QuotaStatus {
+    /// No cap, or comfortably below it.
+    Ok,
+    /// Crossed `WARN_RATIO` of the cap (reported once per crossing).
+    Warning,
+    /// Above the cap.
+    Exceeded,
+}
+
+#[derive(Debug)]
+pub struct DiskQuota {
+    settings_path: PathBuf,
+    /// Ledger and identity of the active profile.
+    tracked: Mutex<(PathBuf, PathBuf)>,
+    settings: Mutex<QuotaSettings>,
+    warned: AtomicBool,
+}
+
+/// Size of `path` in bytes; missing or unreadable files count as empty.
+fn file_size(path: &Path) -> u64 {
+    fs::metadata(path).map(|m| m.len()).unwrap_or(0)
+}
+
+impl DiskQuota {
+    /// Load the cap from `settings_path` (missing or unreadable file = no cap).
+    pub fn load(settings_path: PathBuf, blockchain_path: PathBuf, identity_path: PathBuf) -> Arc<Self> {
+        let settings = fs::read_to_string(&settings_path)
+            .ok()
+            .and_then(|data| serde_json::from_str::<QuotaSettings>(&data).ok())
+            .unwrap_or_default();
+        Arc::new(Self {
+            settings_path,
+            tracked: Mutex::new((blockchain_path, identity_path)),
+            settings: Mutex::new(settings),
+            warned: AtomicBool::new(false),
+        })
+    }
+
+    /// Measure a different profile's files from now on.
+    pub fn set_tracked_files(&self, blockchain_path: PathBuf, identity_path: PathBuf) {
+        *self.tracked.lock().unwrap() = (blockchain_path, identity_path);
+        self.warned.store(false, Ordering::Relaxed);
+    }
+
+    pub fn settings(&self) -> QuotaSettings {
+        self.settings.lock().unwrap().clone()
+    }
+
+    /// Replace and persist the quota settings.
+    pub fn set(&self, settings: QuotaSettings) -> Result<(), String> {
+        if settings.cap_bytes == Some(0) {
+            return Err("cap must be greater than zero".into());
+        }
+        let json = serde_json::to_string_pretty(&settings).map_err(|e| format!("serialize quota: {e}"))?;
+        fs::write(&self.settings_path, json).map_err(|e| format!("write quota: {e}"))?;
+        *self.settings.lock().unwrap() = settings;
+        self.warned.store(false, Ordering::Relaxed);
+        Ok(())
+    }
+
+    pub fn usage(&self) -> DiskUsage {
+        let (blockchain_bytes, identity_bytes) = {
+            let tracked = self.tracked.lock().unwrap();
+            (file_size(&tracked.0), file_size(&tracked.1))
+        };
+        DiskUsage {
+            blockchain_bytes,
+            identity_bytes,
+            total_bytes: blockchain_bytes + identity_bytes,
+            cap_bytes: self.settings().cap_bytes,
+        }
+    }
+
+    /// Classify `usage` against the cap. `Warning` is only returned the first
+    /// time the threshold is crossed so callers can emit it unconditionally.
+    pub fn status(&self, usage: &DiskUsage) -> QuotaStatus {
+        let Some(cap) = usage.cap_bytes else {
+            return QuotaStatus::Ok;
+        };
+        if usage.total_bytes > cap {
+            return QuotaStatus::Exceeded;
+        }
+        if usage.total_bytes as f64 >= cap as f64 * WARN_RATIO {
+            if self.warned.swap(true, Ordering::Relaxed) {
+                return QuotaStatus::Ok;
+            }
+            return QuotaStatus::Warning;
+        }
+        self.warned.store(false, Ordering::Relaxed);
+        QuotaStatus::Ok
+    }
+}
@@ -0,0 +1,69 @@
+//! Persisted read receipts for chats we sent.
+//!
+//! A receipt says that `reader` saw our chat `msg_id` at `read_ms`. Only the
+//! first receipt per (message, reader) is kept, since a chat is read once.
+//! Stored in `receipts.json`; the oldest entries are dropped beyond `CAPACITY`.
+
+use std::fs;
+use std::path::PathBuf;
+use std::sync::Mutex;
+
+use serde::{Deserialize, Serialize};
+
+const CAPACITY: usize = 2000;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ReceiptEntry {
+    /// Message id of our chat.
+    pub msg_id: String,
+    /// Recipient that read it.
+    pub reader: String,
+    pub read_ms: u64,
+}
+
+#[derive(Debug)]
+pub struct ReceiptStore {
+    path: PathBuf,
+    inner: Mutex<Vec<ReceiptEntry>>,
+}
+
+impl ReceiptStore {
+    /// Load receipts from `path` (missing or unreadable file = none).
+    pub fn load(path: PathBuf) -> std::sync::Arc<Self> {
+        let entries = fs::read_to_string(&path)
+            .ok()
+            .and_then(|data| serde_json::from_str::<Vec<ReceiptEntry>>(&data).ok())
+            .unwrap_or_default();
+        std::sync::Arc::new(Self {
+            path,
+            inner: Mutex::new(entries),
+        })
+    }
+
+    fn save(&self, entries: &[ReceiptEntry]) -> Result<(), String> {
+        let json = serde_json::to_string_pretty(entries).map_err(|e| format!("serialize receipts: {e}"))?;
+        fs::write(&self.path, json).map_err(|e| format!("write receipts: {e}"))
+    }
+
+    /// Record a receipt. Returns `false` if this reader already acknowledged
+    /// the message.
+    pub fn record(&self, entry: ReceiptEntry) -> Result<bool, String> {
+        let mut guard = self.inner.lock().unwrap();
+        if guard.iter().any(|e| e.msg_id == entry.msg_id && e.reader == entry.reader) {
+            return Ok(false);
+        }
+        guard.push(entry);
+        if guard.len() > CAPACITY {
+            let excess = guard.len() - CAPACITY;
+            guard.drain(..excess);
+        }
+        self.save(&guard)?;
+        Ok(true)
+    }
+
+    /// When `msg_id` was first read by any recipient, if it has been.
+    pub fn read_status(&self, msg_id: &str) -> Option<u64> {
+        let guard = self.inner.lock().unwrap();
+        guard.iter().filter(|e| e.msg_id == msg_id).map(|e| e.read_ms).min()
+    }
+}
@@ -0,0 +1,173 @@
+//! Catching up on chats from a peer's chain.
+//!
+//! Every node keeps its own ledger, so a peer's blocks can't be linked onto
+//! ours. Instead the requester asks for the blocks after an index of the
+//! peer's chain (`ChainRequest`), checks that what comes back is an unbroken,
+//! hash-linked run, and re-appends the signed chats in it that verify, that
+//! it is a party to and that it doesn't have yet.
+//!
+//! Ledger text is encrypted under the responder's own storage key, so block
+//! content never travels: each block goes out as its header (empty `data`,
+//! see `Blockchain::is_valid_segment`), and chats the requester is party to
+//! ride along sealed for it. Replies are paged; the requester asks again from
+//! the last index it got until the peer has nothing more. Replies from a
+//! chain on another network (see `Blockchain::new_with_genesis`) are refused.
+
+use std::collections::{HashMap, HashSet};
+use std::sync::Mutex;
+
+use ed25519_dalek::SigningKey;
+use serde::{Deserialize, Serialize};
+use wichain_blockchain::{Block, Blockchain};
+
+use super::{
+    decode_stored_chat, encode_stored_chat, is_party_to, open_payload, seal_ephemeral, verify_chat, ChatSigned,
+    GroupManager, StorageKey,
+};
+
+/// Most blocks offered per reply; the network layer sends as many as fit.
+pub const MAX_BLOCKS_PER_REPLY: usize = 256;
+
+/// One block of a chain reply.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SegmentBlock {
+    /// The block with its `data` withheld.
+    pub header: Block,
+    /// The block's chat, sealed for the requester, if it is party to it.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub sealed_chat: Option<String>,
+}
+
+/// Blocks after `since_index` as `requester` may see them, one JSON
+/// `SegmentBlock` per block.
+pub fn segment_for(
+    chain: &Blockchain,
+    key: &StorageKey,
+    since_index: u64,
+    requester: &str,
+    groups: &GroupManager,
+) -> Vec<String> {
+    chain
+        .chain
+        .iter()
+        .filter(|b| b.index > since_index)
+        .take(MAX_BLOCKS_PER_REPLY)
+        .map(|b| {
+            let sealed_chat = decode_stored_chat(&b.data, key)
+                .filter(|c| is_party_to(&c.body, requester, groups))
+                .and_then(|c| seal_ephemeral(requester, &serde_json::to_string(&c).ok()?).ok());
+            let header = Block { data: String::new(), merkle_root: None, ..b.clone() };
+            serde_json::to_string(&SegmentBlock { header, sealed_chat }).unwrap_or_default()
+        })
+        .collect()
+}
+
+/// Append the chats in `blocks` from peer `from`'s chain on `network_id`
+/// that open, are signed, verify, involve `my_pub` and aren't on `chain`
+/// already. Returns how many were appended. A run from another network, or
+/// whose headers don't chain-link, is rejected whole.
+pub fn accept_segment(
+    chain: &mut Blockchain,
+    network_id: &str,
+    blocks: &[SegmentBlock],
+    from: &str,
+    my_sk: &SigningKey,
+    my_pub: &str,
+    groups: &GroupManager,
+) -> Result<usize, String> {
+    chain.ensure_same_network(network_id).map_err(|e| e.to_string())?;
+    let headers: Vec<Block> = blocks.iter().map(|b| b.header.clone()).collect();
+    if !Blockchain::is_valid_segment(&headers) {
+        return Err("blocks do not chain-link".into());
+    }
+    let key = StorageKey::for_identity(my_sk).with_groups(groups);
+    let mut known: HashSet<String> = chain
+        .chain
+        .iter()
+        .filter_map(|b| decode_stored_chat(&b.data, &key))
+        .map(|c| c.message_id())
+        .collect();
+    let mut appended = 0;
+    for sealed in blocks.iter().filter_map(|b| b.sealed_chat.as_deref()) {
+        let Ok(clear) = open_payload(my_sk, from, sealed) else { continue };
+        let Ok(chat) = serde_json::from_str::<ChatSigned>(&clear) else { continue };
+        if chat.sig_b64.is_empty() || !verify_chat(&chat) || !is_party_to(&chat.body, my_pub, groups) {
+            continue;
+        }
+        if known.insert(chat.message_id()) {
+            chain.add_text_block(encode_stored_chat(&chat, &key));
+            appended += 1;
+        }
+    }
+    Ok(appended)
+}
+
+/// Where a resync with one peer has got to on that peer's chain.
+#[derive(Debug, Clone, Default)]
+struct Cursor {
+    index: u64,
+    /// Hash of the block at `index`, once one has been received.
+    hash: Option<String>,
+    in_flight: bool,
+}
+
+/// Resyncs per peer. Replies are only taken from peers we asked, and each
+/// page must continue where the previous one ended.
+#[derive(Debug, Default)]
+pub struct ResyncTracker {
+    cursors: Mutex<HashMap<String, Cursor>>,
+}
+
+impl ResyncTracker {
+    /// Start (or restart) a resync with `peer_id`; returns the index to ask from.
+    pub fn start(&self, peer_id: &str) -> u64 {
+        let mut cursors = self.cursors.lock().unwrap();
+        let cursor = cursors.entry(peer_id.to_string()).or_default();
+        cursor.in_flight = true;
+        cursor.index
+    }
+
+    /// Whether we have a request open with `peer_id`.
+    pub fn expecting(&self, peer_id: &str) -> bool {
+        self.cursors.lock().unwrap().get(peer_id).is_some_and(|c| c.in_flight)
+    }
+
+    /// Check that `blocks` answer our open request to `peer_id` and carry on
+    /// from the last page.
+    pub fn check(&self, peer_id: &str, blocks: &[Block]) -> Result<(), String> {
+        let cursors = self.cursors.lock().unwrap();
+        let cursor = match cursors.get(peer_id) {
+            Some(cursor) if cursor.in_flight => cursor,
+            _ => return Err("unsolicited chain response".into()),
+        };
+        let Some(first) = blocks.first() else { return Ok(()) };
+        if first.index != cursor.index + 1 {
+            return Err(format!("expected block {}, got {}", cursor.index + 1, first.index));
+        }
+        if cursor.hash.as_ref().is_some_and(|hash| *hash != first.previous_hash) {
+            return Err("blocks do not continue the previous page".into());
+        }
+        Ok(())
+    }
+
+    /// Record an accepted page. Returns the index to ask from next, or `None`
+    /// once the peer has sent everything.
+    pub fn advance(&self, peer_id: &str, blocks: &[Block], more: bool) -> Option<u64> {
+        let mut cursors = self.cursors.lock().unwrap();
+        let cursor = cursors.get_mut(peer_id)?;
+        if let Some(last) = blocks.last() {
+            cursor.index = last.index;
+            cursor.hash = Some(last.hash.clone());
+        }
+        cursor.in_flight = more && !blocks.is_empty();
+        cursor.in_flight.then_some(cursor.index)
+    }
+
+    /// Give up on the open request to `peer_id` (a bad page); the next resync
+    /// restarts from the last good one.
+    pub fn abort(&self, peer_id: &str) {
+        if let Some(cursor) = self.cursors.lock().unwrap().get_mut(peer_id) {
+            cursor.in_flight = false;
+        }
+    }
+}
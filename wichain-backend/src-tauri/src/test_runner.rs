@@ -1,155 +1,126 @@
-//! Standalone test runner for WiChain functionality
-//! This can be run independently to test TCP and AES functionality
+//! Self-test behind `run_comprehensive_tests`: exercises encryption and the
+//! real send path between two in-process nodes, and reports what happened.
+//! The nodes bind ephemeral ports, so it can run next to a live node (or
+//! twice at once) and under `cargo test`.
 
+use std::net::{Ipv4Addr, SocketAddr};
 use std::time::Duration;
-use tokio::time::sleep;
+use base64::{engine::general_purpose, Engine as _};
+use ed25519_dalek::SigningKey;
+use rand::rngs::OsRng;
+use tokio::sync::mpsc;
+use tokio::time::timeout;
 use wichain_network::{NetworkNode, NetworkMessage};
 
 // Import the encryption functions from main.rs
 use crate::{
-    encrypt_json_aes256gcm, 
+    encrypt_json_aes256gcm,
     decrypt_json_aes256gcm,
     encrypt_for_storage,
-    decrypt_from_storage
+    decrypt_from_storage,
+    StorageKey,
+    WiChainError,
 };
 
+/// How long the receiving node gets to hand over the block.
+const DELIVERY_TIMEOUT: Duration = Duration::from_secs(3);
+
 /// Test AES-256-GCM encryption and decryption
-async fn test_aes256gcm_encryption() {
-    println!("🧪 Testing AES-256-GCM encryption...");
-    
-    let pub_a = "test_pubkey_a_123456789012345678901234567890";
-    let pub_b = "test_pubkey_b_123456789012345678901234567890";
+fn test_aes256gcm_encryption() -> Result<String, String> {
+    let sk_a = SigningKey::generate(&mut OsRng);
+    let sk_b = SigningKey::generate(&mut OsRng);
+    let pub_a = &general_purpose::STANDARD.encode(sk_a.verifying_key().to_bytes());
+    let pub_b = &general_purpose::STANDARD.encode(sk_b.verifying_key().to_bytes());
     let test_message = "Hello, this is a test message for AES-256-GCM encryption!";
-    
-    // Test encryption
-    let encrypted = encrypt_json_aes256gcm(pub_a, pub_b, test_message)
-        .expect("Encryption should succeed");
-    
-    println!("✅ Encryption successful");
-    println!("   Original length: {} bytes", test_message.len());
-    println!("   Encrypted length: {} bytes", encrypted.len());
-    
-    // Test decryption
-    let decrypted = decrypt_json_aes256gcm(pub_a, pub_b, &encrypted)
-        .expect("Decryption should succeed");
-    
-    println!("✅ Decryption successful");
-    println!("   Decrypted message: '{}'", decrypted);
-    
-    // Verify round-trip
-    assert_eq!(test_message, decrypted);
-    println!("✅ Round-trip test passed!");
-    
-    // Test with different peer order (should work due to sorted key derivation)
-    let encrypted_reverse = encrypt_json_aes256gcm(pub_b, pub_a, test_message)
-        .expect("Reverse encryption should succeed");
-    let decrypted_reverse = decrypt_json_aes256gcm(pub_a, pub_b, &encrypted_reverse)
-        .expect("Reverse decryption should succeed");
-    
-    assert_eq!(test_message, decrypted_reverse);
-    println!("✅ Bidirectional encryption test passed!");
+
+    let encrypted = encrypt_json_aes256gcm(&sk_a, pub_b, test_message)?;
+    if decrypt_json_aes256gcm(&sk_b, pub_a, &encrypted)? != test_message {
+        return Err("AES-256-GCM round trip changed the message".into());
+    }
+    // Reverse direction derives the same X25519 shared key
+    let encrypted_reverse = encrypt_json_aes256gcm(&sk_b, pub_a, test_message)?;
+    if decrypt_json_aes256gcm(&sk_a, pub_b, &encrypted_reverse)? != test_message {
+        return Err("AES-256-GCM reverse round trip changed the message".into());
+    }
+    Ok(format!("AES-256-GCM round trips both ways ({} → {} bytes)", test_message.len(), encrypted.len()))
 }
 
 /// Test blockchain storage encryption
-async fn test_storage_encryption() {
-    println!("🧪 Testing blockchain storage encryption...");
-    
-    let user_pubkey = "test_user_pubkey_123456789012345678901234567890";
+fn test_storage_encryption() -> Result<String, String> {
+    let key = StorageKey::for_identity(&SigningKey::generate(&mut OsRng));
     let test_message = "This is a message stored in the blockchain";
-    
-    // Test encryption
-    let encrypted = encrypt_for_storage(test_message, user_pubkey);
-    println!("✅ Storage encryption successful");
-    println!("   Original: '{}'", test_message);
-    println!("   Encrypted length: {} bytes", encrypted.len());
-    
-    // Test decryption
-    let decrypted = decrypt_from_storage(&encrypted, user_pubkey)
-        .expect("Storage decryption should succeed");
-    
-    println!("✅ Storage decryption successful");
-    println!("   Decrypted: '{}'", decrypted);
-    
-    // Verify round-trip
-    assert_eq!(test_message, decrypted);
-    println!("✅ Storage encryption round-trip test passed!");
-}
 
-/// Test TCP connection establishment and communication
-async fn test_tcp_connection_establishment() {
-    println!("🧪 Testing TCP connection establishment...");
-    
-    // Create two network nodes
-    let node1 = NetworkNode::new(
-        60001, // UDP port
-        "node1_id_123456789012345678901234567890".to_string(),
-        "Node1".to_string(),
-        "node1_pubkey_123456789012345678901234567890".to_string(),
-    );
-    
-    let node2 = NetworkNode::new(
-        60002, // UDP port
-        "node2_id_123456789012345678901234567890".to_string(),
-        "Node2".to_string(),
-        "node2_pubkey_123456789012345678901234567890".to_string(),
-    );
-    
-    println!("✅ Network nodes created");
-    println!("   Node1 UDP port: 60001, TCP port: {}", node1.get_tcp_port());
-    println!("   Node2 UDP port: 60002, TCP port: {}", node2.get_tcp_port());
-    
-    // Start both nodes
-    let (tx1, _rx1) = tokio::sync::mpsc::channel::<NetworkMessage>(64);
-    let (tx2, _rx2) = tokio::sync::mpsc::channel::<NetworkMessage>(64);
-    
-    node1.start(tx1).await;
-    node2.start(tx2).await;
-    
-    println!("✅ Both nodes started");
-    
-    // Wait a bit for nodes to initialize
-    sleep(Duration::from_millis(1000)).await;
-    
-    // Test TCP connection request
-    let result = node1.request_tcp_connection("node2_id_123456789012345678901234567890").await;
-    match result {
-        Ok(()) => println!("✅ TCP connection request sent successfully"),
-        Err(e) => println!("⚠️  TCP connection request failed: {}", e),
+    let encrypted = encrypt_for_storage(test_message, &key);
+    if decrypt_from_storage(&encrypted, &key).as_deref() != Some(test_message) {
+        return Err("storage round trip failed".into());
     }
-    
-    // Wait for connection establishment
-    sleep(Duration::from_millis(2000)).await;
-    
-    // Check connection status
-    let has_tcp = node1.has_tcp_connection("node2_id_123456789012345678901234567890").await;
-    println!("   TCP connection status: {}", if has_tcp { "✅ Connected" } else { "❌ Not connected" });
-    
-    // Test message sending
-    let test_payload = "Test message via TCP";
-    let result = node1.send_message("node2_id_123456789012345678901234567890", test_payload.to_string()).await;
-    match result {
-        Ok(()) => println!("✅ Message sent successfully"),
-        Err(e) => println!("⚠️  Message sending failed: {}", e),
+    // Another identity's key can't read it
+    let other = StorageKey::for_identity(&SigningKey::generate(&mut OsRng));
+    if decrypt_from_storage(&encrypted, &other).is_some() {
+        return Err("another identity's key read stored data".into());
+    }
+    Ok("Storage encryption round trips, and only with this identity's key".into())
+}
+
+/// Node whose id is a fresh pubkey, so its signed announces are accepted,
+/// started on ephemeral ports.
+async fn ephemeral_node(alias: &str) -> Result<(NetworkNode, mpsc::Receiver<NetworkMessage>, u16), String> {
+    let key = SigningKey::generate(&mut OsRng);
+    let id = general_purpose::STANDARD.encode(key.verifying_key().to_bytes());
+    let node = NetworkNode::new(0, id.clone(), alias.to_string(), id).with_signing_key(key);
+    let (tx, rx) = mpsc::channel(64);
+    node.start(tx).await;
+    let port = node.bound_addr().await.ok_or_else(|| format!("{alias}: no UDP socket bound"))?.port();
+    Ok((node, rx, port))
+}
+
+/// Two nodes find each other by address and one sends the other a block,
+/// which must come out of the receiver's channel unchanged.
+async fn test_direct_delivery() -> Result<String, String> {
+    let (sender, _sender_rx, _) = ephemeral_node("Node1").await?;
+    let (receiver, mut receiver_rx, receiver_port) = ephemeral_node("Node2").await?;
+    let result = async {
+        let found = sender
+            .add_peer_by_addr(SocketAddr::from((Ipv4Addr::LOCALHOST, receiver_port)))
+            .await
+            .map_err(|e| format!("discovery failed: {e}"))?;
+        let payload = format!("{{\"self_test\":{}}}", rand::random::<u64>());
+        let delivery = sender
+            .send_message(&found.id, payload.clone())
+            .await
+            .map_err(|e| format!("send failed: {e}"))?;
+        let received = timeout(DELIVERY_TIMEOUT, async {
+            while let Some(msg) = receiver_rx.recv().await {
+                if let NetworkMessage::DirectBlock { from, payload_json, .. } = msg {
+                    return Some((from, payload_json));
+                }
+            }
+            None
+        })
+        .await
+        .ok()
+        .flatten()
+        .ok_or("the receiver never got the block")?;
+        if received != (sender.id.clone(), payload) {
+            return Err(format!("the receiver got something else: {received:?}"));
+        }
+        Ok(format!(
+            "Direct block delivered between in-process nodes (UDP {} → {}, {delivery:?})",
+            sender.bound_addr().await.map_or(0, |a| a.port()),
+            receiver_port
+        ))
     }
-    
-    println!("✅ TCP connection test completed");
+    .await;
+    sender.stop().await;
+    receiver.stop().await;
+    result
 }
 
-/// Run all tests
-pub async fn run_all_tests() {
-    println!("🚀 Running comprehensive WiChain feature tests...\n");
-    
-    // Test encryption
-    test_aes256gcm_encryption().await;
-    println!();
-    
-    test_storage_encryption().await;
-    println!();
-    
-    test_tcp_connection_establishment().await;
-    println!();
-    
-    println!("🎉 All tests completed successfully!");
-    println!("✅ AES-256-GCM encryption is working");
-    println!("✅ TCP connections are working");
+/// Run every check; one line per check, `Err` on the first failure.
+pub async fn run_all_tests() -> Result<Vec<String>, WiChainError> {
+    Ok(vec![
+        test_aes256gcm_encryption().map_err(WiChainError::Encryption)?,
+        test_storage_encryption().map_err(WiChainError::Encryption)?,
+        test_direct_delivery().await.map_err(WiChainError::NetworkSend)?,
+    ])
 }
@@ -25,6 +25,32 @@
 //!    {"direct":{"from":"<b64pub>","to":"<b64pub>","text":"hi","ts":12345}}
 //!    ```
 //!
+//! Attachments (images, files) use an **attachment payload**; `sha256` is the
+//! hex digest of the decoded bytes so receivers can check the blob:
+//!
+//! ```json
+//! {"attachment":{"from":"...","to":"...","filename":"cat.png","mime":"image/png","data_b64":"...","sha256":"..."}}
+//! ```
+//!
+//! Genesis is the text `"Genesis Block"` on the default network; other
+//! networks embed their id (see `Blockchain::new_with_genesis`):
+//!
+//! ```json
+//! {"genesis":{"network_id":"field-team"}}
+//! ```
+//!
+//! A pruned chain starts with a **checkpoint** block instead of the original
+//! genesis (see `Blockchain::prune_before`), which keeps a non-default
+//! network id:
+//!
+//! ```json
+//! {"checkpoint":{"pruned_tip_hash":"...","pruned_blocks":42,"cutoff_ms":12345}}
+//! ```
+//!
+//! The oldest prototypes stored v0 messages (`LegacyMessageJson`, a single
+//! object or an array of `{id, sender, content, signature}`); see
+//! `Block::as_legacy_messages` and `Blockchain::migrate_legacy`.
+//!
 //! If parsing either shape fails, callers can always fall back to
 //! `Block::raw_data()` (original opaque text).
 //!
@@ -33,12 +59,20 @@
 //! ```text
 //! SHA256(index || timestamp_ms || previous_hash || nonce || data)
 //! ```
+//!
+//! Message blocks additionally carry a `merkle_root` over their messages'
+//! digests (see [`crate::merkle`]). It is derived from `data` and kept out of
+//! the hash, so older blocks without it stay valid.
 
+use base64::{engine::general_purpose, Engine as _};
 use serde::{Deserialize, Serialize};
 use sha2::{Digest, Sha256};
 use std::fmt;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+use wichain_core::{truncate_chars, LegacyMessageJson, SignedMessage};
 
-use wichain_core::SignedMessage;
+use crate::merkle::{self, MerkleProofStep};
 
 /// A single block in the chain.
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -49,6 +83,28 @@ pub struct Block {
     pub nonce: u64,
     pub data: String,
     pub hash: String,
+    /// Merkle root of the embedded messages (message blocks only).
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub merkle_root: Option<String>,
+}
+
+/// Summary of the blocks a checkpoint genesis replaced.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CheckpointPayload {
+    /// Hash of the last pruned block.
+    pub pruned_tip_hash: String,
+    /// Blocks pruned so far (cumulative across checkpoints).
+    pub pruned_blocks: u64,
+    pub cutoff_ms: u128,
+    /// Network of the pruned genesis; `None` on the default network.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub network_id: Option<String>,
+}
+
+/// Genesis payload of a chain on a non-default network.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct GenesisPayload {
+    pub network_id: String,
 }
 
 /// Structured "direct text" payload decoded from `data` JSON.
@@ -61,6 +117,48 @@ pub struct DirectTextPayload {
     pub ts: u128,
 }
 
+/// Default cap on decoded attachment size (see `Blockchain::add_attachment_block`).
+pub const DEFAULT_MAX_ATTACHMENT_BYTES: usize = 5 * 1024 * 1024;
+
+/// Default cap on a text block's data (see `Blockchain::try_add_text_block`).
+pub const DEFAULT_MAX_MESSAGE_BYTES: usize = 16 * 1024;
+
+/// Binary attachment decoded from `data` JSON.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct AttachmentPayload {
+    pub from: String,
+    pub to: String,
+    pub filename: String,
+    pub mime: String,
+    pub data_b64: String,
+    /// Hex SHA256 of the decoded bytes.
+    pub sha256: String,
+}
+
+impl AttachmentPayload {
+    /// Encode `bytes` and record their digest.
+    pub fn new(from: &str, to: &str, filename: &str, mime: &str, bytes: &[u8]) -> Self {
+        Self {
+            from: from.to_string(),
+            to: to.to_string(),
+            filename: filename.to_string(),
+            mime: mime.to_string(),
+            data_b64: general_purpose::STANDARD.encode(bytes),
+            sha256: format!("{:x}", Sha256::digest(bytes)),
+        }
+    }
+
+    /// Decode the blob, failing if it isn't valid base64 or doesn't match `sha256`.
+    pub fn decode(&self) -> anyhow::Result<Vec<u8>> {
+        let bytes = general_purpose::STANDARD.decode(&self.data_b64)?;
+        let digest = format!("{:x}", Sha256::digest(&bytes));
+        if !digest.eq_ignore_ascii_case(&self.sha256) {
+            anyhow::bail!("attachment {} failed integrity check", self.filename);
+        }
+        Ok(bytes)
+    }
+}
+
 impl Block {
     /// General constructor; caller supplies *opaque* `data` string.
     pub fn new(
@@ -77,8 +175,10 @@ impl Block {
             nonce,
             data,
             hash: String::new(),
+            merkle_root: None,
         };
         b.hash = b.calculate_hash();
+        b.merkle_root = b.compute_merkle_root();
         b
     }
 
@@ -138,6 +238,29 @@ impl Block {
         Self::new(index, timestamp_ms, previous_hash, 0, payload.to_string())
     }
 
+    /// Create a block carrying one **attachment payload**.
+    pub fn new_attachment(
+        index: u64,
+        timestamp_ms: u128,
+        previous_hash: String,
+        attachment: &AttachmentPayload,
+    ) -> Self {
+        let payload = serde_json::json!({ "attachment": attachment });
+        Self::new(index, timestamp_ms, previous_hash, 0, payload.to_string())
+    }
+
+    /// Create a **checkpoint genesis** standing in for pruned history.
+    pub fn new_checkpoint(timestamp_ms: u128, checkpoint: &CheckpointPayload) -> Self {
+        let payload = serde_json::json!({ "checkpoint": checkpoint });
+        Self::new(0, timestamp_ms, "0".into(), 0, payload.to_string())
+    }
+
+    /// Genesis block of network `network_id`.
+    pub fn new_genesis(timestamp_ms: u128, network_id: &str) -> Self {
+        let payload = serde_json::json!({ "genesis": GenesisPayload { network_id: network_id.to_string() } });
+        Self::new(0, timestamp_ms, "0".into(), 0, payload.to_string())
+    }
+
     /// Recompute the block hash.
     pub fn calculate_hash(&self) -> String {
         let input = format!(
@@ -149,6 +272,52 @@ impl Block {
         format!("{:x}", hasher.finalize())
     }
 
+    /// Proof-of-work: bump `nonce` until the hash has `difficulty` leading
+    /// zero hex chars. Checks `cancel` between attempts; returns `false` if
+    /// interrupted (the block is then left unmined with a consistent hash).
+    pub fn mine(&mut self, difficulty: usize, cancel: &AtomicBool) -> bool {
+        let target = "0".repeat(difficulty);
+        loop {
+            self.hash = self.calculate_hash();
+            if self.hash.starts_with(&target) {
+                return true;
+            }
+            if cancel.load(Ordering::Relaxed) {
+                return false;
+            }
+            self.nonce = self.nonce.wrapping_add(1);
+        }
+    }
+
+    /// Hash is intact and meets `difficulty`.
+    pub fn verify_pow(&self, difficulty: usize) -> bool {
+        self.hash == self.calculate_hash() && self.hash.bytes().take_while(|c| *c == b'0').count() >= difficulty
+    }
+
+    fn message_leaves(&self) -> Option<Vec<[u8; 32]>> {
+        let msgs = self.as_messages()?;
+        Some(msgs.iter().map(|m| m.digest_bytes()).collect())
+    }
+
+    /// Compute the hex SHA256 Merkle root over the embedded messages.
+    /// `None` for text/direct blocks and empty message arrays.
+    pub fn compute_merkle_root(&self) -> Option<String> {
+        merkle::merkle_root(&self.message_leaves()?)
+    }
+
+    /// Sibling path proving message `index` belongs to this block's root.
+    pub fn merkle_proof(&self, index: usize) -> Option<Vec<MerkleProofStep>> {
+        merkle::merkle_proof(&self.message_leaves()?, index)
+    }
+
+    /// Stored root (if any) still matches the messages in `data`.
+    pub fn merkle_root_matches(&self) -> bool {
+        match &self.merkle_root {
+            Some(root) => self.compute_merkle_root().as_ref() == Some(root),
+            None => true,
+        }
+    }
+
     /// Raw (opaque) payload string.
     pub fn raw_data(&self) -> &str {
         &self.data
@@ -163,6 +332,23 @@ impl Block {
         }
     }
 
+    /// Parse a v0 payload: one legacy message object or an array of them.
+    pub fn as_legacy_messages(&self) -> Option<Vec<LegacyMessageJson>> {
+        if self.data.starts_with('{') {
+            return serde_json::from_str::<LegacyMessageJson>(&self.data).ok().map(|m| vec![m]);
+        }
+        serde_json::from_str::<Vec<LegacyMessageJson>>(&self.data).ok()
+    }
+
+    /// `as_messages`, falling back to v0 messages converted with
+    /// `LegacyMessageJson::into_signed` (unverifiable ones are dropped).
+    pub fn messages_with_legacy(&self) -> Option<Vec<SignedMessage>> {
+        self.as_messages().or_else(|| {
+            let legacy = self.as_legacy_messages()?;
+            Some(legacy.into_iter().filter_map(LegacyMessageJson::into_signed).collect())
+        })
+    }
+
     /// Parse messages *and* verify signatures. Returns only verified messages.
     /// If parsing fails, returns empty vec.
     pub fn verified_messages(&self) -> Vec<SignedMessage> {
@@ -173,6 +359,43 @@ impl Block {
             .collect()
     }
 
+    /// Parse a checkpoint genesis payload.
+    pub fn as_checkpoint(&self) -> Option<CheckpointPayload> {
+        #[derive(Deserialize)]
+        struct Wrapper {
+            checkpoint: CheckpointPayload,
+        }
+        if !self.data.starts_with("{\"checkpoint\"") {
+            return None;
+        }
+        serde_json::from_str::<Wrapper>(&self.data).ok().map(|w| w.checkpoint)
+    }
+
+    /// Parse a network genesis payload.
+    pub fn as_genesis(&self) -> Option<GenesisPayload> {
+        #[derive(Deserialize)]
+        struct Wrapper {
+            genesis: GenesisPayload,
+        }
+        if !self.data.starts_with("{\"genesis\"") {
+            return None;
+        }
+        serde_json::from_str::<Wrapper>(&self.data).ok().map(|w| w.genesis)
+    }
+
+    /// Parse an attachment payload (not integrity-checked; see
+    /// [`AttachmentPayload::decode`]).
+    pub fn as_attachment(&self) -> Option<AttachmentPayload> {
+        #[derive(Deserialize)]
+        struct Wrapper {
+            attachment: AttachmentPayload,
+        }
+        if !self.data.starts_with("{\"attachment\"") {
+            return None;
+        }
+        serde_json::from_str::<Wrapper>(&self.data).ok().map(|w| w.attachment)
+    }
+
     /// Attempt to parse **direct text** payload JSON.
     ///
     /// Handles both current structured JSON and a legacy inline encoding used
@@ -217,11 +440,13 @@ impl fmt::Display for Block {
         let preview = if let Some(msgs) = self.as_messages() {
             format!("{} msgs", msgs.len())
         } else if let Some(dt) = self.as_direct_text() {
-            format!("direct {}→{}: {}", &dt.from[..dt.from.len().min(6)], &dt.to[..dt.to.len().min(6)], dt.text)
+            format!("direct {}→{}: {}", truncate_chars(&dt.from, 6), truncate_chars(&dt.to, 6), dt.text)
+        } else if let Some(att) = self.as_attachment() {
+            format!("📎 {} ({})", att.filename, att.mime)
         } else {
             let d = self.data.replace('\n', " ");
-            if d.len() > 32 {
-                format!("{}...", &d[..32])
+            if d.chars().count() > 32 {
+                format!("{}...", truncate_chars(&d, 32))
             } else {
                 d
             }
@@ -232,9 +457,6 @@ impl fmt::Display for Block {
 
 /// Utility: current system timestamp (ms).
 pub fn current_timestamp_ms() -> u128 {
-    use std::time::{SystemTime, UNIX_EPOCH};
-    SystemTime::now()
-        .duration_since(UNIX_EPOCH)
-        .map(|d| d.as_millis())
-        .unwrap_or_default()
+    use crate::clock::{Clock, SystemClock};
+    SystemClock.now_ms()
 }
@@ -1,38 +1,178 @@
 //! Minimal append‑only blockchain for WiChain.
 //!
-//! - Genesis block created automatically.
+//! - Genesis block created automatically. Chains on different networks
+//!   (`new_with_genesis`) have different genesis blocks, and blocks from
+//!   another network are never merged (`ensure_same_network`).
 //! - Blocks store opaque `data: String` but helpers allow:
 //!   - Legacy text (`add_text_block`)
 //!   - Structured signed message arrays (`add_message_block`, `add_messages_block`)
 //!   - Direct peer‑to‑peer text payload (`add_direct_text_block`)
+//!   - Binary attachments (`add_attachment_block`)
+//!
+//! `save_to_file_encrypted()` / `load_from_file_encrypted()` keep the whole
+//! file AES-256-GCM sealed (`nonce || ciphertext`) so block structure,
+//! timestamps and pubkeys aren't readable at rest.
+//!
+//! `append_block_to_file()` / `load_from_appendlog()` persist incrementally as
+//! newline-delimited JSON (one block per line) instead of rewriting the file.
+//!
+//! `prune_before()` compacts old history into a checkpoint genesis.
+//!
+//! Blocks are stamped by the chain's `Clock` (see `new_with_clock`).
+//!
+//! `queue_message()` batches signed messages instead of giving each its own
+//! block: they are committed together by `flush_pending()`, once the
+//! `BatchPolicy` count is reached, or by `flush_due()` once the oldest has
+//! waited out its window. Appending any other block, or saving the chain,
+//! commits the queue first, so blocks stay in arrival order and nothing
+//! queued is lost on save; flush before shutting down without one.
 //!
 //! Validation checks hash links; `validate_deep()` optionally re‑verifies
-//! embedded `SignedMessage`s.
+//! embedded `SignedMessage`s and each block's stored Merkle root
+//! (`validate_deep_parallel()` does the same across a rayon pool).
+//! `validate_deep_with()` also counts signed payloads only the caller can
+//! parse, such as the backend's stored chats.
+
+use aes_gcm::aead::{Aead, KeyInit};
+use aes_gcm::{Aes256Gcm, Nonce};
+use rand::RngCore;
+use rayon::prelude::*;
 
-use crate::block::{current_timestamp_ms, Block, DirectTextPayload};
+use crate::block::{AttachmentPayload, Block, CheckpointPayload, DirectTextPayload};
+use crate::clock::{ChainClock, Clock};
 use serde::{Deserialize, Serialize};
 use std::fs::{self, File};
-use std::io::{BufReader, Write};
+use std::io::{BufRead, BufReader, Write};
 use std::path::Path;
+use std::sync::atomic::AtomicBool;
+
+use wichain_core::{truncate_chars, SignedMessage};
 
-use wichain_core::SignedMessage;
+/// Network of chains made with `Blockchain::new` (genesis `"Genesis Block"`).
+pub const DEFAULT_NETWORK_ID: &str = "default";
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Blockchain {
     pub chain: Vec<Block>,
+    /// Messages waiting for a block (see `queue_message`); committed before
+    /// any other append or save, never saved as a queue.
+    #[serde(skip)]
+    batch: Batch,
+    #[serde(skip)]
+    clock: ChainClock,
+    /// How much of the chain is known to be in timestamp order (see `blocks_in_range`).
+    #[serde(skip)]
+    order: TimeOrder,
+}
+
+/// When queued messages are committed as one block.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct BatchPolicy {
+    /// Commit as soon as this many are queued.
+    pub max_messages: usize,
+    /// Commit once the oldest has been queued this long (see `flush_due`).
+    pub max_delay_ms: u64,
+}
+
+impl Default for BatchPolicy {
+    fn default() -> Self {
+        Self { max_messages: 32, max_delay_ms: 200 }
+    }
+}
+
+/// Timestamp order of the blocks after genesis, checked once per block as
+/// it is appended or loaded rather than on every range query.
+#[derive(Debug, Clone, Copy, Default)]
+struct TimeOrder {
+    /// Leading blocks (genesis excluded) checked so far.
+    checked: usize,
+    /// One of them is older than the block before it.
+    broken: bool,
+}
+
+impl TimeOrder {
+    /// `self` extended over `body` (the chain after genesis). Blocks pushed
+    /// straight onto `chain` are picked up here too; if it shrank, it is
+    /// checked afresh.
+    fn extended(self, body: &[Block]) -> Self {
+        if self.checked > body.len() {
+            return Self::default().extended(body);
+        }
+        if self.broken {
+            return Self { checked: body.len(), ..self };
+        }
+        let fresh = &body[self.checked.saturating_sub(1)..];
+        Self { checked: body.len(), broken: !fresh.is_sorted_by_key(|b| b.timestamp_ms) }
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+struct Batch {
+    policy: BatchPolicy,
+    pending: Vec<SignedMessage>,
+    /// When the oldest pending message was queued.
+    since_ms: Option<u128>,
 }
 
 impl Blockchain {
-    /// Create a new chain w/ genesis block.
+    /// Create a new chain w/ genesis block, on the default network.
     pub fn new() -> Self {
-        let mut bc = Self { chain: Vec::new() };
-        bc.push_genesis();
-        bc
+        Self::new_with_genesis(DEFAULT_NETWORK_ID)
     }
 
-    fn push_genesis(&mut self) {
-        let genesis = Block::new_text(0, current_timestamp_ms(), "0".into(), "Genesis Block");
-        self.chain.push(genesis);
+    /// Create a new chain whose genesis embeds `network_id`, so chains of
+    /// independent networks never share a genesis hash.
+    pub fn new_with_genesis(network_id: &str) -> Self {
+        Self::new_with_clock(network_id, ChainClock::default().0)
+    }
+
+    /// `new_with_genesis`, with genesis and every later block stamped by
+    /// `clock` instead of the system clock.
+    pub fn new_with_clock(network_id: &str, clock: std::sync::Arc<dyn Clock>) -> Self {
+        let now = clock.now_ms();
+        let genesis = if network_id == DEFAULT_NETWORK_ID {
+            Block::new_text(0, now, "0".into(), "Genesis Block")
+        } else {
+            Block::new_genesis(now, network_id)
+        };
+        Self { chain: vec![genesis], batch: Batch::default(), clock: ChainClock(clock), order: TimeOrder::default() }
+    }
+
+    /// Current time by this chain's clock (ms).
+    pub fn now_ms(&self) -> u128 {
+        self.clock.0.now_ms()
+    }
+
+    /// Network this chain belongs to, from its genesis (or the checkpoint
+    /// that replaced it). Chains from before network ids are on the default one.
+    pub fn network_id(&self) -> String {
+        let genesis = &self.chain[0];
+        genesis
+            .as_genesis()
+            .map(|g| g.network_id)
+            .or_else(|| genesis.as_checkpoint()?.network_id)
+            .unwrap_or_else(|| DEFAULT_NETWORK_ID.to_string())
+    }
+
+    /// Refuse blocks from a chain on another network.
+    pub fn ensure_same_network(&self, network_id: &str) -> anyhow::Result<()> {
+        let ours = self.network_id();
+        if ours != network_id {
+            anyhow::bail!("chain is on network {network_id:?}, not {ours:?}");
+        }
+        Ok(())
+    }
+
+    /// Append `b` and note whether it kept the chain in timestamp order.
+    fn push_block(&mut self, b: Block) -> &Block {
+        self.chain.push(b);
+        self.track_order();
+        self.chain.last().unwrap()
+    }
+
+    /// Bring the cached timestamp order up to date with `chain`.
+    fn track_order(&mut self) {
+        self.order = self.order.extended(self.chain.get(1..).unwrap_or_default());
     }
 
     /// The last block (safe; there is always at least genesis).
@@ -40,17 +180,58 @@ impl Blockchain {
         self.chain.last().expect("chain always has genesis")
     }
 
+    /// Mine `data` into a new block at the tip and append it.
+    /// Returns `None` (chain unchanged) if `cancel` was raised mid-way.
+    pub fn add_mined_block(&mut self, data: String, difficulty: usize, cancel: &AtomicBool) -> Option<&Block> {
+        self.flush_pending();
+        let prev = self.last_block();
+        let mut b = Block::new(
+            self.chain.len() as u64,
+            self.now_ms(),
+            prev.hash.clone(),
+            0,
+            data,
+        );
+        if !b.mine(difficulty, cancel) {
+            return None;
+        }
+        Some(self.push_block(b))
+    }
+
+    /// Append a **text block** mined to `difficulty` leading zero hex chars.
+    pub fn add_text_block_mined(&mut self, text: impl Into<String>, difficulty: usize) -> &Block {
+        self.add_mined_block(text.into(), difficulty, &AtomicBool::new(false))
+            .expect("uncancelled mining completes")
+    }
+
+    /// Append a **signed messages** block mined to `difficulty`.
+    pub fn add_messages_block_mined(&mut self, messages: Vec<SignedMessage>, difficulty: usize) -> &Block {
+        let data = serde_json::to_string(&messages).unwrap_or_else(|_| "[]".to_string());
+        self.add_mined_block(data, difficulty, &AtomicBool::new(false))
+            .expect("uncancelled mining completes")
+    }
+
     /// Append a **text block** (legacy).
     pub fn add_text_block(&mut self, text: impl Into<String>) -> &Block {
+        self.flush_pending();
         let prev = self.last_block();
         let b = Block::new_text(
             self.chain.len() as u64,
-            current_timestamp_ms(),
+            self.now_ms(),
             prev.hash.clone(),
             text,
         );
-        self.chain.push(b);
-        self.chain.last().unwrap()
+        self.push_block(b)
+    }
+
+    /// Append a **text block**, failing (chain unchanged) if `text` is larger
+    /// than `max_bytes`; `DEFAULT_MAX_MESSAGE_BYTES` is a sane cap.
+    pub fn try_add_text_block(&mut self, text: impl Into<String>, max_bytes: usize) -> anyhow::Result<&Block> {
+        let text = text.into();
+        if text.len() > max_bytes {
+            anyhow::bail!("message is {} bytes; limit is {max_bytes}", text.len());
+        }
+        Ok(self.add_text_block(text))
     }
 
     /// Append a block containing **one signed message**.
@@ -60,15 +241,66 @@ impl Blockchain {
 
     /// Append a block containing **many signed messages**.
     pub fn add_messages_block(&mut self, messages: Vec<SignedMessage>) -> &Block {
+        self.flush_pending();
+        self.push_messages_block(messages)
+    }
+
+    fn push_messages_block(&mut self, messages: Vec<SignedMessage>) -> &Block {
         let prev = self.last_block();
         let b = Block::new_messages(
             self.chain.len() as u64,
-            current_timestamp_ms(),
+            self.now_ms(),
             prev.hash.clone(),
             &messages,
         );
-        self.chain.push(b);
-        self.chain.last().unwrap()
+        self.push_block(b)
+    }
+
+    /// How `queue_message` batches.
+    pub fn batch_policy(&self) -> BatchPolicy {
+        self.batch.policy
+    }
+
+    /// Takes effect from the next `queue_message` / `flush_due`.
+    pub fn set_batch_policy(&mut self, policy: BatchPolicy) {
+        self.batch.policy = policy;
+    }
+
+    /// Queue `msg` for the next messages block. Returns the block if this
+    /// message filled the batch and it was committed.
+    pub fn queue_message(&mut self, msg: SignedMessage) -> Option<&Block> {
+        let now = self.now_ms();
+        self.batch.since_ms.get_or_insert(now);
+        self.batch.pending.push(msg);
+        if self.batch.pending.len() >= self.batch.policy.max_messages.max(1) {
+            return self.flush_pending();
+        }
+        None
+    }
+
+    /// Messages queued and not yet in a block.
+    pub fn pending_len(&self) -> usize {
+        self.batch.pending.len()
+    }
+
+    /// Commit the queue if its oldest message has waited `max_delay_ms` by
+    /// `now_ms`. Callers tick this on a timer.
+    pub fn flush_due(&mut self, now_ms: u128) -> Option<&Block> {
+        let since = self.batch.since_ms?;
+        if now_ms.saturating_sub(since) < u128::from(self.batch.policy.max_delay_ms) {
+            return None;
+        }
+        self.flush_pending()
+    }
+
+    /// Commit every queued message as one block (`None` if none are queued).
+    pub fn flush_pending(&mut self) -> Option<&Block> {
+        self.batch.since_ms = None;
+        if self.batch.pending.is_empty() {
+            return None;
+        }
+        let messages = std::mem::take(&mut self.batch.pending);
+        Some(self.push_messages_block(messages))
     }
 
     /// NEW: append a **direct peer‑to‑peer text** block.
@@ -77,17 +309,42 @@ impl Blockchain {
     /// in the network layer). This is what you'll call when the user selects a
     /// peer in the UI and sends a message.
     pub fn add_direct_text_block(&mut self, from: &str, to: &str, text: &str) -> &Block {
+        self.flush_pending();
         let prev = self.last_block();
         let b = Block::new_direct(
             self.chain.len() as u64,
-            current_timestamp_ms(),
+            self.now_ms(),
             prev.hash.clone(),
             from,
             to,
             text,
         );
-        self.chain.push(b);
-        self.chain.last().unwrap()
+        self.push_block(b)
+    }
+
+    /// Append an **attachment** block. Fails (chain unchanged) if `bytes` is
+    /// larger than `max_bytes`; `DEFAULT_MAX_ATTACHMENT_BYTES` is a sane cap.
+    pub fn add_attachment_block(
+        &mut self,
+        from: &str,
+        to: &str,
+        filename: &str,
+        mime: &str,
+        bytes: &[u8],
+        max_bytes: usize,
+    ) -> anyhow::Result<&Block> {
+        if bytes.len() > max_bytes {
+            anyhow::bail!("attachment is {} bytes; limit is {max_bytes}", bytes.len());
+        }
+        self.flush_pending();
+        let prev = self.last_block();
+        let b = Block::new_attachment(
+            self.chain.len() as u64,
+            self.now_ms(),
+            prev.hash.clone(),
+            &AttachmentPayload::new(from, to, filename, mime, bytes),
+        );
+        Ok(self.push_block(b))
     }
 
     /// Helper used when *receiving* a direct message from a peer (identical to `add_direct_text_block` but kept for intent).
@@ -95,33 +352,175 @@ impl Blockchain {
         self.add_direct_text_block(from, to, text)
     }
 
+    /// Rewrite blocks holding v0 messages into the `SignedMessage` array form
+    /// (which also gives them a Merkle root). A block is only rewritten if
+    /// every message in it verifies, so nothing is lost.
+    ///
+    /// Changing `data` changes the block's hash, so the migrated blocks and
+    /// everything after them are re-linked (any proof-of-work on them is
+    /// lost); blocks before the first migrated one keep their hashes.
+    /// Returns the number of blocks rewritten.
+    pub fn migrate_legacy(&mut self) -> usize {
+        let mut first = None;
+        let mut migrated = 0;
+        for (i, b) in self.chain.iter_mut().enumerate() {
+            if b.as_messages().is_some() {
+                continue;
+            }
+            let Some(legacy) = b.as_legacy_messages() else { continue };
+            let count = legacy.len();
+            let msgs: Vec<SignedMessage> = legacy.into_iter().filter_map(|m| m.into_signed()).collect();
+            if count == 0 || msgs.len() != count {
+                continue;
+            }
+            b.data = serde_json::to_string(&msgs).unwrap_or_else(|_| "[]".to_string());
+            b.merkle_root = b.compute_merkle_root();
+            first.get_or_insert(i);
+            migrated += 1;
+        }
+        if let Some(first) = first {
+            self.relink_from(first);
+        }
+        migrated
+    }
+
+    /// Recompute the hash of every block from `first` on, re-linking each to
+    /// its predecessor. For callers that rewrote `data` in place; any
+    /// proof-of-work on those blocks is lost.
+    pub fn relink_from(&mut self, first: usize) {
+        for i in first..self.chain.len() {
+            if i > 0 {
+                self.chain[i].previous_hash = self.chain[i - 1].hash.clone();
+            }
+            self.chain[i].hash = self.chain[i].calculate_hash();
+        }
+    }
+
+    /// Drop every block older than `cutoff_ms`, replacing genesis and the
+    /// pruned prefix with a checkpoint genesis that records the pruned tip.
+    ///
+    /// Survivors are re-indexed and re-linked (their hashes change, so any
+    /// proof-of-work on them is lost). Refuses if an older-than-cutoff block
+    /// sits after a newer one, since pruning it would tear the chain apart.
+    /// Returns the number of blocks removed (genesis/checkpoint not counted).
+    pub fn prune_before(&mut self, cutoff_ms: u128) -> anyhow::Result<usize> {
+        let prunable = self
+            .chain
+            .iter()
+            .skip(1)
+            .take_while(|b| b.timestamp_ms < cutoff_ms)
+            .count();
+        if let Some(b) = self.chain[1 + prunable..].iter().find(|b| b.timestamp_ms < cutoff_ms) {
+            anyhow::bail!(
+                "block #{} ({} ms) is older than cutoff but follows newer blocks",
+                b.index,
+                b.timestamp_ms
+            );
+        }
+        if prunable == 0 {
+            return Ok(0);
+        }
+
+        let already_pruned = self.chain[0].as_checkpoint().map(|c| c.pruned_blocks).unwrap_or(0);
+        let checkpoint = Block::new_checkpoint(
+            self.now_ms(),
+            &CheckpointPayload {
+                pruned_tip_hash: self.chain[prunable].hash.clone(),
+                pruned_blocks: already_pruned + prunable as u64,
+                cutoff_ms,
+                network_id: Some(self.network_id()).filter(|id| id != DEFAULT_NETWORK_ID),
+            },
+        );
+
+        let survivors = self.chain.split_off(1 + prunable);
+        self.chain = vec![checkpoint];
+        for mut b in survivors {
+            b.index = self.chain.len() as u64;
+            b.previous_hash = self.last_block().hash.clone();
+            b.hash = b.calculate_hash();
+            self.chain.push(b);
+        }
+        self.order = TimeOrder::default();
+        self.track_order();
+        Ok(prunable)
+    }
+
     /// Basic integrity check: ensure hash chain is unbroken and hashes recompute.
     pub fn is_valid(&self) -> bool {
+        self.first_invalid_block().is_none()
+    }
+
+    /// Position of the first block that breaks `is_valid`: its hash doesn't
+    /// recompute or it doesn't link to its predecessor (0 for an empty chain).
+    pub fn first_invalid_block(&self) -> Option<usize> {
         if self.chain.is_empty() {
-            return false;
+            return Some(0);
         }
-        for i in 1..self.chain.len() {
+        (1..self.chain.len()).find(|&i| {
             let curr = &self.chain[i];
-            let prev = &self.chain[i - 1];
-            if curr.previous_hash != prev.hash {
-                return false;
-            }
-            if curr.hash != curr.calculate_hash() {
-                return false;
+            curr.previous_hash != self.chain[i - 1].hash || curr.hash != curr.calculate_hash()
+        })
+    }
+
+    /// Position of the first block with a wrong Merkle root or an embedded
+    /// signed message that doesn't verify (what `validate_deep` adds).
+    pub fn first_unverified_block(&self) -> Option<usize> {
+        self.chain.iter().position(|b| {
+            !b.merkle_root_matches() || b.as_messages().is_some_and(|msgs| !msgs.iter().all(|m| m.verify()))
+        })
+    }
+
+    /// A run of blocks copied from another node's chain: indices consecutive,
+    /// each `previous_hash` the prior block's `hash`, and every block's hash
+    /// recomputing. Blocks with empty `data` are redacted headers (content
+    /// withheld by the sender), so only their link is checked.
+    pub fn is_valid_segment(blocks: &[Block]) -> bool {
+        let links = blocks
+            .windows(2)
+            .all(|w| w[1].index == w[0].index + 1 && w[1].previous_hash == w[0].hash);
+        links && blocks.iter().all(|b| b.data.is_empty() || b.hash == b.calculate_hash())
+    }
+
+    /// Timestamps never go backwards. Genesis is exempt (a checkpoint genesis
+    /// is newer than the blocks it precedes). `Err` = index of the first
+    /// block older than its predecessor.
+    pub fn validate_timestamps(&self) -> Result<(), usize> {
+        for i in 2..self.chain.len() {
+            if self.chain[i].timestamp_ms < self.chain[i - 1].timestamp_ms {
+                return Err(i);
             }
         }
-        true
+        Ok(())
+    }
+
+    /// Hash links, embedded signatures/Merkle roots and timestamp order.
+    pub fn validate_strict(&self) -> bool {
+        self.validate_deep().0 && self.validate_timestamps().is_ok()
+    }
+
+    /// `is_valid` plus proof-of-work: every block after genesis meets `difficulty`.
+    pub fn is_valid_with_pow(&self, difficulty: usize) -> bool {
+        self.is_valid() && self.chain.iter().skip(1).all(|b| b.verify_pow(difficulty))
     }
 
-    /// Deep validation: also parse/verify embedded signed messages.
+    /// Deep validation: also parse/verify embedded signed messages and Merkle roots.
     /// Returns `(is_valid_chain, total_msgs, bad_msgs)`.
     pub fn validate_deep(&self) -> (bool, usize, usize) {
+        self.validate_deep_with(|_| None)
+    }
+
+    /// [`validate_deep`](Self::validate_deep), also counting blocks that hold
+    /// one signed payload `as_messages` can't parse: `verify_other` returns
+    /// `Some(signature_ok)` for such a block and `None` for anything else.
+    pub fn validate_deep_with(&self, verify_other: impl Fn(&Block) -> Option<bool>) -> (bool, usize, usize) {
         if !self.is_valid() {
             return (false, 0, 0);
         }
         let mut total = 0;
         let mut bad = 0;
+        let mut roots_ok = true;
         for b in &self.chain {
+            roots_ok &= b.merkle_root_matches();
             if let Some(msgs) = b.as_messages() {
                 for m in msgs {
                     total += 1;
@@ -129,13 +528,52 @@ impl Blockchain {
                         bad += 1;
                     }
                 }
+            } else if let Some(ok) = verify_other(b) {
+                total += 1;
+                if !ok {
+                    bad += 1;
+                }
             }
         }
-        (bad == 0, total, bad)
+        (bad == 0 && roots_ok, total, bad)
     }
 
-    /// Save the chain to JSON.
-    pub fn save_to_file(&self, path: impl AsRef<Path>) -> anyhow::Result<()> {
+    /// [`validate_deep`](Self::validate_deep) with blocks checked on rayon's
+    /// pool. Per-block counts are summed, so the result doesn't depend on the
+    /// number of threads or scheduling.
+    pub fn validate_deep_parallel(&self) -> (bool, usize, usize) {
+        self.validate_deep_parallel_with(|_| None)
+    }
+
+    /// [`validate_deep_with`](Self::validate_deep_with) on rayon's pool.
+    pub fn validate_deep_parallel_with(
+        &self,
+        verify_other: impl Fn(&Block) -> Option<bool> + Sync,
+    ) -> (bool, usize, usize) {
+        if !self.is_valid() {
+            return (false, 0, 0);
+        }
+        let (roots_ok, total, bad) = self
+            .chain
+            .par_iter()
+            .map(|b| {
+                let Some(msgs) = b.as_messages() else {
+                    let (total, bad) = match verify_other(b) {
+                        Some(ok) => (1, usize::from(!ok)),
+                        None => (0, 0),
+                    };
+                    return (b.merkle_root_matches(), total, bad);
+                };
+                let bad = msgs.par_iter().filter(|m| !m.verify()).count();
+                (b.merkle_root_matches(), msgs.len(), bad)
+            })
+            .reduce(|| (true, 0, 0), |a, b| (a.0 && b.0, a.1 + b.1, a.2 + b.2));
+        (bad == 0 && roots_ok, total, bad)
+    }
+
+    /// Save the chain to JSON (committing queued messages first).
+    pub fn save_to_file(&mut self, path: impl AsRef<Path>) -> anyhow::Result<()> {
+        self.flush_pending();
         let path = path.as_ref();
         if let Some(parent) = path.parent() {
             fs::create_dir_all(parent)?;
@@ -154,23 +592,158 @@ impl Blockchain {
         }
         let f = File::open(path)?;
         let r = BufReader::new(f);
-        let bc: Self = serde_json::from_reader(r)?;
+        let mut bc: Self = serde_json::from_reader(r)?;
+        bc.track_order();
+        Ok(bc)
+    }
+
+    /// `load_from_file`, refusing a chain that fails `is_valid` (and, with
+    /// `deep`, `validate_deep`'s Merkle and signature checks). The error
+    /// names the first broken block.
+    pub fn load_verified(path: impl AsRef<Path>, deep: bool) -> anyhow::Result<Self> {
+        let path = path.as_ref();
+        let bc = Self::load_from_file(path)?;
+        if let Some(i) = bc.first_invalid_block() {
+            anyhow::bail!("{}: block at position {i} has a bad hash or link", path.display());
+        }
+        let unverified = if deep { bc.first_unverified_block() } else { None };
+        if let Some(i) = unverified {
+            anyhow::bail!("{}: block at position {i} has a bad Merkle root or signature", path.display());
+        }
+        Ok(bc)
+    }
+
+    /// Append one block as a JSON line to an append-only log (O(1) per block).
+    /// A log must start with the chain's genesis, so append every block,
+    /// genesis included.
+    pub fn append_block_to_file(path: impl AsRef<Path>, block: &Block) -> anyhow::Result<()> {
+        let path = path.as_ref();
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        let mut line = serde_json::to_string(block)?;
+        line.push('\n');
+        let mut f = fs::OpenOptions::new().create(true).append(true).open(path)?;
+        f.write_all(line.as_bytes())?;
+        Ok(())
+    }
+
+    /// Rebuild a chain from an append log, checking hashes and links while
+    /// streaming. If file missing (or empty), create new chain.
+    pub fn load_from_appendlog(path: impl AsRef<Path>) -> anyhow::Result<Self> {
+        let path = path.as_ref();
+        if !path.exists() {
+            return Ok(Self::new());
+        }
+        let r = BufReader::new(File::open(path)?);
+        let mut chain: Vec<Block> = Vec::new();
+        for (n, line) in r.lines().enumerate() {
+            let line = line?;
+            if line.trim().is_empty() {
+                continue;
+            }
+            let b: Block = serde_json::from_str(&line)
+                .map_err(|e| anyhow::anyhow!("append log line {}: {e}", n + 1))?;
+            if b.hash != b.calculate_hash() {
+                anyhow::bail!("append log line {}: block #{} hash mismatch", n + 1, b.index);
+            }
+            if let Some(prev) = chain.last()
+                && b.previous_hash != prev.hash
+            {
+                anyhow::bail!("append log line {}: block #{} not linked to #{}", n + 1, b.index, prev.index);
+            }
+            chain.push(b);
+        }
+        if chain.is_empty() {
+            return Ok(Self::new());
+        }
+        let mut bc = Self { chain, batch: Batch::default(), clock: ChainClock::default(), order: TimeOrder::default() };
+        bc.track_order();
         Ok(bc)
     }
 
-    /// Return a vector of all **verified** signed messages in the chain.
+    /// Save the chain AES-256-GCM encrypted under `key`; file = 12-byte random nonce || ciphertext.
+    /// Queued messages are committed first.
+    pub fn save_to_file_encrypted(&mut self, path: impl AsRef<Path>, key: &[u8; 32]) -> anyhow::Result<()> {
+        self.flush_pending();
+        let path = path.as_ref();
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        let json = serde_json::to_vec(self)?;
+        let mut nonce = [0u8; 12];
+        rand::rngs::OsRng.fill_bytes(&mut nonce);
+        let cipher = Aes256Gcm::new(key.into());
+        let sealed = cipher
+            .encrypt(Nonce::from_slice(&nonce), json.as_slice())
+            .map_err(|_| anyhow::anyhow!("chain encryption failed"))?;
+        let mut f = File::create(path)?;
+        f.write_all(&nonce)?;
+        f.write_all(&sealed)?;
+        Ok(())
+    }
+
+    /// Load a chain written by `save_to_file_encrypted`. If file missing,
+    /// create new chain. A wrong key or tampered file is an error (GCM tag).
+    pub fn load_from_file_encrypted(path: impl AsRef<Path>, key: &[u8; 32]) -> anyhow::Result<Self> {
+        let path = path.as_ref();
+        if !path.exists() {
+            return Ok(Self::new());
+        }
+        let raw = fs::read(path)?;
+        if raw.len() < 12 {
+            anyhow::bail!("encrypted chain file truncated");
+        }
+        let (nonce, sealed) = raw.split_at(12);
+        let cipher = Aes256Gcm::new(key.into());
+        let json = cipher
+            .decrypt(Nonce::from_slice(nonce), sealed)
+            .map_err(|_| anyhow::anyhow!("chain decryption failed (wrong key or corrupted file)"))?;
+        let mut bc: Self = serde_json::from_slice(&json)?;
+        bc.track_order();
+        Ok(bc)
+    }
+
+    /// Return a vector of all **verified** signed messages in the chain,
+    /// including converted v0 messages.
     pub fn all_verified_messages(&self) -> Vec<SignedMessage> {
         self.chain
             .iter()
-            .flat_map(|b| b.verified_messages())
+            .filter_map(|b| b.messages_with_legacy())
+            .flatten()
+            .filter(|m| m.verify())
+            .collect()
+    }
+
+    /// Verified messages (v0 ones included) that haven't expired as of `now_ms`.
+    pub fn all_live_messages(&self, now_ms: u64) -> Vec<SignedMessage> {
+        self.chain
+            .iter()
+            .filter_map(|b| b.messages_with_legacy())
+            .flatten()
+            .filter(|m| m.verify() && !m.is_expired(now_ms))
             .collect()
     }
 
-    /// Return a vector of *all* parsed signed messages (no verify).
+    /// Verified message `msg_id` plus its direct replies, by timestamp. If the
+    /// parent isn't in the local chain only the replies are returned.
+    pub fn thread_of(&self, msg_id: &str) -> Vec<SignedMessage> {
+        let mut thread: Vec<SignedMessage> = self
+            .all_verified_messages()
+            .into_iter()
+            .filter(|m| m.id == msg_id || m.reply_to.as_deref() == Some(msg_id))
+            .collect();
+        thread.sort_by_key(|m| m.timestamp_ms);
+        thread
+    }
+
+    /// Return a vector of *all* parsed signed messages (no verify). v0
+    /// messages are included only if their signature checks out, since
+    /// that's what converting them requires.
     pub fn all_messages(&self) -> Vec<SignedMessage> {
         self.chain
             .iter()
-            .filter_map(|b| b.as_messages())
+            .filter_map(|b| b.messages_with_legacy())
             .flatten()
             .collect()
     }
@@ -182,24 +755,101 @@ impl Blockchain {
             .filter_map(|b| b.as_direct_text())
             .collect()
     }
+
+    /// Blocks with `start_ms <= timestamp_ms <= end_ms`, in chain order.
+    ///
+    /// Blocks are appended in time order, so this binary-searches. Binary
+    /// search can't see disorder on its own, so each block's timestamp is
+    /// checked against the one before it once, when it is appended or loaded;
+    /// if they are out of order (foreign clocks, hand-edited files) this falls
+    /// back to a linear filter. Genesis is checked on its own since a
+    /// checkpoint genesis is newer than what follows it.
+    pub fn blocks_in_range(&self, start_ms: u128, end_ms: u128) -> Vec<&Block> {
+        let Some((genesis, body)) = self.chain.split_first() else {
+            return Vec::new();
+        };
+        if start_ms > end_ms {
+            return Vec::new();
+        }
+        let in_range = |b: &&Block| b.timestamp_ms >= start_ms && b.timestamp_ms <= end_ms;
+
+        let mut out: Vec<&Block> = Some(genesis).filter(in_range).into_iter().collect();
+        if !self.order.extended(body).broken {
+            let lo = body.partition_point(|b| b.timestamp_ms < start_ms);
+            let hi = body.partition_point(|b| b.timestamp_ms <= end_ms);
+            out.extend(&body[lo..hi]);
+        } else {
+            out.extend(body.iter().filter(in_range));
+        }
+        out
+    }
+
+    /// Signed messages sent by `pubkey_b64` (unverified; see `all_verified_messages`).
+    pub fn messages_from(&self, pubkey_b64: &str) -> Vec<SignedMessage> {
+        self.chain
+            .iter()
+            .filter_map(|b| b.messages_with_legacy())
+            .flatten()
+            .filter(|m| m.from == pubkey_b64)
+            .collect()
+    }
+
+    /// Direct text messages sent by `pubkey_b64`.
+    pub fn direct_text_from(&self, pubkey_b64: &str) -> Vec<DirectTextPayload> {
+        self.chain
+            .iter()
+            .filter_map(|b| b.as_direct_text())
+            .filter(|d| d.from == pubkey_b64)
+            .collect()
+    }
 }
 
 /* ------------------------------------------------------------------------- */
 /* UI Summaries                                                              */
 /* ------------------------------------------------------------------------- */
 
+/// What a block carries, so the UI can pick an icon without parsing `data`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum BlockKind {
+    /// Free text (legacy text blocks, which is also how the app stores chats).
+    Text,
+    /// A JSON array of signed messages.
+    Messages,
+    Direct,
+    Attachment,
+    /// Genesis or checkpoint, or a payload envelope that doesn't parse.
+    #[default]
+    Unknown,
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct BlockSummary {
     pub index: u64,
     pub timestamp_ms: u128,
     pub hash: String,
     pub previous_hash: String,
+    #[serde(default)]
+    pub kind: BlockKind,
     pub message_count: usize,
     pub preview: String,
 }
 
+/// Prefixes of the JSON envelopes `Block`'s constructors write.
+const ENVELOPES: [&str; 4] = ["{\"direct\"", "{\"attachment\"", "{\"checkpoint\"", "{\"genesis\""];
+
 impl BlockSummary {
     pub fn from_block(b: &Block, preview_len: usize) -> Self {
+        let summary = |kind, message_count, preview| Self {
+            index: b.index,
+            timestamp_ms: b.timestamp_ms,
+            hash: b.hash.clone(),
+            previous_hash: b.previous_hash.clone(),
+            kind,
+            message_count,
+            preview,
+        };
+
         // message array?
         if let Some(msgs) = b.as_messages() {
             let count = msgs.len();
@@ -208,67 +858,68 @@ impl BlockSummary {
             } else {
                 format!("{count} messages")
             };
-            return Self {
-                index: b.index,
-                timestamp_ms: b.timestamp_ms,
-                hash: b.hash.clone(),
-                previous_hash: b.previous_hash.clone(),
-                message_count: count,
-                preview,
-            };
+            return summary(BlockKind::Messages, count, preview);
         }
 
         // direct?
         if let Some(dt) = b.as_direct_text() {
-            return Self {
-                index: b.index,
-                timestamp_ms: b.timestamp_ms,
-                hash: b.hash.clone(),
-                previous_hash: b.previous_hash.clone(),
-                message_count: 1,
-                preview: dt.text,
-            };
+            return summary(BlockKind::Direct, 1, dt.text);
+        }
+
+        // attachment?
+        if let Some(att) = b.as_attachment() {
+            return summary(BlockKind::Attachment, 1, format!("📎 {} ({})", att.filename, att.mime));
         }
 
         // raw text fallback
         let raw = b.raw_data();
-        let preview = if raw.len() > preview_len {
-            format!("{}...", &raw[..preview_len])
+        let preview = if raw.chars().count() > preview_len {
+            format!("{}...", truncate_chars(raw, preview_len))
         } else {
             raw.to_string()
         };
-        Self {
-            index: b.index,
-            timestamp_ms: b.timestamp_ms,
-            hash: b.hash.clone(),
-            previous_hash: b.previous_hash.clone(),
-            message_count: 0,
-            preview,
-        }
+        let kind = if ENVELOPES.iter().any(|e| raw.starts_with(e)) {
+            BlockKind::Unknown
+        } else {
+            BlockKind::Text
+        };
+        summary(kind, 0, preview)
     }
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ChainSummary {
     pub blocks: Vec<BlockSummary>,
+    /// Messages in `blocks` (the whole chain for `from_chain`).
     pub total_messages: usize,
+    /// Chain position of `blocks[0]`.
+    #[serde(default)]
+    pub offset: usize,
+    /// Blocks in the whole chain, for paging through it.
+    #[serde(default)]
+    pub total_blocks: usize,
 }
 
 impl ChainSummary {
+    /// Summary of every block; prefer `page` for long chains.
     pub fn from_chain(chain: &Blockchain) -> Self {
-        let mut total = 0;
-        let blocks = chain
+        Self::page(chain, 0, chain.chain.len())
+    }
+
+    /// Summary of up to `limit` blocks starting at position `offset`.
+    pub fn page(chain: &Blockchain, offset: usize, limit: usize) -> Self {
+        let blocks: Vec<BlockSummary> = chain
             .chain
             .iter()
-            .map(|b| {
-                let bs = BlockSummary::from_block(b, 32);
-                total += bs.message_count;
-                bs
-            })
+            .skip(offset)
+            .take(limit)
+            .map(|b| BlockSummary::from_block(b, 32))
             .collect();
         Self {
+            total_messages: blocks.iter().map(|bs| bs.message_count).sum(),
             blocks,
-            total_messages: total,
+            offset,
+            total_blocks: chain.chain.len(),
         }
     }
 }
@@ -278,6 +929,9 @@ mod tests {
     use super::*;
     use ed25519_dalek::SigningKey;
     use rand::rngs::OsRng;
+    use std::time::Duration;
+    use crate::block::{current_timestamp_ms, DEFAULT_MAX_ATTACHMENT_BYTES, DEFAULT_MAX_MESSAGE_BYTES};
+    use crate::clock::MockClock;
 
     #[test]
     fn test_text_blocks() {
@@ -300,6 +954,211 @@ mod tests {
         assert_eq!(all[0].content, "hi");
     }
 
+    #[test]
+    fn live_messages_skip_expired() {
+        let sk = SigningKey::generate(&mut OsRng);
+        let mut bc = Blockchain::new();
+        bc.add_messages_block(vec![
+            SignedMessage::new("forever".into(), &sk, None, 1_000),
+            SignedMessage::new_expiring("brief".into(), &sk, None, 1_000, 2_000),
+        ]);
+        assert!(bc.is_valid());
+        assert_eq!(bc.all_verified_messages().len(), 2);
+        assert_eq!(bc.all_live_messages(1_999).len(), 2);
+        let live = bc.all_live_messages(2_000);
+        assert_eq!(live.len(), 1);
+        assert_eq!(live[0].content, "forever");
+    }
+
+    #[test]
+    fn thread_collects_parent_and_direct_replies() {
+        let sk = SigningKey::generate(&mut OsRng);
+        let parent = SignedMessage::new("root".into(), &sk, None, 10);
+        let late = SignedMessage::new_reply("late".into(), &sk, None, 30, parent.id.clone());
+        let early = SignedMessage::new_reply("early".into(), &sk, None, 20, parent.id.clone());
+        let nested = SignedMessage::new_reply("nested".into(), &sk, None, 25, early.id.clone());
+        let unrelated = SignedMessage::new("other".into(), &sk, None, 15);
+
+        let mut bc = Blockchain::new();
+        bc.add_messages_block(vec![late.clone(), nested, unrelated]);
+        // Parent not (yet) in the chain: just the replies.
+        let thread: Vec<String> = bc.thread_of(&parent.id).into_iter().map(|m| m.content).collect();
+        assert_eq!(thread, ["late"]);
+
+        bc.add_messages_block(vec![parent.clone(), early]);
+        let thread: Vec<String> = bc.thread_of(&parent.id).into_iter().map(|m| m.content).collect();
+        assert_eq!(thread, ["root", "early", "late"]);
+
+        let mut forged = late;
+        forged.reply_to = Some("elsewhere".into());
+        bc.add_message_block(forged);
+        assert_eq!(bc.thread_of("elsewhere").len(), 0, "unverified replies are skipped");
+    }
+
+    #[test]
+    fn attachment_roundtrip_and_limits() {
+        let bytes: Vec<u8> = (0..=255u8).cycle().take(1000).collect();
+        let mut bc = Blockchain::new();
+        let block = bc
+            .add_attachment_block("FROM", "TO", "cat.png", "image/png", &bytes, DEFAULT_MAX_ATTACHMENT_BYTES)
+            .unwrap()
+            .clone();
+        assert!(bc.is_valid());
+        assert!(block.as_messages().is_none());
+        assert!(block.as_direct_text().is_none());
+
+        let att = block.as_attachment().unwrap();
+        assert_eq!((att.from.as_str(), att.to.as_str()), ("FROM", "TO"));
+        assert_eq!(att.decode().unwrap(), bytes);
+        assert_eq!(BlockSummary::from_block(&block, 32).preview, "📎 cat.png (image/png)");
+        assert!(block.to_string().ends_with("📎 cat.png (image/png)"));
+
+        let mut corrupt = att.clone();
+        corrupt.data_b64 = AttachmentPayload::new("", "", "", "", b"other").data_b64;
+        assert!(corrupt.decode().is_err());
+        corrupt.data_b64 = "not base64!".into();
+        assert!(corrupt.decode().is_err());
+
+        let len = bc.chain.len();
+        assert!(bc.add_attachment_block("FROM", "TO", "big.bin", "application/octet-stream", &bytes, 999).is_err());
+        assert_eq!(bc.chain.len(), len);
+        assert!(bc.chain[0].as_attachment().is_none());
+    }
+
+    #[test]
+    fn text_blocks_respect_the_message_limit() {
+        let mut bc = Blockchain::new();
+        let at_limit = "x".repeat(DEFAULT_MAX_MESSAGE_BYTES);
+        assert_eq!(bc.try_add_text_block(at_limit.clone(), DEFAULT_MAX_MESSAGE_BYTES).unwrap().data, at_limit);
+
+        let len = bc.chain.len();
+        let err = bc.try_add_text_block(at_limit + "x", DEFAULT_MAX_MESSAGE_BYTES).unwrap_err();
+        assert!(err.to_string().contains("limit is 16384"), "{err}");
+        assert_eq!(bc.chain.len(), len);
+        assert!(bc.is_valid());
+    }
+
+    #[test]
+    fn summaries_classify_each_block_kind() {
+        let sk = SigningKey::generate(&mut OsRng);
+        let mut bc = Blockchain::new_with_genesis("lan");
+        bc.add_text_block("hello");
+        bc.add_messages_block(vec![SignedMessage::new("m".into(), &sk, None, 1)]);
+        bc.add_direct_text_block("FROM", "TO", "hi");
+        bc.add_attachment_block("FROM", "TO", "a.txt", "text/plain", b"x", DEFAULT_MAX_ATTACHMENT_BYTES).unwrap();
+        bc.add_text_block("{\"direct\":\"not a payload\"}");
+
+        let summary = ChainSummary::from_chain(&bc);
+        let kinds: Vec<BlockKind> = summary.blocks.iter().map(|b| b.kind).collect();
+        use BlockKind::*;
+        assert_eq!(kinds, [Unknown, Text, Messages, Direct, Attachment, Unknown]);
+        assert_eq!((summary.total_messages, summary.offset, summary.total_blocks), (3, 0, 6));
+
+        let page = ChainSummary::page(&bc, 2, 2);
+        assert_eq!(page.blocks.iter().map(|b| b.index).collect::<Vec<_>>(), [2, 3]);
+        assert_eq!((page.total_messages, page.offset, page.total_blocks), (2, 2, 6));
+        assert!(ChainSummary::page(&bc, 10, 5).blocks.is_empty());
+    }
+
+    /// `blocks` blocks of 10 messages; a few signed messages are reused so
+    /// the cost is in verification rather than signing.
+    fn chain_of_messages(blocks: usize) -> Blockchain {
+        let sk = SigningKey::generate(&mut OsRng);
+        let msgs: Vec<SignedMessage> = (0..10).map(|i| SignedMessage::new(format!("m{i}"), &sk, None, i)).collect();
+        let mut bc = Blockchain::new();
+        for _ in 0..blocks {
+            bc.add_messages_block(msgs.clone());
+        }
+        bc.add_text_block("plain");
+        bc
+    }
+
+    /// Change one message in each of `blocks` and re-link, so only the
+    /// signatures (and Merkle roots) are wrong.
+    fn tamper(bc: &mut Blockchain, blocks: &[usize]) {
+        for &idx in blocks {
+            let mut msgs = bc.chain[idx].as_messages().unwrap();
+            msgs[idx % 10].content.push('!');
+            bc.chain[idx].data = serde_json::to_string(&msgs).unwrap();
+        }
+        for idx in 1..bc.chain.len() {
+            bc.chain[idx].previous_hash = bc.chain[idx - 1].hash.clone();
+            bc.chain[idx].hash = bc.chain[idx].calculate_hash();
+        }
+        assert!(bc.is_valid());
+    }
+
+    #[test]
+    fn parallel_deep_validation_matches_serial() {
+        let mut bc = chain_of_messages(100);
+        tamper(&mut bc, &[3, 50, 77]);
+        let serial = bc.validate_deep();
+        assert_eq!(serial, (false, 1000, 3));
+        assert_eq!(bc.validate_deep_parallel(), serial);
+
+        let mut broken = bc.clone();
+        broken.chain[10].previous_hash = "x".into();
+        assert_eq!(broken.validate_deep_parallel(), (false, 0, 0));
+    }
+
+    #[test]
+    fn parallel_deep_validation_ignores_thread_count() {
+        let mut bc = chain_of_messages(4);
+        assert_eq!(bc.validate_deep_parallel(), (true, 40, 0));
+        tamper(&mut bc, &[2]);
+        let serial = bc.validate_deep();
+        for threads in [1, 3] {
+            let pool = rayon::ThreadPoolBuilder::new().num_threads(threads).build().unwrap();
+            assert_eq!(pool.install(|| bc.validate_deep_parallel()), serial, "{threads} threads");
+        }
+    }
+
+    /// A v0 message as the first prototypes wrote it (`id` a UUID).
+    fn legacy_json(sk: &SigningKey, id: &str, content: &str) -> String {
+        use base64::{engine::general_purpose, Engine as _};
+        use ed25519_dalek::Signer;
+        use sha2::{Digest, Sha256};
+        let digest = Sha256::new().chain_update(content).chain_update(id).finalize();
+        serde_json::json!({
+            "id": id,
+            "sender": wichain_core::encode_pubkey_b64(&sk.verifying_key().to_bytes()),
+            "content": content,
+            "signature": general_purpose::STANDARD.encode(sk.sign(&digest).to_bytes()),
+        })
+        .to_string()
+    }
+
+    #[test]
+    fn legacy_blocks_are_read_and_migrated() {
+        let sk = SigningKey::generate(&mut OsRng);
+        let mut bc = Blockchain::new();
+        bc.add_text_block("before");
+        bc.add_text_block(legacy_json(&sk, "00000000-0000-4000-8000-00000000000a", "old hello"));
+        bc.add_text_block(format!("[{},{}]", legacy_json(&sk, "00000000-0000-4000-8000-00000000000b", "one"), legacy_json(&sk, "00000000-0000-4000-8000-00000000000c", "two")));
+        let forged = legacy_json(&sk, "00000000-0000-4000-8000-00000000000d", "real").replace("\"real\"", "\"fake\"");
+        bc.add_text_block(forged.clone());
+        bc.add_message_block(SignedMessage::new_now("new style".into(), &sk, None));
+        assert!(bc.is_valid());
+        assert_eq!(bc.chain[2].as_legacy_messages().unwrap()[0].content, "old hello");
+        assert!(bc.chain[1].as_legacy_messages().is_none());
+
+        let contents = |bc: &Blockchain| -> Vec<String> { bc.all_verified_messages().into_iter().map(|m| m.content).collect() };
+        assert_eq!(contents(&bc), ["old hello", "one", "two", "new style"]);
+        assert_eq!(bc.all_messages().len(), 4, "unverifiable v0 messages can't be converted");
+        assert_eq!(bc.all_live_messages(u64::MAX).len(), 4, "v0 messages never expire");
+
+        let kept_hash = bc.chain[1].hash.clone();
+        assert_eq!(bc.migrate_legacy(), 2);
+        assert!(bc.is_valid());
+        assert_eq!(bc.chain[1].hash, kept_hash);
+        assert!(bc.chain[2].as_messages().is_some());
+        assert!(bc.chain[3].merkle_root.is_some());
+        assert_eq!(bc.chain[4].data, forged, "forged block left alone");
+        assert_eq!(contents(&bc), ["old hello", "one", "two", "new style"]);
+        assert_eq!(bc.validate_deep(), (true, 4, 0));
+        assert_eq!(bc.migrate_legacy(), 0);
+    }
+
     #[test]
     fn test_direct_text_block() {
         let mut bc = Blockchain::new();
@@ -310,6 +1169,289 @@ mod tests {
         assert_eq!(d[0].text, "hello");
     }
 
+    #[test]
+    fn test_mined_blocks() {
+        let mut bc = Blockchain::new();
+        let b = bc.add_text_block_mined("work", 3);
+        assert!(b.hash.starts_with("000"));
+        assert!(b.verify_pow(3));
+        let sk = SigningKey::generate(&mut OsRng);
+        bc.add_messages_block_mined(vec![SignedMessage::new_now("hi".into(), &sk, None)], 2);
+        assert!(bc.is_valid_with_pow(2));
+        assert!(!bc.is_valid_with_pow(8));
+
+        bc.add_text_block("unmined");
+        assert!(bc.is_valid());
+        assert!(!bc.is_valid_with_pow(2));
+    }
+
+    #[test]
+    fn test_mining_cancel() {
+        let mut bc = Blockchain::new();
+        let cancel = AtomicBool::new(true);
+        assert!(bc.add_mined_block("never".into(), 64, &cancel).is_none());
+        assert_eq!(bc.chain.len(), 1);
+    }
+
+    #[test]
+    fn rapid_messages_share_one_block_and_flush_before_saving() {
+        let sk = SigningKey::generate(&mut OsRng);
+        let clock = MockClock::new(1_000);
+        let mut bc = Blockchain::new_with_clock(DEFAULT_NETWORK_ID, clock.clone());
+        for i in 0..5 {
+            assert!(bc.queue_message(SignedMessage::new(format!("m{i}"), &sk, None, i)).is_none());
+        }
+        assert_eq!((bc.chain.len(), bc.pending_len()), (1, 5));
+        assert!(bc.flush_due(bc.now_ms()).is_none(), "window still open");
+
+        // Shutting down: flush, then save. Nothing queued is written.
+        let path = std::env::temp_dir().join(format!("wichain-batch-{}.json", rand::random::<u64>()));
+        bc.queue_message(SignedMessage::new("late".into(), &sk, None, 5));
+        let block = bc.flush_pending().expect("queued messages commit").clone();
+        assert_eq!(block.as_messages().unwrap().len(), 6);
+        assert!(bc.flush_pending().is_none());
+        bc.save_to_file(&path).unwrap();
+        let reloaded = Blockchain::load_from_file(&path).unwrap();
+        assert_eq!(reloaded.chain.len(), 2);
+        assert_eq!(reloaded.all_verified_messages().len(), 6);
+        assert!(reloaded.is_valid());
+        fs::remove_file(&path).ok();
+
+        // A full batch commits itself; a stale one commits on the next tick.
+        bc.set_batch_policy(BatchPolicy { max_messages: 2, max_delay_ms: 200 });
+        bc.queue_message(SignedMessage::new("a".into(), &sk, None, 6));
+        assert_eq!(bc.queue_message(SignedMessage::new("b".into(), &sk, None, 7)).map(|b| b.index), Some(2));
+        bc.queue_message(SignedMessage::new("c".into(), &sk, None, 8));
+        clock.advance(Duration::from_millis(199));
+        assert!(bc.flush_due(bc.now_ms()).is_none());
+        clock.advance(Duration::from_millis(1));
+        assert_eq!(bc.flush_due(bc.now_ms()).map(|b| b.index), Some(3));
+        assert_eq!((bc.chain.len(), bc.pending_len()), (4, 0));
+
+        // Any other append commits the queue first, keeping arrival order,
+        // and saving never drops what is still queued.
+        bc.queue_message(SignedMessage::new("queued".into(), &sk, None, 9));
+        bc.add_text_block("after");
+        assert_eq!(bc.chain[4].as_messages().unwrap()[0].content, "queued");
+        assert_eq!(bc.chain[5].data, "after");
+        bc.queue_message(SignedMessage::new("unsaved".into(), &sk, None, 10));
+        bc.save_to_file(&path).unwrap();
+        assert_eq!(bc.pending_len(), 0);
+        let reloaded = Blockchain::load_from_file(&path).unwrap();
+        assert_eq!(reloaded.chain.len(), 7);
+        assert!(reloaded.all_verified_messages().iter().any(|m| m.content == "unsaved"));
+        fs::remove_file(&path).ok();
+    }
+
+    #[test]
+    fn mock_clock_stamps_back_dated_blocks() {
+        let day = Duration::from_secs(86_400);
+        let clock = MockClock::new(1_000);
+        let mut bc = Blockchain::new_with_clock("lan", clock.clone());
+        bc.add_text_block("a day old");
+        clock.advance(day);
+        bc.add_text_block("today");
+        let stamps: Vec<u128> = bc.chain.iter().map(|b| b.timestamp_ms).collect();
+        assert_eq!(stamps, [1_000, 1_000, 86_401_000]);
+        assert_eq!(bc.validate_timestamps(), Ok(()));
+        assert_eq!(bc.blocks_in_range(0, 1_000).len(), 2);
+
+        // Only the back-dated block is pruned, under a checkpoint stamped by the same clock.
+        clock.advance(day);
+        assert_eq!(bc.prune_before(2_000).unwrap(), 1);
+        assert_eq!(bc.chain[0].timestamp_ms, 172_801_000);
+        assert_eq!(bc.chain[1].raw_data(), "today");
+        assert_eq!(bc.network_id(), "lan");
+
+        // The free function keeps reading the system clock.
+        assert!(current_timestamp_ms() > bc.now_ms());
+    }
+
+    /// Chain of `n` text blocks with timestamps 1..=n.
+    fn timed_chain(n: u128) -> Blockchain {
+        let mut bc = Blockchain::new();
+        for ts in 1..=n {
+            let prev = bc.last_block().hash.clone();
+            bc.chain.push(Block::new_text(ts as u64, ts, prev, format!("b{ts}")));
+        }
+        bc
+    }
+
+    #[test]
+    fn test_prune_before() {
+        let mut bc = timed_chain(100);
+        let removed = bc.prune_before(61).unwrap();
+        assert_eq!(removed, 60);
+        assert_eq!(bc.chain.len(), 41);
+        assert!(bc.is_valid());
+        let cp = bc.chain[0].as_checkpoint().unwrap();
+        assert_eq!(cp.pruned_blocks, 60);
+        assert_eq!(bc.chain[1].data, "b61");
+
+        // Pruning again accumulates; nothing older left = no-op.
+        assert_eq!(bc.prune_before(71).unwrap(), 10);
+        assert_eq!(bc.chain[0].as_checkpoint().unwrap().pruned_blocks, 70);
+        assert_eq!(bc.prune_before(71).unwrap(), 0);
+        assert!(bc.is_valid());
+    }
+
+    #[test]
+    fn test_prune_refuses_out_of_order() {
+        let mut bc = timed_chain(10);
+        let prev = bc.last_block().hash.clone();
+        bc.chain.push(Block::new_text(11, 2, prev, "late"));
+        assert!(bc.prune_before(5).is_err());
+        assert_eq!(bc.chain.len(), 12);
+    }
+
+    #[test]
+    fn test_filter_by_sender() {
+        let alice = SigningKey::generate(&mut OsRng);
+        let bob = SigningKey::generate(&mut OsRng);
+        let alice_msg = SignedMessage::new_now("from alice".into(), &alice, None);
+        let bob_msg = SignedMessage::new_now("from bob".into(), &bob, None);
+        let mut bc = Blockchain::new();
+        bc.add_messages_block(vec![alice_msg.clone(), bob_msg.clone()]);
+        bc.add_message_block(SignedMessage::new_now("again".into(), &alice, None));
+        bc.add_direct_text_block("ALICE", "BOB", "hi bob");
+        bc.add_direct_text_block("BOB", "ALICE", "hi alice");
+
+        let from_alice = bc.messages_from(&alice_msg.from);
+        assert_eq!(from_alice.len(), 2);
+        assert!(from_alice.iter().all(|m| m.from == alice_msg.from));
+        assert_eq!(bc.messages_from(&bob_msg.from)[0].content, "from bob");
+
+        let direct = bc.direct_text_from("BOB");
+        assert_eq!(direct.len(), 1);
+        assert_eq!(direct[0].text, "hi alice");
+    }
+
+    #[test]
+    fn test_blocks_in_range() {
+        let bc = timed_chain(10);
+        let ts = |v: Vec<&Block>| v.iter().map(|b| b.timestamp_ms).collect::<Vec<_>>();
+        assert_eq!(ts(bc.blocks_in_range(3, 5)), vec![3, 4, 5]);
+        assert_eq!(ts(bc.blocks_in_range(10, 10)), vec![10]);
+        assert!(bc.blocks_in_range(6, 2).is_empty());
+    }
+
+    #[test]
+    fn test_blocks_in_range_gap_and_disorder() {
+        let mut bc = Blockchain::new();
+        for ts in [1u128, 2, 3, 100, 101] {
+            let prev = bc.last_block().hash.clone();
+            bc.chain.push(Block::new_text(bc.chain.len() as u64, ts, prev, "x"));
+        }
+        assert!(bc.blocks_in_range(4, 99).is_empty());
+
+        // An out-of-order block (foreign clock): linear fallback still finds it,
+        // in memory and once reloaded.
+        let prev = bc.last_block().hash.clone();
+        bc.chain.push(Block::new_text(bc.chain.len() as u64, 2, prev, "late"));
+        bc.add_text_block("now");
+        let got: Vec<_> = bc.blocks_in_range(2, 2).iter().map(|b| b.index).collect();
+        assert_eq!(got, vec![2, 6]);
+        let path = std::env::temp_dir().join(format!("wichain-range-{}.json", rand::random::<u64>()));
+        bc.save_to_file(&path).unwrap();
+        let loaded = Blockchain::load_from_file(&path).unwrap();
+        assert_eq!(loaded.blocks_in_range(2, 2).len(), 2);
+        fs::remove_file(&path).ok();
+
+        // No genesis at all (hand-edited file): empty, not a panic.
+        let empty: Blockchain = serde_json::from_str(r#"{"chain":[]}"#).unwrap();
+        assert!(empty.blocks_in_range(0, u128::MAX).is_empty());
+    }
+
+    #[test]
+    fn test_encrypted_roundtrip() {
+        let dir = std::env::temp_dir().join(format!("wichain-enc-{}", current_timestamp_ms()));
+        let path = dir.join("chain.bin");
+        let key = [7u8; 32];
+        let mut bc = Blockchain::new();
+        bc.add_text_block("secret stuff");
+        bc.save_to_file_encrypted(&path, &key).unwrap();
+
+        let raw = fs::read(&path).unwrap();
+        assert!(!String::from_utf8_lossy(&raw).contains("secret stuff"));
+        let loaded = Blockchain::load_from_file_encrypted(&path, &key).unwrap();
+        assert_eq!(loaded.chain.len(), 2);
+        assert_eq!(loaded.chain[1].data, "secret stuff");
+        assert!(loaded.is_valid());
+
+        assert!(Blockchain::load_from_file_encrypted(&path, &[8u8; 32]).is_err());
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn load_verified_names_the_tampered_block() {
+        let dir = std::env::temp_dir().join(format!("wichain-verify-{}", current_timestamp_ms()));
+        let path = dir.join("chain.json");
+        let sk = SigningKey::generate(&mut OsRng);
+        let mut bc = Blockchain::new();
+        bc.add_text_block("one");
+        bc.add_message_block(SignedMessage::new("two".into(), &sk, None, 2));
+        bc.add_text_block("three");
+        bc.save_to_file(&path).unwrap();
+        assert_eq!(Blockchain::load_verified(&path, true).unwrap().chain.len(), 4);
+
+        // Edited on disk without re-hashing.
+        let mut edited = bc.clone();
+        edited.chain[1].data = "uno".into();
+        edited.save_to_file(&path).unwrap();
+        let err = Blockchain::load_verified(&path, false).unwrap_err().to_string();
+        assert!(err.contains("position 1"), "{err}");
+
+        // Re-hashed and re-linked, but the message no longer matches its signature.
+        let mut forged = bc.clone();
+        let mut msgs = forged.chain[2].as_messages().unwrap();
+        msgs[0].content = "forged".into();
+        forged.chain[2].data = serde_json::to_string(&msgs).unwrap();
+        forged.relink_from(2);
+        forged.save_to_file(&path).unwrap();
+        assert!(Blockchain::load_verified(&path, false).is_ok());
+        let err = Blockchain::load_verified(&path, true).unwrap_err().to_string();
+        assert!(err.contains("position 2"), "{err}");
+
+        assert!(Blockchain::load_verified(dir.join("missing.json"), true).is_ok());
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn test_appendlog_roundtrip() {
+        let dir = std::env::temp_dir().join(format!("wichain-log-{}", current_timestamp_ms()));
+        let path = dir.join("chain.ndjson");
+        let mut bc = Blockchain::new();
+        Blockchain::append_block_to_file(&path, bc.last_block()).unwrap();
+        for i in 0..50 {
+            let b = bc.add_text_block(format!("msg {i}")).clone();
+            Blockchain::append_block_to_file(&path, &b).unwrap();
+        }
+
+        let loaded = Blockchain::load_from_appendlog(&path).unwrap();
+        assert_eq!(loaded.chain.len(), 51);
+        assert!(loaded.is_valid());
+        assert_eq!(loaded.last_block().hash, bc.last_block().hash);
+
+        // A block that doesn't link to the tip is rejected.
+        let stray = Block::new_text(99, 0, "nope".into(), "x");
+        Blockchain::append_block_to_file(&path, &stray).unwrap();
+        assert!(Blockchain::load_from_appendlog(&path).is_err());
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn test_backdated_block_caught() {
+        let mut bc = timed_chain(5);
+        assert_eq!(bc.validate_timestamps(), Ok(()));
+        assert!(bc.validate_strict());
+
+        let prev = bc.last_block().hash.clone();
+        bc.chain.push(Block::new_text(6, 3, prev, "back-dated"));
+        assert!(bc.is_valid());
+        assert_eq!(bc.validate_timestamps(), Err(6));
+        assert!(!bc.validate_strict());
+    }
+
     #[test]
     fn test_tamper_detect() {
         let mut bc = Blockchain::new();
@@ -320,4 +1462,53 @@ mod tests {
         }
         assert!(!bc.is_valid());
     }
+
+    #[test]
+    fn segments_must_link_and_hash() {
+        let mut bc = Blockchain::new();
+        for text in ["a", "b", "c"] {
+            bc.add_text_block(text);
+        }
+        let segment = bc.chain[1..].to_vec();
+        assert!(Blockchain::is_valid_segment(&segment));
+
+        // A redacted header keeps its hash, so the chain still links.
+        let mut redacted = segment.clone();
+        redacted[1].data.clear();
+        assert!(Blockchain::is_valid_segment(&redacted));
+
+        let mut tampered = segment.clone();
+        tampered[1].data = "B".into();
+        assert!(!Blockchain::is_valid_segment(&tampered));
+
+        let gap = vec![segment[0].clone(), segment[2].clone()];
+        assert!(!Blockchain::is_valid_segment(&gap));
+    }
+
+    #[test]
+    fn networks_have_distinct_genesis_and_refuse_each_other() {
+        let default = Blockchain::new();
+        assert_eq!(default.chain[0].data, "Genesis Block");
+        assert_eq!(default.network_id(), DEFAULT_NETWORK_ID);
+
+        let ours = Blockchain::new_with_genesis("field-team");
+        let theirs = Blockchain::new_with_genesis("other-net");
+        assert_eq!(ours.network_id(), "field-team");
+        assert_ne!(ours.chain[0].hash, theirs.chain[0].hash);
+        assert!(ours.is_valid() && theirs.is_valid());
+
+        assert!(ours.ensure_same_network("field-team").is_ok());
+        assert!(ours.ensure_same_network(&theirs.network_id()).is_err());
+        assert!(default.ensure_same_network(&ours.network_id()).is_err());
+
+        // The id survives the round trip through disk and a prune.
+        let mut ours: Blockchain = serde_json::from_str(&serde_json::to_string(&ours).unwrap()).unwrap();
+        ours.add_text_block("old");
+        ours.add_text_block("new");
+        let cutoff = ours.chain[2].timestamp_ms;
+        ours.chain[1].timestamp_ms = cutoff - 1;
+        ours.relink_from(1);
+        assert_eq!(ours.prune_before(cutoff).unwrap(), 1);
+        assert_eq!(ours.network_id(), "field-team");
+    }
 }
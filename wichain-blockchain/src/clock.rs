@@ -0,0 +1,67 @@
+//! Time source for block timestamps.
+//!
+//! A `Blockchain` reads the time through a `Clock`: the system clock, unless
+//! it was made with `Blockchain::new_with_clock`. Tests hand it a
+//! `MockClock` to stamp blocks at chosen (e.g. back-dated) times, so expiry,
+//! pruning by time and batch windows can be checked exactly.
+//! `current_timestamp_ms` always reads the system clock.
+
+use std::fmt;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+pub trait Clock: fmt::Debug + Send + Sync {
+    /// Unix time in ms.
+    fn now_ms(&self) -> u128;
+}
+
+/// The real wall clock.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now_ms(&self) -> u128 {
+        SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_millis())
+            .unwrap_or_default()
+    }
+}
+
+/// A clock that stands still until set or advanced.
+#[derive(Debug, Default)]
+pub struct MockClock {
+    now_ms: AtomicU64,
+}
+
+impl MockClock {
+    pub fn new(now_ms: u64) -> Arc<Self> {
+        Arc::new(Self { now_ms: AtomicU64::new(now_ms) })
+    }
+
+    pub fn set(&self, now_ms: u64) {
+        self.now_ms.store(now_ms, Ordering::Relaxed);
+    }
+
+    pub fn advance(&self, by: Duration) {
+        self.now_ms.fetch_add(by.as_millis() as u64, Ordering::Relaxed);
+    }
+}
+
+impl Clock for MockClock {
+    fn now_ms(&self) -> u128 {
+        u128::from(self.now_ms.load(Ordering::Relaxed))
+    }
+}
+
+/// The clock a chain was made with; not serialized, so loaded chains use
+/// the system clock.
+#[derive(Debug, Clone)]
+pub(crate) struct ChainClock(pub(crate) Arc<dyn Clock>);
+
+impl Default for ChainClock {
+    fn default() -> Self {
+        Self(Arc::new(SystemClock))
+    }
+}
@@ -10,9 +10,13 @@
 
 pub mod block;
 pub mod blockchain;
+pub mod clock;
+pub mod merkle;
 
-pub use block::{current_timestamp_ms, Block};
-pub use blockchain::{BlockSummary, Blockchain, ChainSummary};
+pub use block::{current_timestamp_ms, AttachmentPayload, Block, DEFAULT_MAX_ATTACHMENT_BYTES, DEFAULT_MAX_MESSAGE_BYTES};
+pub use clock::{Clock, MockClock, SystemClock};
+pub use blockchain::{BatchPolicy, BlockKind, BlockSummary, Blockchain, ChainSummary, DEFAULT_NETWORK_ID};
+pub use merkle::{verify_merkle_proof, MerkleProofStep};
 
 #[cfg(test)]
 mod tests {
@@ -31,4 +35,36 @@ mod tests {
         assert!(bc.is_valid());
         assert!(!bc.all_messages().is_empty());
     }
+
+    #[test]
+    fn message_inclusion_proof() {
+        let sk = SigningKey::generate(&mut OsRng);
+        let msgs: Vec<_> = (0..3)
+            .map(|i| SignedMessage::new_now(format!("m{i}"), &sk, None))
+            .collect();
+        let mut bc = Blockchain::new();
+        let block = bc.add_messages_block(msgs.clone()).clone();
+        let root = block.merkle_root.clone().expect("message block has a root");
+        let proof = block.merkle_proof(2).unwrap();
+        assert!(verify_merkle_proof(msgs[2].digest_bytes(), &proof, &root));
+        assert!(bc.chain[0].merkle_root.is_none());
+
+        // Tampering with the stored root is caught by deep validation.
+        bc.chain[1].merkle_root = Some("00".repeat(32));
+        assert!(bc.is_valid());
+        assert!(!bc.validate_deep().0);
+    }
+
+    #[test]
+    fn multibyte_previews_do_not_split_chars() {
+        let mut bc = Blockchain::new();
+        let direct = bc.add_direct_text_block("é😀é😀", "🦀🦀🦀🦀", "hi").clone();
+        assert!(direct.to_string().contains("direct é😀é😀→🦀🦀🦀🦀: hi"));
+
+        let text = "😀".repeat(40);
+        let block = bc.add_text_block(text.as_str()).clone();
+        assert!(block.to_string().ends_with(&format!("{}...", "😀".repeat(32))));
+        let summary = BlockSummary::from_block(&block, 10);
+        assert_eq!(summary.preview, format!("{}...", "😀".repeat(10)));
+    }
 }
@@ -0,0 +1,133 @@
+//! SHA256 Merkle trees over the messages of a block.
+//!
+//! Leaves are each message's `SignedMessage::digest_bytes()`; parents are
+//! `SHA256(left || right)`. An odd node at any level is paired with itself
+//! (Bitcoin style), so every level but the root has an even width.
+
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+/// One step of an inclusion proof, from the leaf level upwards.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct MerkleProofStep {
+    /// Hex SHA256 of the sibling node.
+    pub sibling: String,
+    /// Sibling is the *left* input of the parent hash.
+    pub sibling_on_left: bool,
+}
+
+fn hash_pair(left: &[u8; 32], right: &[u8; 32]) -> [u8; 32] {
+    let mut hasher = Sha256::new();
+    hasher.update(left);
+    hasher.update(right);
+    hasher.finalize().into()
+}
+
+fn next_level(level: &[[u8; 32]]) -> Vec<[u8; 32]> {
+    level
+        .chunks(2)
+        .map(|pair| hash_pair(&pair[0], pair.get(1).unwrap_or(&pair[0])))
+        .collect()
+}
+
+fn to_hex(bytes: &[u8; 32]) -> String {
+    bytes.iter().map(|b| format!("{b:02x}")).collect()
+}
+
+fn from_hex(s: &str) -> Option<[u8; 32]> {
+    if s.len() != 64 || !s.is_ascii() {
+        return None;
+    }
+    let mut out = [0u8; 32];
+    for (i, byte) in out.iter_mut().enumerate() {
+        *byte = u8::from_str_radix(&s[i * 2..i * 2 + 2], 16).ok()?;
+    }
+    Some(out)
+}
+
+/// Hex Merkle root of `leaves` (`None` if empty).
+pub fn merkle_root(leaves: &[[u8; 32]]) -> Option<String> {
+    if leaves.is_empty() {
+        return None;
+    }
+    let mut level = leaves.to_vec();
+    while level.len() > 1 {
+        level = next_level(&level);
+    }
+    Some(to_hex(&level[0]))
+}
+
+/// Sibling path proving `leaves[index]` is under `merkle_root(leaves)`.
+pub fn merkle_proof(leaves: &[[u8; 32]], index: usize) -> Option<Vec<MerkleProofStep>> {
+    if index >= leaves.len() {
+        return None;
+    }
+    let mut proof = Vec::new();
+    let mut level = leaves.to_vec();
+    let mut idx = index;
+    while level.len() > 1 {
+        let sibling_idx = idx ^ 1;
+        let sibling = level.get(sibling_idx).unwrap_or(&level[idx]);
+        proof.push(MerkleProofStep {
+            sibling: to_hex(sibling),
+            sibling_on_left: sibling_idx < idx,
+        });
+        level = next_level(&level);
+        idx /= 2;
+    }
+    Some(proof)
+}
+
+/// Check that `leaf` hashes up to the hex `root` along `proof`.
+pub fn verify_merkle_proof(leaf: [u8; 32], proof: &[MerkleProofStep], root: &str) -> bool {
+    let mut acc = leaf;
+    for step in proof {
+        let Some(sibling) = from_hex(&step.sibling) else {
+            return false;
+        };
+        acc = if step.sibling_on_left {
+            hash_pair(&sibling, &acc)
+        } else {
+            hash_pair(&acc, &sibling)
+        };
+    }
+    to_hex(&acc) == root
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn leaves(n: u8) -> Vec<[u8; 32]> {
+        (0..n).map(|i| Sha256::digest([i]).into()).collect()
+    }
+
+    #[test]
+    fn single_leaf_root_is_leaf() {
+        let l = leaves(1);
+        assert_eq!(merkle_root(&l), Some(to_hex(&l[0])));
+        assert_eq!(merkle_proof(&l, 0), Some(vec![]));
+        assert!(merkle_root(&[]).is_none());
+    }
+
+    #[test]
+    fn proofs_verify_for_every_leaf() {
+        for n in 1..=7 {
+            let l = leaves(n);
+            let root = merkle_root(&l).unwrap();
+            for i in 0..l.len() {
+                let proof = merkle_proof(&l, i).unwrap();
+                assert!(verify_merkle_proof(l[i], &proof, &root), "n={n} i={i}");
+            }
+            assert!(merkle_proof(&l, l.len()).is_none());
+        }
+    }
+
+    #[test]
+    fn proof_rejects_wrong_leaf() {
+        let l = leaves(4);
+        let root = merkle_root(&l).unwrap();
+        let proof = merkle_proof(&l, 1).unwrap();
+        assert!(!verify_merkle_proof(l[2], &proof, &root));
+    }
+}
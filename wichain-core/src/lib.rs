@@ -1,7 +1,9 @@
-//! Core WiChain primitives: identities, signed messages, trust scoring utilities.
+//! Core WiChain primitives: identities, signed messages, trust scoring utilities,
+//! and XOR obfuscation for peers without AES (see `obfuscation`).
 //
 // Modules
 pub mod message;
+pub mod obfuscation;
 pub mod trust;
 
 pub use message::{
@@ -14,6 +16,7 @@ pub use trust::*; // re‑export TrustManager, Peer, etc.
 use ed25519_dalek::{Signature, Signer, Verifier, SigningKey, VerifyingKey};
 use rand_core::OsRng;
 use serde::{Serialize, Deserialize};
+use sha2::{Digest, Sha256};
 use thiserror::Error;
 
 // *** bring trait into scope for base64 encode/decode ***
@@ -26,8 +29,15 @@ pub enum IdentityError {
     Base64(#[from] base64::DecodeError),
     #[error("invalid public key length: expected 32, got {0}")]
     BadPubLen(usize),
+    #[error("invalid seed phrase: {0}")]
+    SeedPhrase(#[from] bip39::Error),
+    #[error("seed phrase must have {SEED_PHRASE_WORDS} words, got {0}")]
+    SeedPhraseLength(usize),
 }
 
+/// Words in an identity backup phrase (BIP39, 256 bits of entropy).
+pub const SEED_PHRASE_WORDS: usize = 24;
+
 /// Helper: base64‑encode a 32‑byte Ed25519 public key.
 pub fn encode_pubkey_b64(pk_bytes: &[u8; 32]) -> String {
     general_purpose::STANDARD.encode(pk_bytes)
@@ -44,6 +54,46 @@ pub fn decode_pubkey_b64(s: &str) -> Result<[u8; 32], IdentityError> {
     Ok(arr)
 }
 
+/// Hex digits in a fingerprint safety code, shown in groups of four.
+pub const FINGERPRINT_HEX_CHARS: usize = 12;
+/// Words in the spoken form of a fingerprint.
+pub const FINGERPRINT_WORDS: usize = 4;
+
+fn fingerprint_digest(pubkey_b64: &str) -> Result<[u8; 32], IdentityError> {
+    let raw = decode_pubkey_b64(pubkey_b64.trim())?;
+    Ok(Sha256::digest(raw).into())
+}
+
+/// Short safety code for a base64 pubkey, for two people to compare aloud:
+/// the first 12 hex digits of SHA-256 over the raw key, e.g. `"3FA9 1C0D 77E2"`.
+pub fn fingerprint(pubkey_b64: &str) -> Result<String, IdentityError> {
+    let digest = fingerprint_digest(pubkey_b64)?;
+    let hex: String = digest[..FINGERPRINT_HEX_CHARS / 2].iter().map(|b| format!("{b:02X}")).collect();
+    let groups: Vec<&str> = (0..hex.len()).step_by(4).map(|i| &hex[i..i + 4]).collect();
+    Ok(groups.join(" "))
+}
+
+/// The same fingerprint as words: 11-bit chunks of the digest (after the hex
+/// code's bytes) picked from the BIP39 English list.
+pub fn fingerprint_words(pubkey_b64: &str) -> Result<Vec<&'static str>, IdentityError> {
+    let digest = fingerprint_digest(pubkey_b64)?;
+    let offset = FINGERPRINT_HEX_CHARS / 2;
+    let bits = u64::from_be_bytes(digest[offset..offset + 8].try_into().unwrap());
+    let words = bip39::Language::English.word_list();
+    Ok((0..FINGERPRINT_WORDS)
+        .map(|i| words[((bits >> (64 - 11 * (i + 1))) & 0x7FF) as usize])
+        .collect())
+}
+
+/// Helper: the first `n` characters of `s` (not bytes), for log and preview
+/// snippets. Never splits a multi-byte UTF-8 character.
+pub fn truncate_chars(s: &str, n: usize) -> &str {
+    match s.char_indices().nth(n) {
+        Some((idx, _)) => &s[..idx],
+        None => s,
+    }
+}
+
 /// A locally stored identity (alias + keypair).
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct UserIdentity {
@@ -64,6 +114,33 @@ impl UserIdentity {
         }
     }
 
+    /// Restore an identity from its 24-word BIP39 backup phrase. The phrase's
+    /// entropy is the Ed25519 secret key itself, so the same phrase always
+    /// yields the same keypair. Case and extra whitespace are ignored; the
+    /// alias is left empty for the caller to fill in.
+    pub fn from_mnemonic(phrase: &str) -> Result<Self, IdentityError> {
+        let normalized = phrase.split_whitespace().collect::<Vec<_>>().join(" ").to_lowercase();
+        let mnemonic = bip39::Mnemonic::parse_in(bip39::Language::English, normalized)?;
+        if mnemonic.word_count() != SEED_PHRASE_WORDS {
+            return Err(IdentityError::SeedPhraseLength(mnemonic.word_count()));
+        }
+        let mut private_key = [0u8; 32];
+        private_key.copy_from_slice(&mnemonic.to_entropy());
+        let signing_key = SigningKey::from_bytes(&private_key);
+        Ok(UserIdentity {
+            alias: String::new(),
+            public_key: signing_key.verifying_key().to_bytes(),
+            private_key,
+        })
+    }
+
+    /// The 24-word BIP39 backup phrase for this identity's secret key.
+    pub fn to_mnemonic(&self) -> String {
+        bip39::Mnemonic::from_entropy(&self.private_key)
+            .expect("32 bytes is valid BIP39 entropy")
+            .to_string()
+    }
+
     pub fn sign(&self, message: &[u8]) -> Signature {
         let signing_key = SigningKey::from_bytes(&self.private_key);
         signing_key.sign(message)
@@ -99,4 +176,60 @@ mod tests {
         let dec = decode_pubkey_b64(&enc).unwrap();
         assert_eq!(dec, id.public_key);
     }
+
+    #[test]
+    fn seed_phrase_roundtrip_restores_the_same_key() {
+        // Fixed key so the checksum-word swap below is deterministic.
+        let sk = SigningKey::from_bytes(&[42u8; 32]);
+        let id = UserIdentity { alias: "Carol".into(), public_key: sk.verifying_key().to_bytes(), private_key: sk.to_bytes() };
+        let phrase = id.to_mnemonic();
+        assert_eq!(phrase.split(' ').count(), SEED_PHRASE_WORDS);
+
+        let restored = UserIdentity::from_mnemonic(&phrase).unwrap();
+        assert_eq!(restored.public_key, id.public_key);
+        assert_eq!(restored.private_key, id.private_key);
+        let sloppy = format!("  {}\n", phrase.to_uppercase().replace(' ', "   "));
+        assert_eq!(UserIdentity::from_mnemonic(&sloppy).unwrap().public_key, id.public_key);
+
+        // Swapping the checksum word breaks the phrase.
+        let mut words: Vec<&str> = phrase.split(' ').collect();
+        let last = words.pop().unwrap();
+        words.push(if last == "abandon" { "ability" } else { "abandon" });
+        assert!(matches!(UserIdentity::from_mnemonic(&words.join(" ")), Err(IdentityError::SeedPhrase(_))));
+
+        let short = bip39::Mnemonic::from_entropy(&[7u8; 16]).unwrap().to_string();
+        assert!(matches!(UserIdentity::from_mnemonic(&short), Err(IdentityError::SeedPhraseLength(12))));
+        assert!(UserIdentity::from_mnemonic("not a seed phrase").is_err());
+    }
+
+    #[test]
+    fn fingerprints_are_stable_and_distinct() {
+        let alice = encode_pubkey_b64(&UserIdentity::generate("Alice".into()).public_key);
+        let bob = encode_pubkey_b64(&UserIdentity::generate("Bob".into()).public_key);
+
+        let code = fingerprint(&alice).unwrap();
+        assert_eq!(code.len(), FINGERPRINT_HEX_CHARS + 2);
+        assert!(code.split(' ').all(|g| g.len() == 4 && g.chars().all(|c| c.is_ascii_hexdigit())));
+        assert_eq!(fingerprint(&format!(" {alice}\n")).unwrap(), code);
+        assert_ne!(fingerprint(&bob).unwrap(), code);
+
+        let words = fingerprint_words(&alice).unwrap();
+        assert_eq!(words.len(), FINGERPRINT_WORDS);
+        assert_eq!(fingerprint_words(&alice).unwrap(), words);
+        assert_ne!(fingerprint_words(&bob).unwrap(), words);
+
+        // Known vector: all-zero key.
+        let zero = encode_pubkey_b64(&[0u8; 32]);
+        assert_eq!(fingerprint(&zero).unwrap(), "6668 7AAD F862");
+        assert!(matches!(fingerprint("AAAA"), Err(IdentityError::BadPubLen(3))));
+    }
+
+    #[test]
+    fn truncate_chars_respects_char_boundaries() {
+        assert_eq!(truncate_chars("hello", 3), "hel");
+        assert_eq!(truncate_chars("hi", 10), "hi");
+        assert_eq!(truncate_chars("héllo", 2), "hé");
+        assert_eq!(truncate_chars("🦀🦀🦀", 1), "🦀");
+        assert_eq!(truncate_chars("", 4), "");
+    }
 }
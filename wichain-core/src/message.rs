@@ -24,19 +24,32 @@ use crate::{encode_pubkey_b64, decode_pubkey_b64};
 /// - `id`: random UUID per message.
 /// - `from`: base64 sender public key (32 bytes).
 /// - `to`: optional recipient pubkey (base64) for future direct mode; empty = broadcast.
+/// - `to_many`: several recipients sharing one signature (multi‑recipient mode;
+///   `to` is then `None`).
 /// - `timestamp_ms`: sender clock (millis since UNIX epoch) for ordering UX; not trusted consensus.
 /// - `content`: message body text (UTF‑8).
-/// - `sig`: base64(64 bytes) Ed25519 signature over canonical digest.
+/// - `expires_at_ms`: optional expiry (millis since UNIX epoch) for ephemeral chats.
+/// - `reply_to`: optional id of the message this one replies to (threads).
+/// - `sig`: base64(64 bytes) Ed25519 signature over [`SignedMessage::canonical_bytes`].
 ///
-/// Digest = SHA256( id || from || to || timestamp_ms || content_bytes )
+/// Messages signed before canonical bytes were introduced signed
+/// `SHA256( id || from || to || timestamp_ms || content_bytes )`; `verify`
+/// still accepts those for messages without an expiry, parent or `to_many`. That digest also
+/// remains the Merkle leaf.
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct SignedMessage {
     pub id: String,
     pub from: String,
     #[serde(default)]
     pub to: Option<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub to_many: Vec<String>,
     pub timestamp_ms: u64,
     pub content: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub expires_at_ms: Option<u64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub reply_to: Option<String>,
     pub sig: String,
 }
 
@@ -48,21 +61,35 @@ impl SignedMessage {
         to: Option<String>,
         timestamp_ms: u64,
     ) -> Self {
-        let id = Uuid::new_v4().to_string();
-        let from = encode_pubkey_b64(&signing_key.verifying_key().to_bytes());
-        let digest_bytes = Self::digest_bytes_static(&id, &from, to.as_deref(), timestamp_ms, &content);
-        let sig = signing_key.sign(&digest_bytes);
-        let sig_b64 = general_purpose::STANDARD.encode(sig.to_bytes());
+        Self { to, ..Self::unsigned(content, signing_key, timestamp_ms) }.signed(signing_key)
+    }
+
+    /// A message from `signing_key` with a fresh id and no optional fields,
+    /// not yet signed. Constructors set their fields on it, then sign once.
+    fn unsigned(content: String, signing_key: &SigningKey, timestamp_ms: u64) -> Self {
         Self {
-            id,
-            from,
-            to,
+            id: Uuid::new_v4().to_string(),
+            from: encode_pubkey_b64(&signing_key.verifying_key().to_bytes()),
+            to: None,
+            to_many: Vec::new(),
             timestamp_ms,
             content,
-            sig: sig_b64,
+            expires_at_ms: None,
+            reply_to: None,
+            sig: String::new(),
         }
     }
 
+    fn signed(mut self, signing_key: &SigningKey) -> Self {
+        self.sign(signing_key);
+        self
+    }
+
+    fn sign(&mut self, signing_key: &SigningKey) {
+        let sig = signing_key.sign(&self.canonical_bytes());
+        self.sig = general_purpose::STANDARD.encode(sig.to_bytes());
+    }
+
     /// Convenience: create with current system time (best‑effort; not trusted).
     pub fn new_now(content: String, signing_key: &SigningKey, to: Option<String>) -> Self {
         let ts = std::time::SystemTime::now()
@@ -72,6 +99,58 @@ impl SignedMessage {
         Self::new(content, signing_key, to, ts)
     }
 
+    /// Create + sign a message that expires at `expires_at_ms`.
+    pub fn new_expiring(
+        content: String,
+        signing_key: &SigningKey,
+        to: Option<String>,
+        timestamp_ms: u64,
+        expires_at_ms: u64,
+    ) -> Self {
+        Self {
+            to,
+            expires_at_ms: Some(expires_at_ms),
+            ..Self::unsigned(content, signing_key, timestamp_ms)
+        }
+        .signed(signing_key)
+    }
+
+    /// Create + sign a reply to the message with id `reply_to`.
+    pub fn new_reply(
+        content: String,
+        signing_key: &SigningKey,
+        to: Option<String>,
+        timestamp_ms: u64,
+        reply_to: String,
+    ) -> Self {
+        Self {
+            to,
+            reply_to: Some(reply_to),
+            ..Self::unsigned(content, signing_key, timestamp_ms)
+        }
+        .signed(signing_key)
+    }
+
+    /// Create + sign one message for several recipients.
+    pub fn new_multi(
+        content: String,
+        signing_key: &SigningKey,
+        to_many: Vec<String>,
+        timestamp_ms: u64,
+    ) -> Self {
+        Self { to_many, ..Self::unsigned(content, signing_key, timestamp_ms) }.signed(signing_key)
+    }
+
+    /// Whether `pubkey_b64` is the recipient or one of `to_many`.
+    pub fn is_addressed_to(&self, pubkey_b64: &str) -> bool {
+        self.to.as_deref() == Some(pubkey_b64) || self.to_many.iter().any(|t| t == pubkey_b64)
+    }
+
+    /// Whether the message has an expiry at or before `now_ms`.
+    pub fn is_expired(&self, now_ms: u64) -> bool {
+        self.expires_at_ms.is_some_and(|at| at <= now_ms)
+    }
+
     /// Verify signature.
     pub fn verify(&self) -> bool {
         // decode sender pubkey
@@ -97,13 +176,65 @@ impl SignedMessage {
             Ok(s) => s,
             Err(_) => return false,
         };
-        // digest
-        let digest_bytes =
-            Self::digest_bytes_static(&self.id, &self.from, self.to.as_deref(), self.timestamp_ms, &self.content);
-        vk.verify(&digest_bytes, &sig).is_ok()
+        if vk.verify(&self.canonical_bytes(), &sig).is_ok() {
+            return true;
+        }
+        // older peers signed the concatenated digest, which can't carry an
+        // expiry, a parent or several recipients
+        if self.expires_at_ms.is_some() || self.reply_to.is_some() || !self.to_many.is_empty() {
+            return false;
+        }
+        if vk.verify(&self.digest_bytes(), &sig).is_ok() {
+            return true;
+        }
+        // converted v0 messages (`LegacyMessageJson::into_signed`) keep their
+        // signature over SHA256(content || id)
+        self.to.is_none() && self.timestamp_ms == 0 && verifies_v0(&vk, &self.content, &self.id, &sig)
+    }
+
+    /// Stable encoding of the signed fields, independent of how the message
+    /// was serialized: a version tag, then each field as a big‑endian u64
+    /// length followed by its bytes (`to` gets a presence byte first). Newer
+    /// optional fields (expiry, parent, `to_many`) are appended behind a tag byte only
+    /// when set, so messages without them encode (and verify) exactly as
+    /// before those fields existed.
+    pub fn canonical_bytes(&self) -> Vec<u8> {
+        fn field(out: &mut Vec<u8>, bytes: &[u8]) {
+            out.extend_from_slice(&(bytes.len() as u64).to_be_bytes());
+            out.extend_from_slice(bytes);
+        }
+        let mut out = Vec::with_capacity(64 + self.id.len() + self.from.len() + self.content.len());
+        field(&mut out, b"wichain-msg-v1");
+        field(&mut out, self.id.as_bytes());
+        field(&mut out, self.from.as_bytes());
+        match &self.to {
+            Some(to) => {
+                out.push(1);
+                field(&mut out, to.as_bytes());
+            }
+            None => out.push(0),
+        }
+        out.extend_from_slice(&self.timestamp_ms.to_be_bytes());
+        field(&mut out, self.content.as_bytes());
+        if let Some(at) = self.expires_at_ms {
+            out.push(b'e');
+            out.extend_from_slice(&at.to_be_bytes());
+        }
+        if let Some(parent) = &self.reply_to {
+            out.push(b'r');
+            field(&mut out, parent.as_bytes());
+        }
+        if !self.to_many.is_empty() {
+            out.push(b'm');
+            out.extend_from_slice(&(self.to_many.len() as u64).to_be_bytes());
+            for to in &self.to_many {
+                field(&mut out, to.as_bytes());
+            }
+        }
+        out
     }
 
-    /// Compute the message digest used for signing.
+    /// Legacy signing digest; still the Merkle leaf for message blocks.
     fn digest_bytes_static(
         id: &str,
         from: &str,
@@ -123,12 +254,28 @@ impl SignedMessage {
         out.into()
     }
 
-    /// Return the canonical digest for this instance.
+    /// Return the (legacy) digest for this instance.
     pub fn digest_bytes(&self) -> [u8; 32] {
         Self::digest_bytes_static(&self.id, &self.from, self.to.as_deref(), self.timestamp_ms, &self.content)
     }
 }
 
+/// Digest signed by v0 messages: SHA256(content || id).
+fn legacy_v0_digest(content: &str, id: &str) -> [u8; 32] {
+    let mut hasher = Sha256::new();
+    hasher.update(content.as_bytes());
+    hasher.update(id.as_bytes());
+    hasher.finalize().into()
+}
+
+/// Does `sig` sign the v0 digest of `content` and `id`? Nothing marks where
+/// content ends and id begins in that digest, so the id must be a hyphenated
+/// UUID (always 36 bytes, as v0 ids were); any other length would let bytes
+/// move between the two fields under the same signature.
+fn verifies_v0(vk: &VerifyingKey, content: &str, id: &str, sig: &Signature) -> bool {
+    id.len() == 36 && Uuid::try_parse(id).is_ok() && vk.verify(&legacy_v0_digest(content, id), sig).is_ok()
+}
+
 /// Legacy v0 JSON message shape (for backward compatibility).
 ///
 /// ```json
@@ -143,7 +290,8 @@ pub struct LegacyMessageJson {
 }
 
 impl LegacyMessageJson {
-    /// Attempt to verify the legacy message signature (old digest: SHA256(content || id)).
+    /// Attempt to verify the legacy message signature (old digest: SHA256(content || id),
+    /// with a UUID `id`).
     pub fn verify(&self) -> bool {
         // decode pubkey
         let pk = match crate::decode_pubkey_b64(&self.sender) {
@@ -167,16 +315,13 @@ impl LegacyMessageJson {
             Ok(s) => s,
             Err(_) => return false,
         };
-        // digest (legacy)
-        let mut hasher = Sha256::new();
-        hasher.update(self.content.as_bytes());
-        hasher.update(self.id.as_bytes());
-        let digest = hasher.finalize();
-        vk.verify(&digest, &sig).is_ok()
+        verifies_v0(&vk, &self.content, &self.id, &sig)
     }
 
     /// Convert (if verified) into a `SignedMessage` approx. representation.
-    /// Legacy format has no timestamp or recipient; we fill with best‑effort defaults.
+    /// Legacy format has no timestamp or recipient; we fill with best‑effort
+    /// defaults. The result keeps the v0 signature, which `SignedMessage::verify`
+    /// accepts for exactly this shape.
     pub fn into_signed(self) -> Option<SignedMessage> {
         if !self.verify() {
             return None;
@@ -185,8 +330,11 @@ impl LegacyMessageJson {
             id: self.id,
             from: self.sender,
             to: None,
+            to_many: Vec::new(),
             timestamp_ms: 0,
             content: self.content,
+            expires_at_ms: None,
+            reply_to: None,
             sig: self.signature,
         })
     }
@@ -208,6 +356,114 @@ mod tests {
         assert!(m.verify());
     }
 
+    #[test]
+    fn verifies_after_reordered_reserialization() {
+        let sk = generate_key();
+        let m = SignedMessage::new_now("hello \"quoted\" ✓".into(), &sk, Some("bob".into()));
+        let v: serde_json::Value = serde_json::to_value(&m).unwrap();
+        // Rebuild the JSON with the fields reversed and pretty-printed.
+        let obj = v.as_object().unwrap();
+        let reordered: Vec<String> = obj
+            .iter()
+            .rev()
+            .map(|(k, v)| format!("\n   {}  :  {}", serde_json::to_string(k).unwrap(), v))
+            .collect();
+        let json = format!("{{{}\n}}", reordered.join(","));
+        assert!(json.find("\"sig\"").unwrap() < json.find("\"id\"").unwrap());
+        let parsed: SignedMessage = serde_json::from_str(&json).unwrap();
+        assert_eq!(parsed.canonical_bytes(), m.canonical_bytes());
+        assert!(parsed.verify());
+
+        let mut tampered = parsed.clone();
+        tampered.to = None;
+        assert!(!tampered.verify());
+    }
+
+    #[test]
+    fn old_digest_signatures_still_verify() {
+        let sk = generate_key();
+        let mut m = SignedMessage::new_now("from an older peer".into(), &sk, None);
+        m.sig = general_purpose::STANDARD.encode(sk.sign(&m.digest_bytes()).to_bytes());
+        assert!(m.verify());
+        m.content.push('!');
+        assert!(!m.verify());
+    }
+
+    #[test]
+    fn expiry_is_signed_and_checked() {
+        let sk = generate_key();
+        let m = SignedMessage::new_expiring("poof".into(), &sk, None, 1_000, 5_000);
+        assert!(m.verify());
+        assert!(!m.is_expired(4_999));
+        assert!(m.is_expired(5_000));
+
+        let mut extended = m.clone();
+        extended.expires_at_ms = Some(9_000);
+        assert!(!extended.verify());
+        let mut stripped = m.clone();
+        stripped.expires_at_ms = None;
+        assert!(!stripped.verify());
+
+        // Messages serialized before the field existed still parse and verify.
+        let plain = SignedMessage::new("kept".into(), &sk, None, 1_000);
+        let json = serde_json::to_string(&plain).unwrap();
+        assert!(!json.contains("expires_at_ms"));
+        let parsed: SignedMessage = serde_json::from_str(&json).unwrap();
+        assert!(parsed.verify());
+        assert!(!parsed.is_expired(u64::MAX));
+
+        // A legacy-digest signature can't be given an expiry after the fact.
+        let mut old = plain.clone();
+        old.sig = general_purpose::STANDARD.encode(sk.sign(&old.digest_bytes()).to_bytes());
+        assert!(old.verify());
+        old.expires_at_ms = Some(2_000);
+        assert!(!old.verify());
+    }
+
+    #[test]
+    fn reply_to_is_signed() {
+        let sk = generate_key();
+        let parent = SignedMessage::new("root".into(), &sk, None, 1);
+        let reply = SignedMessage::new_reply("re".into(), &sk, None, 2, parent.id.clone());
+        assert!(reply.verify());
+        assert_eq!(reply.reply_to.as_deref(), Some(parent.id.as_str()));
+
+        let mut moved = reply.clone();
+        moved.reply_to = Some("some-other-thread".into());
+        assert!(!moved.verify());
+        let mut detached = reply.clone();
+        detached.reply_to = None;
+        assert!(!detached.verify());
+
+        // An empty parent and a zero expiry must not encode the same way.
+        let mut a = parent.clone();
+        a.reply_to = Some(String::new());
+        let mut b = parent.clone();
+        b.expires_at_ms = Some(0);
+        assert_ne!(a.canonical_bytes(), b.canonical_bytes());
+    }
+
+    #[test]
+    fn to_many_is_signed() {
+        let sk = generate_key();
+        let m = SignedMessage::new_multi("all of you".into(), &sk, vec!["bob".into(), "carol".into()], 1);
+        assert!(m.verify());
+        assert!(m.is_addressed_to("bob") && m.is_addressed_to("carol"));
+        assert!(!m.is_addressed_to("mallory"));
+
+        let mut widened = m.clone();
+        widened.to_many.push("mallory".into());
+        assert!(!widened.verify());
+        let mut merged = m.clone();
+        merged.to_many = vec!["bobcarol".into()];
+        assert!(!merged.verify());
+
+        // Single-recipient messages don't serialize the field at all.
+        let single = SignedMessage::new("hi".into(), &sk, Some("bob".into()), 1);
+        assert!(!serde_json::to_string(&single).unwrap().contains("to_many"));
+        assert!(single.is_addressed_to("bob"));
+    }
+
     #[test]
     fn legacy_message_verify() {
         // Build a legacy message and confirm conversion works.
@@ -231,9 +487,27 @@ mod tests {
         };
         assert!(legacy.verify());
         let sm = legacy.into_signed().unwrap();
-        // For legacy -> SignedMessage we don't assert verify() because digest scheme differs; we
-        // just assert conversion worked.
-        let _ = sm;
+        assert!(sm.verify());
+        // The v0 digest doesn't cover recipient or time; those must stay unset.
+        let mut addressed = sm.clone();
+        addressed.to = Some("bob".into());
+        assert!(!addressed.verify());
+        let mut dated = sm.clone();
+        dated.timestamp_ms = 1;
+        assert!(!dated.verify());
+
+        // Nothing separates content from id in the digest: moving a byte
+        // across keeps it, but the id is no longer a 36-byte UUID.
+        let mut shifted_in = sm.clone();
+        shifted_in.content.pop();
+        shifted_in.id = format!("t{}", sm.id);
+        let mut shifted_out = sm.clone();
+        shifted_out.content.push_str(&sm.id[..1]);
+        shifted_out.id = sm.id[1..].to_string();
+        for forged in [shifted_in, shifted_out] {
+            assert_eq!(legacy_v0_digest(&forged.content, &forged.id), legacy_v0_digest(&sm.content, &sm.id));
+            assert!(!forged.verify());
+        }
     }
 }
 // message.rs
\ No newline at end of file
@@ -0,0 +1,114 @@
+//! XOR obfuscation for peers that can't do AES-256-GCM.
+//!
+//! **This is obfuscation, not encryption.** The mask is derived from the two
+//! peers' *public* keys, which everyone on the LAN has seen, and there is no
+//! integrity check. All it does is keep payloads from being readable at a
+//! glance in a packet capture. Use AES-256-GCM whenever the peer supports it.
+//!
+//! Payloads travel as a versioned [`ObfuscatedEnvelope`],
+//! `{"obf":1,"data":"<base64>"}`, so receivers can tell it apart from
+//! encrypted payloads and later schemes can be told apart by version.
+//!
+//! Version 1 XORs the message with SHA-512 blocks over the sorted pubkey pair
+//! and a block counter, so both directions of a conversation use the same
+//! mask and it doesn't repeat every 64 bytes.
+
+use base64::{engine::general_purpose, Engine as _};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha512};
+use thiserror::Error;
+
+/// Envelope version `obfuscate` writes.
+pub const OBFUSCATION_VERSION: u8 = 1;
+
+/// Wire form of an obfuscated payload.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ObfuscatedEnvelope {
+    /// Scheme version (see [`OBFUSCATION_VERSION`]).
+    pub obf: u8,
+    /// Base64 of the masked message bytes.
+    pub data: String,
+}
+
+#[derive(Debug, Error)]
+pub enum ObfuscationError {
+    #[error("not an obfuscated envelope: {0}")]
+    NotAnEnvelope(#[from] serde_json::Error),
+    #[error("unsupported obfuscation version {0}")]
+    UnsupportedVersion(u8),
+    #[error("invalid base64: {0}")]
+    Base64(#[from] base64::DecodeError),
+    #[error("unmasked payload is not UTF-8 (wrong key pair?)")]
+    NotUtf8,
+}
+
+/// XOR `data` with the version 1 mask for the pair `pub_a`/`pub_b`, in place.
+fn apply_mask(data: &mut [u8], pub_a: &str, pub_b: &str) {
+    let (lo, hi) = if pub_a <= pub_b { (pub_a, pub_b) } else { (pub_b, pub_a) };
+    for (counter, chunk) in data.chunks_mut(64).enumerate() {
+        let mut hasher = Sha512::new();
+        hasher.update(b"wichain-obf-v1|");
+        hasher.update(lo.as_bytes());
+        hasher.update(b"|");
+        hasher.update(hi.as_bytes());
+        hasher.update((counter as u64).to_be_bytes());
+        let mask = hasher.finalize();
+        for (byte, m) in chunk.iter_mut().zip(mask.iter()) {
+            *byte ^= m;
+        }
+    }
+}
+
+/// Mask `message` for the conversation between `my_pub` and `peer_pub`;
+/// returns the serialized envelope.
+pub fn obfuscate(message: &str, my_pub: &str, peer_pub: &str) -> String {
+    let mut data = message.as_bytes().to_vec();
+    apply_mask(&mut data, my_pub, peer_pub);
+    let envelope = ObfuscatedEnvelope {
+        obf: OBFUSCATION_VERSION,
+        data: general_purpose::STANDARD.encode(data),
+    };
+    serde_json::to_string(&envelope).expect("serialize obfuscated envelope")
+}
+
+/// Undo `obfuscate` for a payload exchanged between `my_pub` and `peer_pub`.
+pub fn deobfuscate(payload: &str, my_pub: &str, peer_pub: &str) -> Result<String, ObfuscationError> {
+    let envelope: ObfuscatedEnvelope = serde_json::from_str(payload)?;
+    if envelope.obf != OBFUSCATION_VERSION {
+        return Err(ObfuscationError::UnsupportedVersion(envelope.obf));
+    }
+    let mut data = general_purpose::STANDARD.decode(&envelope.data)?;
+    apply_mask(&mut data, my_pub, peer_pub);
+    String::from_utf8(data).map_err(|_| ObfuscationError::NotUtf8)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn round_trips_between_the_two_peers_only() {
+        let (alice, bob, eve) = ("QUxJQ0U=", "Qk9C", "RVZF");
+        let message = format!("{{\"text\":\"héllo ✓ {}\"}}", "x".repeat(200));
+
+        let wire = obfuscate(&message, alice, bob);
+        let envelope: ObfuscatedEnvelope = serde_json::from_str(&wire).unwrap();
+        assert_eq!(envelope.obf, 1);
+        assert!(!wire.contains("llo"), "masked");
+        // Both ends derive the same mask, whichever way round.
+        assert_eq!(deobfuscate(&wire, bob, alice).unwrap(), message);
+        assert_eq!(deobfuscate(&wire, alice, bob).unwrap(), message);
+        assert_ne!(deobfuscate(&wire, eve, alice).ok().as_deref(), Some(message.as_str()));
+        assert_eq!(deobfuscate(&obfuscate("", alice, bob), bob, alice).unwrap(), "");
+    }
+
+    #[test]
+    fn rejects_other_payloads_and_versions() {
+        assert!(matches!(deobfuscate("not json", "a", "b"), Err(ObfuscationError::NotAnEnvelope(_))));
+        assert!(matches!(
+            deobfuscate(r#"{"obf":2,"data":""}"#, "a", "b"),
+            Err(ObfuscationError::UnsupportedVersion(2))
+        ));
+        assert!(matches!(deobfuscate(r#"{"obf":1,"data":"%%"}"#, "a", "b"), Err(ObfuscationError::Base64(_))));
+    }
+}
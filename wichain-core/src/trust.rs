@@ -6,11 +6,21 @@
 //!
 //! Use [`TrustManager::snapshot()`] to produce a UI‑friendly vector of
 //! serializable peer trust records.
+//!
+//! Decay is measured from the score a peer had when last seen, so repeated
+//! `decay_trust` calls don't compound. [`TrustManager::save_to_file`] stores
+//! `last_seen` as wall‑clock time; after a reload the time the app was closed
+//! counts as inactivity too.
 
 use std::collections::HashMap;
-use std::time::{Duration, Instant};
+use std::fs;
+use std::path::Path;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
 use serde::{Serialize, Deserialize};
 
+/// Score a newly tracked peer starts with.
+pub const NEUTRAL_TRUST: f64 = 50.0;
+
 /// Internal representation of a peer tracked for trust.
 #[derive(Debug, Clone)]
 pub struct Peer {
@@ -19,6 +29,10 @@ pub struct Peer {
     pub public_key: String,  // base64 pubkey (string form for convenience)
     pub trust_score: f64,    // 0..100
     pub last_seen: Instant,
+    /// Pinned peers neither decay nor get purged.
+    pub pinned: bool,
+    /// `trust_score` as of `last_seen`, before any inactivity decay.
+    score_at_last_seen: f64,
 }
 
 impl Peer {
@@ -27,10 +41,97 @@ impl Peer {
             id,
             alias,
             public_key,
-            trust_score: 50.0, // neutral starting trust
+            trust_score: NEUTRAL_TRUST,
             last_seen: Instant::now(),
+            pinned: false,
+            score_at_last_seen: NEUTRAL_TRUST,
         }
     }
+
+    /// Mark the peer active now, keeping the decay applied so far.
+    fn touch(&mut self) {
+        self.score_at_last_seen = self.trust_score;
+        self.last_seen = Instant::now();
+    }
+}
+
+/// How inactivity erodes trust.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum DecayMode {
+    /// Lose `decay_per_hour` points per hour.
+    #[default]
+    Linear,
+    /// Close the gap to `floor` by a factor of `exp(-decay_per_hour * hours)`.
+    Exponential,
+}
+
+/// Scoring parameters for a [`TrustManager`].
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct TrustConfig {
+    /// Points added by [`TrustManager::reward`].
+    pub reward: f64,
+    /// Points removed by [`TrustManager::penalize`].
+    pub penalty: f64,
+    /// Points per hour (linear) or rate constant `k` per hour (exponential).
+    pub decay_per_hour: f64,
+    pub decay_mode: DecayMode,
+    /// Scores stay within `floor..=ceiling`; exponential decay tends to `floor`.
+    pub floor: f64,
+    pub ceiling: f64,
+}
+
+impl Default for TrustConfig {
+    fn default() -> Self {
+        Self {
+            reward: 5.0,
+            penalty: 10.0,
+            decay_per_hour: 1.0,
+            decay_mode: DecayMode::Linear,
+            floor: 0.0,
+            ceiling: 100.0,
+        }
+    }
+}
+
+impl TrustConfig {
+    fn clamp(&self, score: f64) -> f64 {
+        score.clamp(self.floor, self.ceiling)
+    }
+
+    /// `score` after `elapsed` of inactivity.
+    fn decayed(&self, score: f64, elapsed: Duration) -> f64 {
+        let hours = elapsed.as_secs_f64() / 3600.0;
+        match self.decay_mode {
+            DecayMode::Linear => self.clamp(score - self.decay_per_hour * hours),
+            DecayMode::Exponential => {
+                self.clamp(self.floor + (score - self.floor) * (-self.decay_per_hour * hours).exp())
+            }
+        }
+    }
+}
+
+/// On-disk form of a tracked peer.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct StoredPeer {
+    id: String,
+    alias: String,
+    public_key: String,
+    /// Score as of `last_seen_unix_ms` (decay is re-derived on load).
+    trust_score: f64,
+    last_seen_unix_ms: u64,
+    #[serde(default)]
+    pinned: bool,
+}
+
+/// On-disk form of a `TrustManager`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct StoredTrust {
+    decay_rate_per_hour: f64,
+    /// Absent in files written before `TrustConfig`; those were linear.
+    #[serde(default)]
+    config: Option<TrustConfig>,
+    drop_after_secs: u64,
+    peers: Vec<StoredPeer>,
 }
 
 /// Serializable snapshot for UI / Tauri (since `Instant` isn’t serializable).
@@ -42,23 +143,37 @@ pub struct PeerTrustSnapshot {
     pub trust_score: f64,
     /// Seconds since last seen (approx).
     pub last_seen_secs: f64,
+    #[serde(default)]
+    pub pinned: bool,
 }
 
 pub struct TrustManager {
     peers: HashMap<String, Peer>, // keyed by peer id
-    decay_rate_per_hour: f64,     // trust points lost per hour of inactivity
+    config: TrustConfig,
     drop_after: Duration,         // remove peer if unseen this long
 }
 
 impl TrustManager {
+    /// Default scoring with linear decay of `decay_rate_per_hour` points/hour.
     pub fn new(decay_rate_per_hour: f64) -> Self {
+        Self::with_config(TrustConfig {
+            decay_per_hour: decay_rate_per_hour,
+            ..TrustConfig::default()
+        })
+    }
+
+    pub fn with_config(config: TrustConfig) -> Self {
         Self {
             peers: HashMap::new(),
-            decay_rate_per_hour,
+            config,
             drop_after: Duration::from_secs(24 * 3600), // default 24h retention
         }
     }
 
+    pub fn config(&self) -> &TrustConfig {
+        &self.config
+    }
+
     /// Set how long to keep unseen peers before purging.
     pub fn set_drop_after(&mut self, dur: Duration) {
         self.drop_after = dur;
@@ -71,10 +186,13 @@ impl TrustManager {
                 // update alias/public_key if changed, refresh last_seen
                 p.alias = alias;
                 p.public_key = public_key;
-                p.last_seen = Instant::now();
+                p.touch();
             }
             None => {
-                self.peers.insert(id.clone(), Peer::new(id, alias, public_key));
+                let mut peer = Peer::new(id.clone(), alias, public_key);
+                peer.trust_score = self.config.clamp(peer.trust_score);
+                peer.score_at_last_seen = peer.trust_score;
+                self.peers.insert(id, peer);
             }
         }
     }
@@ -82,27 +200,69 @@ impl TrustManager {
     /// Adjust trust by `delta` (positive = reward, negative = penalty).
     pub fn update_trust(&mut self, id: &str, delta: f64) {
         if let Some(peer) = self.peers.get_mut(id) {
-            peer.trust_score = (peer.trust_score + delta).clamp(0.0, 100.0);
-            peer.last_seen = Instant::now();
+            peer.trust_score = self.config.clamp(peer.trust_score + delta);
+            peer.touch();
         }
     }
 
+    /// Credit `id` with the configured reward.
+    pub fn reward(&mut self, id: &str) {
+        self.update_trust(id, self.config.reward);
+    }
+
+    /// Dock `id` the configured penalty.
+    pub fn penalize(&mut self, id: &str) {
+        self.update_trust(id, -self.config.penalty);
+    }
+
     /// Called periodically (or before snapshot) to decay inactive peers.
     pub fn decay_trust(&mut self) {
         let now = Instant::now();
         self.peers.retain(|_, peer| {
+            if peer.pinned {
+                return true;
+            }
             let elapsed = now.duration_since(peer.last_seen);
             if elapsed > self.drop_after {
                 // drop stale peer
                 return false;
             }
-            let hours = elapsed.as_secs_f64() / 3600.0;
-            let decay = self.decay_rate_per_hour * hours;
-            peer.trust_score = (peer.trust_score - decay).clamp(0.0, 100.0);
+            peer.trust_score = self.config.decayed(peer.score_at_last_seen, elapsed);
             true
         });
     }
 
+    /// Exempt `id` from decay and purging, keeping its current score.
+    /// Returns `false` for an unknown peer.
+    pub fn pin(&mut self, id: &str) -> bool {
+        self.set_pinned(id, true)
+    }
+
+    /// Let `id` decay again, starting from now. Returns `false` for an unknown peer.
+    pub fn unpin(&mut self, id: &str) -> bool {
+        self.set_pinned(id, false)
+    }
+
+    fn set_pinned(&mut self, id: &str, pinned: bool) -> bool {
+        let Some(peer) = self.peers.get_mut(id) else {
+            return false;
+        };
+        peer.pinned = pinned;
+        peer.touch();
+        true
+    }
+
+    /// Override `id`'s score (clamped to the configured bounds). Returns
+    /// `false` for an unknown peer.
+    pub fn set_score(&mut self, id: &str, score: f64) -> bool {
+        let Some(peer) = self.peers.get_mut(id) else {
+            return false;
+        };
+        peer.trust_score = self.config.clamp(score);
+        peer.touch();
+        true
+    }
+
     /// Retrieve trust score (before decay).
     pub fn get_score(&self, id: &str) -> Option<f64> {
         self.peers.get(id).map(|p| p.trust_score)
@@ -120,10 +280,122 @@ impl TrustManager {
                 public_key: p.public_key.clone(),
                 trust_score: p.trust_score,
                 last_seen_secs: now.duration_since(p.last_seen).as_secs_f64(),
+                pinned: p.pinned,
+            })
+            .collect()
+    }
+
+    /// All tracked peers and settings as JSON, with wall‑clock `last_seen`.
+    /// Each score is the one the peer had when last seen; importing re-derives decay.
+    pub fn export_json(&self) -> String {
+        let now = Instant::now();
+        let wall_now = SystemTime::now();
+        let peers = self
+            .peers
+            .values()
+            .map(|p| {
+                let seen = wall_now
+                    .checked_sub(now.duration_since(p.last_seen))
+                    .unwrap_or(UNIX_EPOCH);
+                StoredPeer {
+                    id: p.id.clone(),
+                    alias: p.alias.clone(),
+                    public_key: p.public_key.clone(),
+                    trust_score: p.score_at_last_seen,
+                    last_seen_unix_ms: seen.duration_since(UNIX_EPOCH).map(|d| d.as_millis() as u64).unwrap_or(0),
+                    pinned: p.pinned,
+                }
+            })
+            .collect();
+        let stored = StoredTrust {
+            decay_rate_per_hour: self.config.decay_per_hour,
+            config: Some(self.config.clone()),
+            drop_after_secs: self.drop_after.as_secs(),
+            peers,
+        };
+        serde_json::to_string_pretty(&stored).expect("trust data serializes")
+    }
+
+    /// Load peers from `export_json` output, keeping this manager's settings.
+    /// `merge = false` replaces every tracked peer; `merge = true` keeps
+    /// existing ones and, for peers on both sides, takes the higher score and
+    /// the more recent `last_seen`. Invalid input changes nothing. Returns the
+    /// number of peers read.
+    pub fn import_json(&mut self, json: &str, merge: bool) -> anyhow::Result<usize> {
+        let stored: StoredTrust =
+            serde_json::from_str(json).map_err(|e| anyhow::anyhow!("invalid trust data: {e}"))?;
+        let imported = self.peers_from_stored(stored.peers);
+        let count = imported.len();
+        if !merge {
+            self.peers.clear();
+        }
+        for peer in imported {
+            match self.peers.get_mut(&peer.id) {
+                Some(existing) => {
+                    if peer.last_seen > existing.last_seen {
+                        existing.alias = peer.alias;
+                        existing.public_key = peer.public_key;
+                        existing.last_seen = peer.last_seen;
+                    }
+                    existing.score_at_last_seen = existing.score_at_last_seen.max(peer.score_at_last_seen);
+                    existing.trust_score = existing.score_at_last_seen;
+                    existing.pinned |= peer.pinned;
+                }
+                None => {
+                    self.peers.insert(peer.id.clone(), peer);
+                }
+            }
+        }
+        self.decay_trust();
+        Ok(count)
+    }
+
+    /// Each `last_seen` becomes an `Instant` that far in the past, so offline
+    /// time decays trust too; timestamps in the future (clock changes) count
+    /// as "just now".
+    fn peers_from_stored(&self, stored: Vec<StoredPeer>) -> Vec<Peer> {
+        let now = Instant::now();
+        let wall_now = SystemTime::now();
+        stored
+            .into_iter()
+            .map(|p| {
+                let seen = UNIX_EPOCH + Duration::from_millis(p.last_seen_unix_ms);
+                let ago = wall_now.duration_since(seen).unwrap_or_default();
+                let score = self.config.clamp(p.trust_score);
+                Peer {
+                    id: p.id,
+                    alias: p.alias,
+                    public_key: p.public_key,
+                    trust_score: score,
+                    last_seen: now.checked_sub(ago).unwrap_or(now),
+                    pinned: p.pinned,
+                    score_at_last_seen: score,
+                }
             })
             .collect()
     }
 
+    /// Write all tracked peers to `path` (see `export_json`).
+    pub fn save_to_file(&self, path: impl AsRef<Path>) -> anyhow::Result<()> {
+        fs::write(path, self.export_json())?;
+        Ok(())
+    }
+
+    /// Load a manager written by `save_to_file`, settings included.
+    pub fn load_from_file(path: impl AsRef<Path>) -> anyhow::Result<Self> {
+        let stored: StoredTrust = serde_json::from_str(&fs::read_to_string(path)?)?;
+        let mut tm = match stored.config {
+            Some(config) => Self::with_config(config),
+            None => Self::new(stored.decay_rate_per_hour),
+        };
+        tm.drop_after = Duration::from_secs(stored.drop_after_secs);
+        for peer in tm.peers_from_stored(stored.peers) {
+            tm.peers.insert(peer.id.clone(), peer);
+        }
+        tm.decay_trust();
+        Ok(tm)
+    }
+
     /// Iterator over internal (non‑serializable) peers (debug/testing).
     pub fn peers(&self) -> impl Iterator<Item = &Peer> {
         self.peers.values()
@@ -174,4 +446,159 @@ mod tests {
         assert_eq!(snap.len(), 1);
         assert_eq!(snap[0].alias, "Alice");
     }
+
+    #[test]
+    fn repeated_decay_does_not_compound() {
+        let mut tm = TrustManager::new(10.0);
+        tm.upsert_peer("peer1".into(), "Alice".into(), "pubkey1".into());
+        tm.peers.get_mut("peer1").unwrap().last_seen -= Duration::from_secs(3600);
+        tm.decay_trust();
+        tm.decay_trust();
+        assert!((tm.get_score("peer1").unwrap() - 40.0).abs() < 1e-6);
+    }
+
+    #[test]
+    fn save_and_load_keep_decay_across_restart() {
+        let path = std::env::temp_dir().join(format!("wichain-trust-{}.json", uuid::Uuid::new_v4()));
+        let mut tm = TrustManager::new(10.0);
+        tm.upsert_peer("peer1".into(), "Alice".into(), "pk1".into());
+        tm.update_trust("peer1", 20.0);
+        tm.peers.get_mut("peer1").unwrap().last_seen -= Duration::from_secs(1800);
+        tm.decay_trust();
+        assert!((tm.get_score("peer1").unwrap() - 65.0).abs() < 1e-3);
+        tm.save_to_file(&path).unwrap();
+
+        // Same score right after reload: decay is re-derived, not applied twice.
+        let loaded = TrustManager::load_from_file(&path).unwrap();
+        assert!((loaded.get_score("peer1").unwrap() - 65.0).abs() < 1e-3);
+        let peer = loaded.peers().next().unwrap();
+        assert_eq!(peer.alias, "Alice");
+        assert!(peer.last_seen.elapsed() >= Duration::from_secs(1799));
+
+        // An hour spent offline counts as inactivity.
+        let mut stored: StoredTrust = serde_json::from_str(&fs::read_to_string(&path).unwrap()).unwrap();
+        stored.peers[0].last_seen_unix_ms -= 3_600_000;
+        fs::write(&path, serde_json::to_string(&stored).unwrap()).unwrap();
+        let later = TrustManager::load_from_file(&path).unwrap();
+        assert!((later.get_score("peer1").unwrap() - 55.0).abs() < 1e-3);
+        let _ = fs::remove_file(&path);
+    }
+
+    #[test]
+    fn linear_vs_exponential_decay_after_an_hour() {
+        let hour_old = |decay_mode, decay_per_hour| {
+            let mut tm = TrustManager::with_config(TrustConfig {
+                decay_per_hour,
+                decay_mode,
+                floor: 20.0,
+                ..TrustConfig::default()
+            });
+            tm.upsert_peer("peer1".into(), "Alice".into(), "pk1".into());
+            tm.peers.get_mut("peer1").unwrap().last_seen -= Duration::from_secs(3600);
+            tm.decay_trust();
+            tm.get_score("peer1").unwrap()
+        };
+        // Linear: 50 - 10 = 40. Exponential: 20 + 30 * e^-0.5 ≈ 38.2.
+        assert!((hour_old(DecayMode::Linear, 10.0) - 40.0).abs() < 1e-6);
+        let expected = 20.0 + 30.0 * (-0.5f64).exp();
+        assert!((hour_old(DecayMode::Exponential, 0.5) - expected).abs() < 1e-6);
+    }
+
+    #[test]
+    fn exponential_decay_approaches_floor_and_config_bounds_scores() {
+        let mut tm = TrustManager::with_config(TrustConfig {
+            reward: 30.0,
+            penalty: 45.0,
+            decay_per_hour: 1.0,
+            decay_mode: DecayMode::Exponential,
+            floor: 10.0,
+            ceiling: 90.0,
+        });
+        tm.upsert_peer("peer1".into(), "Alice".into(), "pk1".into());
+        tm.reward("peer1");
+        tm.reward("peer1");
+        assert_eq!(tm.get_score("peer1"), Some(90.0));
+        tm.penalize("peer1");
+        assert_eq!(tm.get_score("peer1"), Some(45.0));
+
+        tm.peers.get_mut("peer1").unwrap().last_seen -= Duration::from_secs(20 * 3600);
+        tm.decay_trust();
+        let s = tm.get_score("peer1").unwrap();
+        assert!(s > 10.0 && s - 10.0 < 1e-6, "near but not below the floor, got {s}");
+    }
+
+    #[test]
+    fn pinned_peer_keeps_score_and_survives_purge() {
+        let mut tm = TrustManager::new(10.0);
+        tm.set_drop_after(Duration::from_secs(2 * 3600));
+        tm.upsert_peer("friend".into(), "Alice".into(), "pk1".into());
+        tm.upsert_peer("other".into(), "Bob".into(), "pk2".into());
+        assert!(tm.set_score("friend", 80.0));
+        assert!(tm.pin("friend"));
+        assert!(!tm.pin("nobody"));
+
+        for p in tm.peers.values_mut() {
+            p.last_seen -= Duration::from_secs(3600);
+        }
+        tm.decay_trust();
+        assert_eq!(tm.get_score("friend"), Some(80.0));
+        assert!((tm.get_score("other").unwrap() - 40.0).abs() < 1e-6);
+
+        for p in tm.peers.values_mut() {
+            p.last_seen -= Duration::from_secs(3 * 3600);
+        }
+        let snap = tm.snapshot();
+        assert_eq!(snap.len(), 1, "unpinned peer purged");
+        assert!(snap[0].pinned && snap[0].id == "friend");
+
+        // Unpinning restarts decay from the pinned score.
+        assert!(tm.unpin("friend"));
+        tm.peers.get_mut("friend").unwrap().last_seen -= Duration::from_secs(3600);
+        tm.decay_trust();
+        assert!((tm.get_score("friend").unwrap() - 70.0).abs() < 1e-6);
+    }
+
+    #[test]
+    fn export_clear_import_round_trip() {
+        let mut tm = TrustManager::new(0.0);
+        tm.upsert_peer("peer1".into(), "Alice".into(), "pk1".into());
+        tm.upsert_peer("peer2".into(), "Bob".into(), "pk2".into());
+        tm.update_trust("peer1", 30.0);
+        tm.pin("peer2");
+        let json = tm.export_json();
+
+        let err = tm.import_json("{not json", false).unwrap_err();
+        assert!(err.to_string().contains("invalid trust data"), "{err}");
+        assert_eq!(tm.get_score("peer1"), Some(80.0), "failed import leaves state alone");
+
+        tm.import_json(r#"{"decay_rate_per_hour":0,"drop_after_secs":1,"peers":[]}"#, false).unwrap();
+        assert_eq!(tm.peers().count(), 0);
+        assert_eq!(tm.import_json(&json, false).unwrap(), 2);
+        assert_eq!(tm.get_score("peer1"), Some(80.0));
+        assert_eq!(tm.get_score("peer2"), Some(50.0));
+        assert!(tm.peers().any(|p| p.id == "peer2" && p.pinned));
+    }
+
+    #[test]
+    fn merge_import_keeps_higher_score_and_newer_sighting() {
+        let mut other = TrustManager::new(0.0);
+        other.upsert_peer("peer1".into(), "Alice (laptop)".into(), "pk1".into());
+        other.update_trust("peer1", 40.0);
+        other.upsert_peer("peer3".into(), "Carol".into(), "pk3".into());
+        let json = other.export_json();
+
+        let mut tm = TrustManager::new(0.0);
+        tm.upsert_peer("peer1".into(), "Alice".into(), "pk1".into());
+        tm.peers.get_mut("peer1").unwrap().last_seen -= Duration::from_secs(60);
+        tm.upsert_peer("peer2".into(), "Bob".into(), "pk2".into());
+        tm.update_trust("peer2", 10.0);
+
+        tm.import_json(&json, true).unwrap();
+        assert_eq!(tm.get_score("peer1"), Some(90.0));
+        let alice = tm.peers().find(|p| p.id == "peer1").unwrap();
+        assert_eq!(alice.alias, "Alice (laptop)");
+        assert!(alice.last_seen.elapsed() < Duration::from_secs(30));
+        assert_eq!(tm.get_score("peer2"), Some(60.0));
+        assert_eq!(tm.get_score("peer3"), Some(50.0));
+    }
 }
@@ -0,0 +1,95 @@
+//! Bounded memory of recently delivered direct messages, so retransmits and
+//! duplicated datagrams are forwarded to the app only once.
+//!
+//! Messages carrying a `msg_id` are keyed on `(from, msg_id)` and remembered
+//! until evicted (oldest first, `CAPACITY` entries). Legacy messages without
+//! an id are keyed on a hash of `(from, payload_json)` and only count as
+//! duplicates within `LEGACY_WINDOW`, since the same text may legitimately be
+//! sent twice.
+
+use std::collections::hash_map::DefaultHasher;
+use std::collections::{HashMap, VecDeque};
+use std::hash::{Hash, Hasher};
+use std::time::{Duration, Instant};
+
+pub(crate) const CAPACITY: usize = 512;
+pub(crate) const LEGACY_WINDOW: Duration = Duration::from_secs(5);
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+enum Key {
+    Id(String, String),
+    Legacy(u64),
+}
+
+/// Shared by every receive path of a `NetworkNode` (UDP sockets and TCP readers).
+#[derive(Debug, Default)]
+pub(crate) struct RecentMessages {
+    order: VecDeque<(Key, Instant)>,
+    seen: HashMap<Key, Instant>,
+}
+
+impl RecentMessages {
+    /// `true` the first time a message is seen (and remembers it); `false`
+    /// for a duplicate that should be dropped.
+    pub(crate) fn first_sighting(&mut self, from: &str, msg_id: &str, payload_json: &str, now: Instant) -> bool {
+        let key = if msg_id.is_empty() {
+            let mut hasher = DefaultHasher::new();
+            (from, payload_json).hash(&mut hasher);
+            Key::Legacy(hasher.finish())
+        } else {
+            Key::Id(from.to_string(), msg_id.to_string())
+        };
+
+        if let Some(at) = self.seen.get(&key) {
+            let duplicate = match key {
+                Key::Id(..) => true,
+                Key::Legacy(_) => now.duration_since(*at) < LEGACY_WINDOW,
+            };
+            if duplicate {
+                return false;
+            }
+        }
+
+        self.seen.insert(key.clone(), now);
+        self.order.push_back((key, now));
+        while self.order.len() > CAPACITY {
+            let Some((old, at)) = self.order.pop_front() else { break };
+            // A re-sent legacy message has a newer entry further back; keep it.
+            if self.seen.get(&old) == Some(&at) {
+                self.seen.remove(&old);
+            }
+        }
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn ids_are_remembered_until_evicted() {
+        let now = Instant::now();
+        let mut r = RecentMessages::default();
+        assert!(r.first_sighting("a", "m1", "x", now));
+        assert!(!r.first_sighting("a", "m1", "different body", now + LEGACY_WINDOW * 10));
+        // Same id from another sender is a different message.
+        assert!(r.first_sighting("b", "m1", "x", now));
+
+        for i in 0..CAPACITY {
+            assert!(r.first_sighting("c", &i.to_string(), "x", now));
+        }
+        assert!(r.first_sighting("a", "m1", "x", now), "evicted after CAPACITY newer ids");
+        assert!(r.seen.len() <= CAPACITY);
+    }
+
+    #[test]
+    fn legacy_duplicates_only_within_window() {
+        let now = Instant::now();
+        let mut r = RecentMessages::default();
+        assert!(r.first_sighting("a", "", "hello", now));
+        assert!(!r.first_sighting("a", "", "hello", now + Duration::from_secs(1)));
+        assert!(r.first_sighting("b", "", "hello", now));
+        assert!(r.first_sighting("a", "", "hello", now + LEGACY_WINDOW));
+    }
+}
@@ -0,0 +1,238 @@
+//! Splitting of oversized `DirectBlock` payloads into `DirectBlockChunk`
+//! datagrams and their reassembly on the receiving side.
+//!
+//! Chunks are plain substrings of `payload_json` cut on UTF‑8 boundaries.
+//! The receiver buffers them per `(from, msg_id)`; duplicates are ignored and
+//! a buffer that hasn't completed within `REASSEMBLY_TIMEOUT` is dropped.
+//! Chunks are unauthenticated, so how many partial messages (and how many
+//! bytes) are buffered is capped per sender and in all; past a cap the oldest
+//! partial message is evicted.
+
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+
+use tracing::warn;
+
+/// Payload bytes per chunk. JSON string escaping can roughly double this,
+/// which still leaves headroom under `MAX_DGRAM` for the envelope.
+pub(crate) const CHUNK_SIZE: usize = 3 * 1024;
+/// Upper bound on chunks per message (~768 KiB) so a peer can't make us buffer unbounded data.
+pub(crate) const MAX_CHUNKS: u32 = 256;
+pub(crate) const REASSEMBLY_TIMEOUT: Duration = Duration::from_secs(10);
+/// Partial messages buffered per sender, and in all.
+pub(crate) const MAX_PARTIALS_PER_SENDER: usize = 8;
+pub(crate) const MAX_PARTIALS: usize = 64;
+/// Chunk bytes buffered per sender (two full messages), and in all.
+pub(crate) const MAX_BUFFERED_PER_SENDER: usize = 2 * MAX_CHUNKS as usize * CHUNK_SIZE;
+pub(crate) const MAX_BUFFERED: usize = 8 * 1024 * 1024;
+
+/// Split `payload` into pieces of at most `CHUNK_SIZE` bytes on char boundaries.
+pub(crate) fn split_payload(payload: &str) -> Vec<&str> {
+    let mut out = Vec::new();
+    let mut rest = payload;
+    while !rest.is_empty() {
+        let mut cut = rest.len().min(CHUNK_SIZE);
+        while !rest.is_char_boundary(cut) {
+            cut -= 1;
+        }
+        let (head, tail) = rest.split_at(cut);
+        out.push(head);
+        rest = tail;
+    }
+    out
+}
+
+/// Borrowed fields of a received `DirectBlockChunk`.
+pub(crate) struct Chunk<'a> {
+    pub from: &'a str,
+    pub to: &'a str,
+    pub msg_id: &'a str,
+    pub seq: u32,
+    pub total: u32,
+    pub data: &'a str,
+}
+
+struct Partial {
+    to: String,
+    parts: Vec<Option<String>>,
+    received: u32,
+    /// Chunk bytes held so far.
+    bytes: usize,
+    started: Instant,
+}
+
+/// Per-sender reassembly buffers (owned by `recv_loop`).
+#[derive(Default)]
+pub(crate) struct Reassembler {
+    buffers: HashMap<(String, String), Partial>,
+}
+
+impl Reassembler {
+    /// Feed one chunk. Returns `(to, payload_json)` once every chunk of the
+    /// message has arrived.
+    pub(crate) fn accept(&mut self, chunk: Chunk<'_>, now: Instant) -> Option<(String, String)> {
+        let Chunk { from, to, msg_id, seq, total, data } = chunk;
+        self.expire(now);
+        if total == 0 || total > MAX_CHUNKS || seq >= total {
+            warn!("Dropping malformed chunk {seq}/{total} of {msg_id} from {from}");
+            return None;
+        }
+
+        let key = (from.to_string(), msg_id.to_string());
+        let partial = self.buffers.entry(key.clone()).or_insert_with(|| Partial {
+            to: to.to_string(),
+            parts: vec![None; total as usize],
+            received: 0,
+            bytes: 0,
+            started: now,
+        });
+        if partial.parts.len() != total as usize {
+            warn!("Chunk count changed mid-message for {msg_id} from {from}; ignoring chunk");
+            return None;
+        }
+        let slot = &mut partial.parts[seq as usize];
+        if slot.is_some() {
+            return None; // duplicate
+        }
+        *slot = Some(data.to_string());
+        partial.received += 1;
+        partial.bytes += data.len();
+        if partial.received < total {
+            self.enforce_caps(&key);
+            return None;
+        }
+
+        let done = self.buffers.remove(&key)?;
+        let payload: String = done.parts.into_iter().flatten().collect();
+        Some((done.to, payload))
+    }
+
+    /// Evict the oldest partial messages until the sender of `keep` and all
+    /// senders together are within the caps; `keep` (the one just added to)
+    /// goes last, only if it is over a cap on its own.
+    fn enforce_caps(&mut self, keep: &(String, String)) {
+        let from = keep.0.as_str();
+        loop {
+            let usage = |of_sender: bool| {
+                self.buffers
+                    .iter()
+                    .filter(|(k, _)| !of_sender || k.0 == from)
+                    .fold((0, 0), |(n, bytes), (_, p)| (n + 1, bytes + p.bytes))
+            };
+            let (n, bytes) = usage(true);
+            let sender_over = n > MAX_PARTIALS_PER_SENDER || bytes > MAX_BUFFERED_PER_SENDER;
+            let (n, bytes) = usage(false);
+            if !sender_over && n <= MAX_PARTIALS && bytes <= MAX_BUFFERED {
+                return;
+            }
+            let oldest = self
+                .buffers
+                .iter()
+                .filter(|(k, _)| *k != keep && (!sender_over || k.0 == from))
+                .min_by_key(|(_, p)| p.started)
+                .map(|(k, _)| k.clone());
+            let victim = oldest.unwrap_or_else(|| keep.clone());
+            if let Some(p) = self.buffers.remove(&victim) {
+                warn!(
+                    "Evicting partial message {} from {} ({}/{} chunks): reassembly buffers full",
+                    victim.1,
+                    victim.0,
+                    p.received,
+                    p.parts.len()
+                );
+            }
+            if victim == *keep {
+                return;
+            }
+        }
+    }
+
+    /// Drop buffers older than `REASSEMBLY_TIMEOUT`.
+    pub(crate) fn expire(&mut self, now: Instant) {
+        self.buffers.retain(|(from, msg_id), p| {
+            let alive = now.duration_since(p.started) < REASSEMBLY_TIMEOUT;
+            if !alive {
+                warn!(
+                    "Dropping partial message {msg_id} from {from} ({}/{} chunks)",
+                    p.received,
+                    p.parts.len()
+                );
+            }
+            alive
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn chunk(seq: u32, total: u32, data: &str) -> Chunk<'_> {
+        Chunk { from: "a", to: "b", msg_id: "m1", seq, total, data }
+    }
+
+    #[test]
+    fn split_respects_char_boundaries() {
+        let payload = "é".repeat(CHUNK_SIZE); // 2 bytes each
+        let chunks = split_payload(&payload);
+        assert!(chunks.iter().all(|c| c.len() <= CHUNK_SIZE));
+        assert_eq!(chunks.concat(), payload);
+        assert!(split_payload("").is_empty());
+    }
+
+    #[test]
+    fn reassembles_out_of_order_with_duplicates() {
+        let payload = "x".repeat(CHUNK_SIZE * 2 + 10);
+        let chunks = split_payload(&payload);
+        let total = chunks.len() as u32;
+        let now = Instant::now();
+        let mut r = Reassembler::default();
+        assert!(r.accept(chunk(2, total, chunks[2]), now).is_none());
+        assert!(r.accept(chunk(0, total, chunks[0]), now).is_none());
+        assert!(r.accept(chunk(0, total, chunks[0]), now).is_none());
+        let (to, got) = r.accept(chunk(1, total, chunks[1]), now).unwrap();
+        assert_eq!(to, "b");
+        assert_eq!(got, payload);
+    }
+
+    #[test]
+    fn partial_buffers_time_out() {
+        let now = Instant::now();
+        let mut r = Reassembler::default();
+        assert!(r.accept(chunk(0, 2, "he"), now).is_none());
+        let later = now + REASSEMBLY_TIMEOUT + Duration::from_millis(1);
+        // The first chunk expired, so the second alone doesn't complete it.
+        assert!(r.accept(chunk(1, 2, "llo"), later).is_none());
+        assert!(r.accept(chunk(5, 2, "bad"), later).is_none());
+    }
+
+    #[test]
+    fn flooding_msg_ids_evicts_the_oldest_partials() {
+        let now = Instant::now();
+        let mut r = Reassembler::default();
+        fn first<'a>(from: &'a str, msg_id: &'a str) -> Chunk<'a> {
+            Chunk { from, to: "b", msg_id, seq: 0, total: 2, data: "x" }
+        }
+        // One sender opens message after message, then many (spoofed) senders do.
+        for i in 0..1_000 {
+            r.accept(first("flooder", &format!("m{i}")), now + Duration::from_micros(i));
+        }
+        assert_eq!(r.buffers.keys().filter(|k| k.0 == "flooder").count(), MAX_PARTIALS_PER_SENDER);
+        for i in 0..1_000 {
+            r.accept(first(&format!("spoofed-{i}"), "m"), now + Duration::from_millis(1) + Duration::from_micros(i));
+        }
+        assert_eq!(r.buffers.len(), MAX_PARTIALS);
+        assert!(r.buffers.contains_key(&("spoofed-999".to_string(), "m".to_string())), "newest kept");
+
+        // Bytes are capped too: a sender's second huge message pushes out its first.
+        let big = "y".repeat(2 * CHUNK_SIZE);
+        for msg_id in ["huge-1", "huge-2"] {
+            for seq in 0..MAX_CHUNKS - 1 {
+                r.accept(Chunk { from: "hog", to: "b", msg_id, seq, total: MAX_CHUNKS, data: &big }, now + Duration::from_secs(1));
+            }
+        }
+        let hog: Vec<&str> = r.buffers.keys().filter(|k| k.0 == "hog").map(|k| k.1.as_str()).collect();
+        assert_eq!(hog, ["huge-2"]);
+        assert!(r.buffers.values().map(|p| p.bytes).sum::<usize>() <= MAX_BUFFERED);
+    }
+}
@@ -0,0 +1,149 @@
+//! Per-peer TCP connection history that outlives individual connections.
+//!
+//! `ConnectionStats` only describes the live connection and is gone once it
+//! drops; this keeps running totals per peer instead: messages each way,
+//! connects and disconnects, the mean of every connection-test round trip and
+//! the total time spent connected. With a file attached (see
+//! `NetworkNode::with_history_file`) the totals are written on every connect
+//! and disconnect and on `stop`, so they survive restarts; traffic since the
+//! last write is lost if the process dies.
+
+use std::collections::HashMap;
+use std::fs;
+use std::path::PathBuf;
+use std::sync::Mutex;
+use std::time::Instant;
+
+use serde::{Deserialize, Serialize};
+use tracing::warn;
+
+/// A peer's connection totals across every connection so far.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ConnectionHistory {
+    pub peer_id: String,
+    pub messages_sent: u64,
+    pub messages_received: u64,
+    pub connects: u64,
+    pub disconnects: u64,
+    /// Mean of every connection-test round trip (`None` before the first).
+    pub avg_rtt_ms: Option<u64>,
+    /// Time spent connected, the current connection included.
+    pub uptime_ms: u64,
+    pub is_connected: bool,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+struct PeerRecord {
+    messages_sent: u64,
+    messages_received: u64,
+    connects: u64,
+    disconnects: u64,
+    rtt_total_ms: u64,
+    rtt_samples: u64,
+    uptime_ms: u64,
+    #[serde(skip)]
+    connected_since: Option<Instant>,
+}
+
+impl PeerRecord {
+    fn uptime_ms(&self) -> u64 {
+        let current = self.connected_since.map_or(0, |t| t.elapsed().as_millis() as u64);
+        self.uptime_ms + current
+    }
+}
+
+#[derive(Debug, Default)]
+struct Inner {
+    path: Option<PathBuf>,
+    peers: HashMap<String, PeerRecord>,
+}
+
+#[derive(Debug, Default)]
+pub(crate) struct HistoryStore {
+    inner: Mutex<Inner>,
+}
+
+impl HistoryStore {
+    /// Persist to `path` from now on, starting from whatever it already holds
+    /// (missing or unreadable file = nothing).
+    pub(crate) fn attach_file(&self, path: PathBuf) {
+        let loaded = fs::read_to_string(&path)
+            .ok()
+            .and_then(|data| serde_json::from_str::<HashMap<String, PeerRecord>>(&data).ok())
+            .unwrap_or_default();
+        let mut inner = self.inner.lock().unwrap();
+        for (peer_id, record) in loaded {
+            inner.peers.entry(peer_id).or_insert(record);
+        }
+        inner.path = Some(path);
+    }
+
+    fn update(&self, peer_id: &str, persist: bool, f: impl FnOnce(&mut PeerRecord)) {
+        let mut inner = self.inner.lock().unwrap();
+        f(inner.peers.entry(peer_id.to_string()).or_default());
+        if persist {
+            Self::save(&inner);
+        }
+    }
+
+    fn save(inner: &Inner) {
+        let Some(path) = &inner.path else { return };
+        let written = serde_json::to_string_pretty(&inner.peers)
+            .map_err(anyhow::Error::from)
+            .and_then(|json| fs::write(path, json).map_err(anyhow::Error::from));
+        if let Err(e) = written {
+            warn!("Failed saving connection history to {}: {e}", path.display());
+        }
+    }
+
+    /// Write the file now (no-op without one).
+    pub(crate) fn flush(&self) {
+        Self::save(&self.inner.lock().unwrap());
+    }
+
+    pub(crate) fn connected(&self, peer_id: &str) {
+        self.update(peer_id, true, |r| {
+            r.connects += 1;
+            r.connected_since = Some(Instant::now());
+        });
+    }
+
+    pub(crate) fn disconnected(&self, peer_id: &str) {
+        self.update(peer_id, true, |r| {
+            r.disconnects += 1;
+            if let Some(since) = r.connected_since.take() {
+                r.uptime_ms += since.elapsed().as_millis() as u64;
+            }
+        });
+    }
+
+    pub(crate) fn sent(&self, peer_id: &str) {
+        self.update(peer_id, false, |r| r.messages_sent += 1);
+    }
+
+    pub(crate) fn received(&self, peer_id: &str) {
+        self.update(peer_id, false, |r| r.messages_received += 1);
+    }
+
+    pub(crate) fn rtt(&self, peer_id: &str, rtt_ms: u64) {
+        self.update(peer_id, false, |r| {
+            r.rtt_total_ms += rtt_ms;
+            r.rtt_samples += 1;
+        });
+    }
+
+    pub(crate) fn get(&self, peer_id: &str) -> Option<ConnectionHistory> {
+        let inner = self.inner.lock().unwrap();
+        let r = inner.peers.get(peer_id)?;
+        Some(ConnectionHistory {
+            peer_id: peer_id.to_string(),
+            messages_sent: r.messages_sent,
+            messages_received: r.messages_received,
+            connects: r.connects,
+            disconnects: r.disconnects,
+            avg_rtt_ms: (r.rtt_samples > 0).then(|| r.rtt_total_ms / r.rtt_samples),
+            uptime_ms: r.uptime_ms(),
+            is_connected: r.connected_since.is_some(),
+        })
+    }
+}
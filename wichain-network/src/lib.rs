@@ -1,33 +1,108 @@
 //! WiChain LAN networking: UDP peer discovery + direct peer messages.
 //!
 //! *UDP broadcast* is used only for discovery (Peer + Ping/Pong). Actual chat
-//! data travels in `DirectBlock` datagrams (unicast).
+//! data travels in `DirectBlock` datagrams (unicast); payloads too large for
+//! one datagram are split into `DirectBlockChunk`s and reassembled on receipt.
+//! UDP direct sends are acknowledged (`Ack`) and retransmitted with backoff.
+//! A group message is one `GroupBlock` ciphertext for all members: it is
+//! broadcast once, and members that don't ack it get it directly (see
+//! `send_group_block`).
+//! Discovery runs over IPv4 broadcast, IPv6 link-local multicast
+//! (`DISCOVERY_GROUP_V6` = `ff02::1`, all nodes), or both; see `IpFamily`.
+//! `Peer`/`Ping`/`Pong` are signed with the node's Ed25519 key (see
+//! `announce_bytes`); unsigned or forged announces are dropped.
+//! Messages for peers we haven't discovered yet wait in a per-peer outbox and
+//! are flushed when that peer's next `Peer`/`Ping` announce arrives.
+//!
+//! With the `mdns` feature, nodes can also advertise and browse a
+//! `_wichain._udp.local.` DNS-SD service (see `with_mdns` and the `mdns`
+//! module), for networks whose switches drop broadcast.
 //!
 //! Alias is mutable at runtime so the backend can hot‑update after a rename.
 
 use std::{
-    collections::HashMap,
-    net::{IpAddr, Ipv4Addr, SocketAddr},
-    sync::Arc,
+    collections::{HashMap, HashSet, VecDeque},
+    net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr, SocketAddrV6},
+    path::PathBuf,
+    sync::{
+        atomic::{AtomicBool, AtomicU16, AtomicU64, Ordering},
+        Arc, Weak,
+    },
     time::{Duration, Instant},
 };
 
+use ed25519_dalek::{Signature, Signer as _, SigningKey, Verifier as _, VerifyingKey};
 use serde::{Deserialize, Serialize};
 use tokio::{
     io::{AsyncWriteExt, AsyncReadExt},
-    net::{UdpSocket, TcpListener as TokioTcpListener, TcpStream as TokioTcpStream},
-    sync::{mpsc, Mutex, RwLock},
-    time::{timeout, Duration as TokioDuration},
+    net::{
+        tcp::{OwnedReadHalf, OwnedWriteHalf},
+        UdpSocket, TcpListener as TokioTcpListener, TcpStream as TokioTcpStream,
+    },
+    sync::{broadcast, mpsc, oneshot, watch, Mutex, RwLock},
+    task::JoinHandle,
+    time::timeout,
 };
 use tracing::{error, info, warn, debug};
 
-const BROADCAST_INTERVAL: Duration = Duration::from_millis(500); // ⚡ REAL-TIME: 500ms for INSTANT peer discovery!
-const PEER_STALE_SECS: u64 = 30;
+mod dedup;
+mod fragment;
+mod history;
+#[cfg(feature = "mdns")]
+mod mdns;
+mod metrics;
+mod outbound;
+mod pacing;
+mod presence;
+use dedup::RecentMessages;
+use fragment::{Chunk, Reassembler};
+pub use history::ConnectionHistory;
+use history::HistoryStore;
+#[cfg(feature = "mdns")]
+pub use mdns::{MdnsMode, SERVICE_TYPE as MDNS_SERVICE_TYPE};
+use metrics::Counters;
+pub use metrics::NetworkMetrics;
+use outbound::{Outbound, PongWaiters};
+use pacing::Pacing;
+use presence::{Presence, PRESENCE_DEBOUNCE};
+
+/// Base announce + ping interval; widens while the peer set is stable (see `pacing`).
+const DEFAULT_BROADCAST_INTERVAL: Duration = Duration::from_millis(500);
+/// Default quiet time after which a peer is dropped from the roster; it is
+/// marked `away` after half of it.
+pub const DEFAULT_PEER_TIMEOUT: Duration = Duration::from_secs(30);
+/// How often the roster is swept for away / gone peers.
+const PEER_GC_INTERVAL: Duration = Duration::from_secs(1);
 const MAX_DGRAM: usize = 8 * 1024;
 const TCP_PORT_OFFSET: u16 = 1000; // TCP port = UDP port + offset
 // const TCP_CONNECTION_TIMEOUT: Duration = Duration::from_secs(10);
-// const TCP_KEEPALIVE_INTERVAL: Duration = Duration::from_secs(30);
+const TCP_KEEPALIVE_INTERVAL: Duration = Duration::from_secs(30);
+/// A TCP connection with no successful write or inbound frame for this long is reaped.
+const TCP_STALE_AFTER: Duration = Duration::from_secs(90);
+/// Largest accepted TCP frame body; bigger length prefixes close the connection.
+const MAX_TCP_FRAME: usize = 1024 * 1024;
 const TCP_MESSAGE_TIMEOUT: Duration = Duration::from_secs(2); // OPTIMIZED: 5s → 2s for faster messaging
+/// How long `test_tcp_connection` waits for the matching `TcpConnectionTestResponse`.
+const TCP_TEST_TIMEOUT: Duration = Duration::from_secs(2);
+/// First wait for an `Ack`; doubled after every retransmit.
+const ACK_TIMEOUT: Duration = Duration::from_millis(400);
+const MAX_SEND_ATTEMPTS: u32 = 3;
+/// IPv6 discovery group: link-local all-nodes. Every IPv6 host is a member
+/// already, so announces reach peers even where joining fails.
+pub const DISCOVERY_GROUP_V6: Ipv6Addr = Ipv6Addr::new(0xff02, 0, 0, 0, 0, 0, 0, 1);
+/// Outbox length per undiscovered peer; further sends are rejected.
+const MAX_QUEUED_PER_PEER: usize = 64;
+const DEFAULT_REACHABILITY_TTL: Duration = Duration::from_secs(5);
+const PROBE_TIMEOUT: Duration = Duration::from_millis(1500);
+/// `add_peer_by_addr` pings this often, this many times, before giving up.
+const ADD_PEER_RETRY: Duration = Duration::from_secs(1);
+const ADD_PEER_ATTEMPTS: u32 = 3;
+const RTT_HISTORY: usize = 16;
+/// Announces whose `nonce` (the sender's clock) is further than this from
+/// ours are dropped, so a captured one can only be replayed this long; peers
+/// whose clocks are off by more are not heard at all.
+const ANNOUNCE_MAX_SKEW: Duration = Duration::from_secs(60 * 60);
+const MESSAGE_HISTORY: usize = 64;
 
 /// Info exposed to UI.
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -38,6 +113,9 @@ pub struct PeerInfo {
     pub last_seen_ms: u64,
     pub connection_type: String, // "UDP", "TCP", or "Unknown"
     pub tcp_port: Option<u16>,
+    /// Quiet for over half the peer timeout; dropped once the full timeout passes.
+    #[serde(default)]
+    pub away: bool,
 }
 
 /// Connection statistics for monitoring.
@@ -48,26 +126,257 @@ pub struct ConnectionStats {
     pub message_count: u64,
     pub last_activity_ms: u64,
     pub last_test_time_ms: Option<u64>,
+    /// Mean of the recent connection-test round trips.
+    pub avg_rtt_ms: Option<u64>,
+    /// Inbound message rate over the last minute.
+    pub messages_per_min: f64,
+}
+
+/// Transport currently used to reach a peer.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "UPPERCASE")]
+pub enum Transport {
+    Udp,
+    Tcp,
+}
+
+/// Address families used for discovery (and the sockets that receive it).
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum IpFamily {
+    /// IPv4 broadcast only (the historical behaviour).
+    #[default]
+    V4,
+    /// IPv6 multicast to `DISCOVERY_GROUP_V6` only.
+    V6,
+    /// Both, on separate sockets.
+    Dual,
+}
+
+impl IpFamily {
+    fn v4(self) -> bool {
+        matches!(self, IpFamily::V4 | IpFamily::Dual)
+    }
+
+    fn v6(self) -> bool {
+        matches!(self, IpFamily::V6 | IpFamily::Dual)
+    }
+}
+
+/// How a direct message reached the peer.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "transport", rename_all = "UPPERCASE")]
+pub enum Delivery {
+    /// Written to the peer's TCP stream.
+    Tcp,
+    /// Acknowledged over UDP after `attempts` sends.
+    Udp { attempts: u32 },
+    /// Peer unknown right now; held in the outbox (`pending` messages for it).
+    Queued { pending: usize },
+}
+
+/// Error for a peer id the node doesn't know (never seen, or timed out).
+/// Returned inside `anyhow::Error`; `downcast_ref` it to tell it apart.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct UnknownPeer(pub String);
+
+impl std::fmt::Display for UnknownPeer {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Peer not found: {}", self.0)
+    }
+}
+
+impl std::error::Error for UnknownPeer {}
+
+/// Undelivered `payload_json`s per peer id, oldest first.
+type Outbox = Arc<Mutex<HashMap<String, VecDeque<String>>>>;
+
+/// Peer ids whose traffic is dropped on receipt.
+type Blocklist = Arc<RwLock<HashSet<String>>>;
+
+/// Local node events for the app layer (never sent on the wire).
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "type")]
+pub enum NetworkEvent {
+    /// A peer moved between transports (TCP handshake registered, or TCP dropped back to UDP).
+    ConnectionChanged {
+        peer_id: String,
+        from: Transport,
+        to: Transport,
+    },
+    /// A reappearing peer's outbox was flushed; `remaining` stay queued after a failed send.
+    QueueFlushed {
+        peer_id: String,
+        delivered: usize,
+        remaining: usize,
+    },
+    /// A peer showed up in the roster (see `presence`).
+    PeerJoined { peer: PeerInfo },
+    /// A peer timed out, or was removed, and stayed gone (see `presence`).
+    PeerLeft { peer: PeerInfo },
+    /// A known peer announced itself under a new alias.
+    AliasChanged {
+        peer_id: String,
+        old_alias: String,
+        new_alias: String,
+    },
+    /// `start` couldn't bind discovery on the wildcard address and fell back
+    /// to loopback at `addr`: only peers on this machine can find us.
+    BindDegraded { addr: SocketAddr },
+}
+
+/// Liveness of a node, as `NetworkNode::health` reports it.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct NodeHealth {
+    /// A discovery socket is bound.
+    pub udp_bound: bool,
+    /// Where it is bound (`None` while stopped).
+    pub udp_addr: Option<SocketAddr>,
+    /// The wildcard bind failed and discovery fell back to loopback, so no
+    /// LAN peer can find us.
+    pub udp_fallback: bool,
+    pub tcp_listening: bool,
+    pub peer_count: usize,
+    /// Seconds since `start`; 0 while stopped.
+    pub uptime_secs: u64,
+    /// Unix ms of the last periodic announce (`None` before the first, or
+    /// for nodes that don't broadcast).
+    pub last_broadcast_ms: Option<u64>,
+}
+
+/// What `start` bound and when, for `NetworkNode::health`.
+#[derive(Debug, Default)]
+struct NodeStatus {
+    started: std::sync::Mutex<Option<Instant>>,
+    udp_fallback: AtomicBool,
+    tcp_listening: AtomicBool,
+    /// 0 = never.
+    last_broadcast_ms: AtomicU64,
+}
+
+/// Payload schemes a node can open, as announced in `Peer`. The names mean
+/// nothing to this crate; see `NetworkNode::with_capabilities`.
+///
+/// Signed separately (`sig`, hex, over `capabilities_bytes`) so peers that
+/// predate capabilities still verify the announce itself.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Capabilities {
+    pub ciphers: Vec<String>,
+    #[serde(default)]
+    pub sig: String,
 }
 
 /// Network datagrams.
 #[derive(Debug, Clone, Serialize, Deserialize)]
 #[serde(tag = "type")]
 pub enum NetworkMessage {
-    Peer { id: String, alias: String, pubkey: String },
-    Ping { id: String, alias: String },
-    Pong { id: String, alias: String },
+    /// Announces are signed over `announce_bytes(id, alias, pubkey, nonce)`
+    /// (hex `sig`); for `Ping`/`Pong` the pubkey is the `id` itself and
+    /// `clock_bytes(time_ms, echo_ms)` follows. `nonce` is the sender's clock
+    /// in ms, strictly increasing per sender (see `AnnounceMarks`).
+    Peer {
+        id: String,
+        alias: String,
+        pubkey: String,
+        #[serde(default)]
+        nonce: u64,
+        #[serde(default)]
+        sig: String,
+        /// What the node supports; absent from older peers.
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        capabilities: Option<Capabilities>,
+    },
+    Ping {
+        id: String,
+        alias: String,
+        #[serde(default)]
+        nonce: u64,
+        #[serde(default)]
+        sig: String,
+        /// Sender's wall clock (ms) when it pinged, for
+        /// `NetworkNode::peer_clock_offset`.
+        #[serde(default)]
+        time_ms: u64,
+    },
+    Pong {
+        id: String,
+        alias: String,
+        #[serde(default)]
+        nonce: u64,
+        #[serde(default)]
+        sig: String,
+        /// Responder's wall clock (ms) when it answered.
+        #[serde(default)]
+        time_ms: u64,
+        /// The answered ping's `time_ms`, so the pinger can time the round trip.
+        #[serde(default)]
+        echo_ms: u64,
+    },
 
-    /// Legacy full chain broadcast (ignored in current flow; retained for compat).
+    /// Block or whole-chain gossip from `NetworkNode::broadcast_block` (the
+    /// app ignores it for now; chains sync through resyncs).
     Block { block_json: String },
 
     /// Direct peer message (chat payload JSON).
     DirectBlock {
         from: String,
         to: String,
+        /// UUID the receiver echoes in `Ack` (empty from older peers = no ACK).
+        #[serde(default)]
+        msg_id: String,
+        payload_json: String,
+    },
+
+    /// Receipt for a UDP `DirectBlock`, sent back to the datagram's source.
+    Ack { from: String, msg_id: String },
+
+    /// One piece of a `DirectBlock` too large for a single datagram.
+    /// Never forwarded to the app; `recv_loop` emits the reassembled `DirectBlock`.
+    DirectBlockChunk {
+        from: String,
+        to: String,
+        msg_id: String,
+        seq: u32,
+        total: u32,
+        data: String,
+    },
+
+    /// One ciphertext for every member of group `group_id`, broadcast on the
+    /// discovery port. Each member acks it as `group_ack_id(msg_id, member)`.
+    GroupBlock {
+        from: String,
+        group_id: String,
+        msg_id: String,
         payload_json: String,
     },
 
+    /// "Seen" receipt for chat `msg_id`, sent by its reader `from` to the
+    /// chat's author `to`. Signed by `from` over `receipt_bytes` (hex `sig`).
+    ReadReceipt {
+        from: String,
+        to: String,
+        msg_id: String,
+        ts: u64,
+        #[serde(default)]
+        sig: String,
+    },
+
+    /// Ask `to` for the blocks of its chain after index `since_index`.
+    ChainRequest { from: String, since_index: u64 },
+
+    /// Reply to a `ChainRequest`: a JSON array of consecutive blocks (content
+    /// of blocks the requester isn't party to withheld). `more` = the
+    /// responder stopped early; ask again from the last index. `network_id`
+    /// is the responder's chain network (`None` from peers predating it).
+    ChainResponse {
+        from: String,
+        blocks_json: String,
+        #[serde(default)]
+        more: bool,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        network_id: Option<String>,
+    },
+
     /// TCP connection request (sent via UDP to initiate TCP connection).
     TcpConnectionRequest {
         from: String,
@@ -94,7 +403,9 @@ pub enum NetworkMessage {
         timestamp: u64,
     },
 
-    /// TCP connection test response.
+    /// TCP connection test response, written back on the stream the test
+    /// arrived on. `timestamp` echoes the test; `response_time_ms` is the
+    /// responder's view of `now - timestamp` (cross-clock, informational only).
     TcpConnectionTestResponse {
         from: String,
         to: String,
@@ -110,18 +421,275 @@ pub enum NetworkMessage {
     },
 }
 
+impl NetworkMessage {
+    /// Peer id of the node that sent this datagram (if the variant carries one).
+    pub fn sender_id(&self) -> Option<&str> {
+        match self {
+            NetworkMessage::Peer { id, .. }
+            | NetworkMessage::Ping { id, .. }
+            | NetworkMessage::Pong { id, .. } => Some(id),
+            NetworkMessage::DirectBlock { from, .. }
+            | NetworkMessage::DirectBlockChunk { from, .. }
+            | NetworkMessage::Ack { from, .. }
+            | NetworkMessage::GroupBlock { from, .. }
+            | NetworkMessage::ReadReceipt { from, .. }
+            | NetworkMessage::ChainRequest { from, .. }
+            | NetworkMessage::ChainResponse { from, .. }
+            | NetworkMessage::TcpConnectionRequest { from, .. }
+            | NetworkMessage::TcpConnectionResponse { from, .. }
+            | NetworkMessage::TcpKeepalive { from }
+            | NetworkMessage::TcpConnectionTest { from, .. }
+            | NetworkMessage::TcpConnectionTestResponse { from, .. }
+            | NetworkMessage::TcpHandshake { from, .. } => Some(from),
+            NetworkMessage::Block { .. } => None,
+        }
+    }
+
+    /// `false` for a `Peer`/`Ping`/`Pong` whose signature doesn't verify
+    /// against the claimed pubkey, or whose `id` isn't that pubkey, and for a
+    /// `ReadReceipt` not signed by its `from`. Other variants carry no
+    /// signature and always pass.
+    pub fn announce_is_authentic(&self) -> bool {
+        match self {
+            NetworkMessage::Peer { id, alias, pubkey, nonce, sig, .. } => {
+                id == pubkey && verify_hex_sig(pubkey, &announce_bytes(id, alias, pubkey, *nonce), sig)
+            }
+            NetworkMessage::Ping { id, alias, nonce, sig, time_ms } => {
+                verify_hex_sig(id, &[announce_bytes(id, alias, id, *nonce), clock_bytes(*time_ms, 0)].concat(), sig)
+            }
+            NetworkMessage::Pong { id, alias, nonce, sig, time_ms, echo_ms } => {
+                verify_hex_sig(id, &[announce_bytes(id, alias, id, *nonce), clock_bytes(*time_ms, *echo_ms)].concat(), sig)
+            }
+            NetworkMessage::ReadReceipt { from, to, msg_id, ts, sig } => {
+                verify_hex_sig(from, &receipt_bytes(from, to, msg_id, *ts), sig)
+            }
+            _ => true,
+        }
+    }
+
+    /// Sender and `nonce` of a `Peer`/`Ping`/`Pong`.
+    fn announce_stamp(&self) -> Option<(&str, u64)> {
+        match self {
+            NetworkMessage::Peer { id, nonce, .. }
+            | NetworkMessage::Ping { id, nonce, .. }
+            | NetworkMessage::Pong { id, nonce, .. } => Some((id, *nonce)),
+            _ => None,
+        }
+    }
+
+    /// `false` for a `Peer`/`Ping`/`Pong` whose `nonce` is further than
+    /// `ANNOUNCE_MAX_SKEW` from `now_ms`. Other variants always pass.
+    pub(crate) fn announce_is_recent(&self, now_ms: u64) -> bool {
+        self.announce_stamp()
+            .is_none_or(|(_, nonce)| nonce.abs_diff(now_ms) <= ANNOUNCE_MAX_SKEW.as_millis() as u64)
+    }
+
+    /// Capabilities of a `Peer` announce whose signature checks out against
+    /// its `id` (`None` if there are none, or they are unsigned or forged).
+    pub fn announced_capabilities(&self) -> Option<&[String]> {
+        let NetworkMessage::Peer { id, nonce, capabilities: Some(caps), .. } = self else {
+            return None;
+        };
+        verify_hex_sig(id, &capabilities_bytes(id, *nonce, &caps.ciphers), &caps.sig).then_some(caps.ciphers.as_slice())
+    }
+}
+
+/// Bytes signed for announced capabilities: `"caps" || id || 0 || nonce (BE) || ciphers joined by 0`.
+fn capabilities_bytes(id: &str, nonce: u64, ciphers: &[String]) -> Vec<u8> {
+    let mut out = b"caps".to_vec();
+    out.extend_from_slice(id.as_bytes());
+    out.push(0);
+    out.extend_from_slice(&nonce.to_be_bytes());
+    out.extend_from_slice(ciphers.join("\0").as_bytes());
+    out
+}
+
+/// Bytes signed by an announce: `id || alias || pubkey || nonce (BE)`.
+fn announce_bytes(id: &str, alias: &str, pubkey: &str, nonce: u64) -> Vec<u8> {
+    let mut out = Vec::with_capacity(id.len() + alias.len() + pubkey.len() + 8);
+    out.extend_from_slice(id.as_bytes());
+    out.extend_from_slice(alias.as_bytes());
+    out.extend_from_slice(pubkey.as_bytes());
+    out.extend_from_slice(&nonce.to_be_bytes());
+    out
+}
+
+/// Bytes a `Ping`/`Pong` signs after its `announce_bytes`: `time_ms (BE) || echo_ms (BE)`
+/// (a ping has no `echo_ms` and signs 0).
+fn clock_bytes(time_ms: u64, echo_ms: u64) -> Vec<u8> {
+    [time_ms.to_be_bytes(), echo_ms.to_be_bytes()].concat()
+}
+
+/// Newest announce `nonce` accepted per peer id. An older one is a replay
+/// (it would roll the alias back and repoint the peer's address) and is
+/// dropped. A mark is forgotten once every announce it could shadow is past
+/// `ANNOUNCE_MAX_SKEW` anyway.
+#[derive(Debug, Default)]
+pub(crate) struct AnnounceMarks(HashMap<String, (u64, Instant)>);
+
+impl AnnounceMarks {
+    /// Whether `msg` is recent and not older than the last announce accepted
+    /// from its sender; if so it becomes the sender's mark. Resending the
+    /// latest announce (as mDNS does) is fine. Other variants always pass.
+    pub(crate) fn admit(&mut self, msg: &NetworkMessage, now_ms: u64, now: Instant) -> bool {
+        let Some((id, nonce)) = msg.announce_stamp() else {
+            return true;
+        };
+        self.0.retain(|_, (_, at)| now.duration_since(*at) < 2 * ANNOUNCE_MAX_SKEW);
+        if !msg.announce_is_recent(now_ms) {
+            return false;
+        }
+        match self.0.get_mut(id) {
+            Some((last, _)) if nonce < *last => false,
+            Some(mark) => {
+                *mark = (nonce, now);
+                true
+            }
+            None => {
+                self.0.insert(id.to_string(), (nonce, now));
+                true
+            }
+        }
+    }
+}
+
+pub(crate) type SharedAnnounceMarks = Arc<Mutex<AnnounceMarks>>;
+
+/// `msg_id` of the `Ack` `member` sends for `GroupBlock` `msg_id`, so the
+/// sender can tell which members one broadcast reached.
+fn group_ack_id(msg_id: &str, member: &str) -> String {
+    format!("{msg_id}/{member}")
+}
+
+/// Bytes signed by a read receipt: `from || 0 || to || 0 || msg_id || ts (BE)`.
+fn receipt_bytes(from: &str, to: &str, msg_id: &str, ts: u64) -> Vec<u8> {
+    let mut out = Vec::with_capacity(from.len() + to.len() + msg_id.len() + 10);
+    out.extend_from_slice(from.as_bytes());
+    out.push(0);
+    out.extend_from_slice(to.as_bytes());
+    out.push(0);
+    out.extend_from_slice(msg_id.as_bytes());
+    out.extend_from_slice(&ts.to_be_bytes());
+    out
+}
+
+/// Hex Ed25519 `sig_hex` over `bytes` by base64 `pubkey`.
+fn verify_hex_sig(pubkey: &str, bytes: &[u8], sig_hex: &str) -> bool {
+    let Ok(pk) = wichain_core::decode_pubkey_b64(pubkey) else {
+        return false;
+    };
+    let Ok(vk) = VerifyingKey::from_bytes(&pk) else {
+        return false;
+    };
+    let Some(sig) = hex::decode(sig_hex).ok().and_then(|b| Signature::from_slice(&b).ok()) else {
+        return false;
+    };
+    vk.verify(bytes, &sig).is_ok()
+}
+
+/// This node's discovery identity: builds signed `Peer`/`Ping`/`Pong`.
+/// Without a key the announces go out unsigned (and peers will drop them).
+#[derive(Clone)]
+struct Announcer {
+    id: String,
+    alias: Arc<Mutex<String>>,
+    pubkey: String,
+    key: Option<Arc<SigningKey>>,
+    capabilities: Vec<String>,
+    /// Last nonce handed out; shared by every copy so nonces never repeat.
+    last_nonce: Arc<AtomicU64>,
+}
+
+impl Announcer {
+    /// Current alias, a fresh nonce and the signature over them.
+    async fn signed(&self) -> (String, u64, String) {
+        self.signed_with(&[]).await
+    }
+
+    /// Like `signed`, with `extra` appended to the signed bytes.
+    async fn signed_with(&self, extra: &[u8]) -> (String, u64, String) {
+        let alias = self.alias.lock().await.clone();
+        let nonce = self.next_nonce();
+        let sig = self
+            .key
+            .as_ref()
+            .map(|k| hex::encode(k.sign(&[&announce_bytes(&self.id, &alias, &self.pubkey, nonce)[..], extra].concat()).to_bytes()))
+            .unwrap_or_default();
+        (alias, nonce, sig)
+    }
+
+    /// Our clock in ms, bumped past the last nonce if it hasn't moved (or went back).
+    fn next_nonce(&self) -> u64 {
+        let now = now_ms();
+        let last = self
+            .last_nonce
+            .fetch_update(Ordering::Relaxed, Ordering::Relaxed, |last| Some(now.max(last + 1)))
+            .unwrap_or_default();
+        now.max(last + 1)
+    }
+
+    async fn peer(&self) -> NetworkMessage {
+        let (alias, nonce, sig) = self.signed().await;
+        let capabilities = match &self.key {
+            Some(key) if !self.capabilities.is_empty() => Some(Capabilities {
+                sig: hex::encode(key.sign(&capabilities_bytes(&self.id, nonce, &self.capabilities)).to_bytes()),
+                ciphers: self.capabilities.clone(),
+            }),
+            _ => None,
+        };
+        NetworkMessage::Peer { id: self.id.clone(), alias, pubkey: self.pubkey.clone(), nonce, sig, capabilities }
+    }
+
+    async fn ping(&self) -> NetworkMessage {
+        let time_ms = now_ms();
+        let (alias, nonce, sig) = self.signed_with(&clock_bytes(time_ms, 0)).await;
+        NetworkMessage::Ping { id: self.id.clone(), alias, nonce, sig, time_ms }
+    }
+
+    /// Answer to a ping sent at the pinger's `echo_ms`.
+    async fn pong(&self, echo_ms: u64) -> NetworkMessage {
+        self.pong_at(now_ms(), echo_ms).await
+    }
+
+    async fn pong_at(&self, time_ms: u64, echo_ms: u64) -> NetworkMessage {
+        let (alias, nonce, sig) = self.signed_with(&clock_bytes(time_ms, echo_ms)).await;
+        NetworkMessage::Pong { id: self.id.clone(), alias, nonce, sig, time_ms, echo_ms }
+    }
+}
+
 #[derive(Debug, Clone)]
 struct PeerEntry {
     info: PeerInfo,
     last_seen: Instant,
     last_addr: SocketAddr,
     tcp_port: Option<u16>,
+    /// From the peer's last announce that carried signed capabilities.
+    capabilities: Option<Vec<String>>,
+    /// Estimated `peer clock - our clock` in ms, from the last timed pong.
+    clock_offset_ms: Option<i64>,
+}
+
+/// NTP-style estimate of a peer's clock offset from one ping/pong exchange:
+/// we pinged at `sent_ms`, the peer answered at its `peer_ms`, and the pong
+/// arrived at `received_ms` (our clock). Assumes the trip was symmetric, so
+/// the peer answered half-way through the round trip.
+fn clock_offset(sent_ms: u64, peer_ms: u64, received_ms: u64) -> i64 {
+    let midpoint = (i128::from(sent_ms) + i128::from(received_ms)) / 2;
+    (i128::from(peer_ms) - midpoint) as i64
+}
+
+/// Cached outcome of a reachability probe.
+#[derive(Debug, Clone, Copy)]
+struct Reachability {
+    reachable: bool,
+    checked_at: Instant,
 }
 
     /// TCP connection state for a peer.
     #[derive(Debug)]
 struct TcpConnection {
-    stream: Arc<Mutex<TokioTcpStream>>,
+    /// Write half; the read half is owned by `read_replies`.
+    stream: Arc<Mutex<OwnedWriteHalf>>,
     #[allow(dead_code)]
     peer_id: String,
     last_activity: Instant,
@@ -130,6 +698,63 @@ struct TcpConnection {
     last_test_time: Option<Instant>,
     #[allow(dead_code)]
     handshake_completed: bool,
+    rtt_samples: VecDeque<u64>,      // last RTT_HISTORY test round trips (ms)
+    message_times: VecDeque<Instant>, // last MESSAGE_HISTORY inbound messages
+}
+
+impl TcpConnection {
+    fn new(stream: OwnedWriteHalf, peer_id: String, handshake_completed: bool) -> Self {
+        Self {
+            stream: Arc::new(Mutex::new(stream)),
+            peer_id,
+            last_activity: Instant::now(),
+            // Set once the peer answers the probe `attach` sends.
+            is_connected: false,
+            message_count: 0,
+            last_test_time: None,
+            handshake_completed,
+            rtt_samples: VecDeque::with_capacity(RTT_HISTORY),
+            message_times: VecDeque::with_capacity(MESSAGE_HISTORY),
+        }
+    }
+
+    fn record_message(&mut self) {
+        let now = Instant::now();
+        self.last_activity = now;
+        self.message_count += 1;
+        if self.message_times.len() == MESSAGE_HISTORY {
+            self.message_times.pop_front();
+        }
+        self.message_times.push_back(now);
+    }
+
+    fn record_rtt(&mut self, rtt_ms: u64) {
+        let now = Instant::now();
+        self.last_activity = now;
+        self.last_test_time = Some(now);
+        if self.rtt_samples.len() == RTT_HISTORY {
+            self.rtt_samples.pop_front();
+        }
+        self.rtt_samples.push_back(rtt_ms);
+    }
+
+    fn avg_rtt_ms(&self) -> Option<u64> {
+        if self.rtt_samples.is_empty() {
+            return None;
+        }
+        Some(self.rtt_samples.iter().sum::<u64>() / self.rtt_samples.len() as u64)
+    }
+
+    fn messages_per_min(&self) -> f64 {
+        let window = Duration::from_secs(60);
+        let recent: Vec<&Instant> = self.message_times.iter().filter(|t| t.elapsed() <= window).collect();
+        // A saturated ring only covers part of the minute; extrapolate from its span.
+        if recent.len() == MESSAGE_HISTORY {
+            let span = recent[0].elapsed().as_secs_f64().max(1.0);
+            return recent.len() as f64 * 60.0 / span;
+        }
+        recent.len() as f64
+    }
 }
 
 /// TCP connection manager.
@@ -138,7 +763,22 @@ struct TcpConnectionManager {
     connections: Arc<RwLock<HashMap<String, TcpConnection>>>,
     #[allow(dead_code)]
     tcp_listener: Option<TokioTcpListener>,
-    tcp_port: u16,
+    /// Listening port; 0 until an ephemeral listener is bound (see `NetworkNode::new`).
+    tcp_port: AtomicU16,
+    events: broadcast::Sender<NetworkEvent>,
+    /// `test_tcp_connection` waiters keyed by `(peer_id, timestamp)`.
+    pending_tests: Mutex<HashMap<(String, u64), oneshot::Sender<()>>>,
+    /// `add_peer_by_addr` and `probe_peer` waiters keyed by the address
+    /// pinged; get the id of the node that answered.
+    pending_pongs: PongWaiters,
+    /// Traffic counters for the whole node (UDP and TCP).
+    metrics: Arc<Counters>,
+    /// Per-peer totals across connections (see `history`).
+    history: HistoryStore,
+    /// Sockets every outbound UDP send goes through.
+    outbound: Outbound,
+    /// The node's shutdown signal; ends the tasks `attach` spawns on `stop`.
+    shutdown: watch::Receiver<bool>,
 }
 
 pub struct NetworkNode {
@@ -148,15 +788,56 @@ pub struct NetworkNode {
     pubkey: String,
     peers: Arc<Mutex<HashMap<String, PeerEntry>>>,
     tcp_manager: Arc<TcpConnectionManager>,
+    reachability: Arc<Mutex<HashMap<String, Reachability>>>,
+    reachability_ttl: Arc<Mutex<Duration>>,
+    peer_timeout: Arc<Mutex<Duration>>,
+    bound_addr: Arc<Mutex<Option<SocketAddr>>>,
+    status: Arc<NodeStatus>,
+    /// Discovery sockets bound by `start`; directed pings go out on them so
+    /// the replies reach `recv_loop`.
+    discovery: Arc<Mutex<Vec<Arc<UdpSocket>>>>,
+    outbox: Outbox,
+    blocked: Blocklist,
+    family: IpFamily,
+    signing_key: Option<Arc<SigningKey>>,
+    /// Announced in `Peer` (see `with_capabilities`).
+    capabilities: Vec<String>,
+    recent: Arc<Mutex<RecentMessages>>,
+    pacing: Arc<Pacing>,
+    /// Flipped to `true` by `stop`; every task spawned by `start` watches it.
+    shutdown: watch::Sender<bool>,
+    tasks: Arc<Mutex<Vec<JoinHandle<()>>>>,
+    /// Last nonce our announces used (see `Announcer::next_nonce`).
+    announce_nonce: Arc<AtomicU64>,
+    /// Newest announce accepted from each peer, against replays.
+    announces: SharedAnnounceMarks,
+    #[cfg(feature = "mdns")]
+    mdns: MdnsMode,
 }
 
 impl NetworkNode {
+    /// Node discovering on UDP `port`, with its TCP listener on
+    /// `port + TCP_PORT_OFFSET`. Port 0 binds both to ephemeral ports
+    /// (see `bound_addr` and `get_tcp_port` after `start`); such a node has no
+    /// discovery port shared with others, so it doesn't broadcast and peers
+    /// are added with `add_peer_by_addr`.
     pub fn new(port: u16, id: String, alias: String, pubkey: String) -> Self {
-        let tcp_port = port + TCP_PORT_OFFSET;
+        let tcp_port = if port == 0 { 0 } else { port + TCP_PORT_OFFSET };
+        let (events, _) = broadcast::channel(64);
+        let metrics = Arc::new(Counters::default());
+        let pending_pongs = PongWaiters::default();
+        let (shutdown, shutdown_rx) = watch::channel(false);
         let tcp_manager = Arc::new(TcpConnectionManager {
             connections: Arc::new(RwLock::new(HashMap::new())),
             tcp_listener: None,
-            tcp_port,
+            tcp_port: AtomicU16::new(tcp_port),
+            events,
+            pending_tests: Mutex::new(HashMap::new()),
+            pending_pongs: pending_pongs.clone(),
+            outbound: Outbound::new(metrics.clone(), pending_pongs),
+            metrics,
+            history: HistoryStore::default(),
+            shutdown: shutdown_rx,
         });
 
         Self {
@@ -166,9 +847,127 @@ impl NetworkNode {
             pubkey,
             peers: Arc::new(Mutex::new(HashMap::new())),
             tcp_manager,
+            reachability: Arc::new(Mutex::new(HashMap::new())),
+            reachability_ttl: Arc::new(Mutex::new(DEFAULT_REACHABILITY_TTL)),
+            peer_timeout: Arc::new(Mutex::new(DEFAULT_PEER_TIMEOUT)),
+            bound_addr: Arc::new(Mutex::new(None)),
+            status: Arc::new(NodeStatus::default()),
+            discovery: Arc::new(Mutex::new(Vec::new())),
+            outbox: Arc::new(Mutex::new(HashMap::new())),
+            blocked: Arc::new(RwLock::new(HashSet::new())),
+            family: IpFamily::default(),
+            signing_key: None,
+            capabilities: Vec::new(),
+            recent: Arc::new(Mutex::new(RecentMessages::default())),
+            pacing: Arc::new(Pacing::new(DEFAULT_BROADCAST_INTERVAL)),
+            shutdown,
+            tasks: Arc::new(Mutex::new(Vec::new())),
+            announce_nonce: Arc::new(AtomicU64::new(0)),
+            announces: Arc::new(Mutex::new(AnnounceMarks::default())),
+            #[cfg(feature = "mdns")]
+            mdns: MdnsMode::default(),
+        }
+    }
+
+    /// Key that signs this node's announces; its public half must be `id`.
+    pub fn with_signing_key(mut self, key: SigningKey) -> Self {
+        self.signing_key = Some(Arc::new(key));
+        self
+    }
+
+    fn announcer(&self) -> Announcer {
+        Announcer {
+            id: self.id.clone(),
+            alias: self.alias.clone(),
+            pubkey: self.pubkey.clone(),
+            key: self.signing_key.clone(),
+            capabilities: self.capabilities.clone(),
+            last_nonce: self.announce_nonce.clone(),
+        }
+    }
+
+    /// Payload schemes to announce (names are up to the app). Call before `start`.
+    pub fn with_capabilities(mut self, ciphers: Vec<String>) -> Self {
+        self.capabilities = ciphers;
+        self
+    }
+
+    /// What `peer_id` last announced it supports (`None` if it never has).
+    pub async fn peer_capabilities(&self, peer_id: &str) -> Option<Vec<String>> {
+        self.peers.lock().await.get(peer_id).and_then(|p| p.capabilities.clone())
+    }
+
+    /// Roughly how far `peer_id`'s clock runs ahead of ours, in ms (negative
+    /// if behind), from its last pong to one of our pings. Advisory only,
+    /// for adjusting displayed timestamps: peers choose what they report, so
+    /// never rely on it for ordering or signatures.
+    pub async fn peer_clock_offset(&self, peer_id: &str) -> Option<i64> {
+        self.peers.lock().await.get(peer_id).and_then(|p| p.clock_offset_ms)
+    }
+
+    /// Keep per-peer connection history in `path` across restarts (in memory
+    /// only otherwise). Call before `start`.
+    pub fn with_history_file(self, path: PathBuf) -> Self {
+        self.tcp_manager.history.attach_file(path);
+        self
+    }
+
+    /// `peer_id`'s totals across every TCP connection so far (`None` if we
+    /// never had one).
+    pub fn connection_history(&self, peer_id: &str) -> Option<ConnectionHistory> {
+        self.tcp_manager.history.get(peer_id)
+    }
+
+    /// Discover over `family` instead of IPv4 only. Call before `start`.
+    pub fn with_ip_family(mut self, family: IpFamily) -> Self {
+        self.family = family;
+        self
+    }
+
+    /// Discover over mDNS as well as (or instead of) broadcast. Call before `start`.
+    #[cfg(feature = "mdns")]
+    pub fn with_mdns(mut self, mode: MdnsMode) -> Self {
+        self.mdns = mode;
+        self
+    }
+
+    /// Address families this node discovers on.
+    pub fn ip_family(&self) -> IpFamily {
+        self.family
+    }
+
+    /// Configured UDP discovery port.
+    pub fn port(&self) -> u16 {
+        self.port
+    }
+
+    /// Local address of the discovery socket (`None` until `start` has bound it).
+    pub async fn bound_addr(&self) -> Option<SocketAddr> {
+        *self.bound_addr.lock().await
+    }
+
+    /// What is bound and listening, and for how long. A `udp_fallback`
+    /// node only hears peers on this machine.
+    pub async fn health(&self) -> NodeHealth {
+        let udp_addr = self.bound_addr().await;
+        let uptime_secs = self.status.started.lock().unwrap().map_or(0, |t| t.elapsed().as_secs());
+        let last_broadcast_ms = self.status.last_broadcast_ms.load(Ordering::Relaxed);
+        NodeHealth {
+            udp_bound: udp_addr.is_some(),
+            udp_addr,
+            udp_fallback: self.status.udp_fallback.load(Ordering::Relaxed),
+            tcp_listening: self.status.tcp_listening.load(Ordering::Relaxed),
+            peer_count: self.peers.lock().await.len(),
+            uptime_secs,
+            last_broadcast_ms: (last_broadcast_ms != 0).then_some(last_broadcast_ms),
         }
     }
 
+    /// Subscribe to local node events (transport changes, ...).
+    pub fn subscribe_events(&self) -> broadcast::Receiver<NetworkEvent> {
+        self.tcp_manager.events.subscribe()
+    }
+
     /// Update alias hot (called by backend on rename).
     pub async fn set_alias(&self, new_alias: String) {
         {
@@ -184,158 +983,532 @@ impl NetworkNode {
 
     /// Start receiver + periodic broadcaster + TCP listener.
     pub async fn start(&self, tx: mpsc::Sender<NetworkMessage>) {
-        // Try primary binding first
-        let bind_addr = format!("0.0.0.0:{}", self.port);
-        let socket = match UdpSocket::bind(&bind_addr).await {
-            Ok(s) => {
-                let _ = s.set_broadcast(true);
-                info!("✅ Listening on {}", bind_addr);
-                s
+        self.shutdown.send_replace(false);
+        let mut tasks = Vec::new();
+        let mut sockets = Vec::new();
+        let mut fell_back = false;
+        if self.family.v4() {
+            if let Some((s, fallback)) = bind_discovery_v4(self.port).await {
+                sockets.push((Arc::new(s), SocketAddr::new(IpAddr::V4(Ipv4Addr::BROADCAST), self.port)));
+                fell_back |= fallback;
             }
-            Err(e) => {
-                warn!("Primary binding failed: {}, trying fallback", e);
-                // Fallback for macOS/Windows compatibility issues
-                let fallback_addr = format!("127.0.0.1:{}", self.port);
-                match UdpSocket::bind(&fallback_addr).await {
-                    Ok(s) => {
-                        let _ = s.set_broadcast(true);
-                        info!("✅ Listening on fallback {}", fallback_addr);
-                        s
-                    }
-                    Err(e2) => {
-                        error!("❌ Failed to bind UDP socket on both addresses: {e:?}, {e2:?}");
-                        return;
-                    }
+        }
+        if self.family.v6() {
+            if let Some((s, fallback)) = bind_discovery_v6(self.port).await {
+                sockets.push((Arc::new(s), discovery_target_v6(self.port)));
+                fell_back |= fallback;
+            }
+        }
+        let Some((first, _)) = sockets.first() else {
+            error!("❌ No discovery socket could be bound ({:?})", self.family);
+            return;
+        };
+        *self.bound_addr.lock().await = first.local_addr().ok();
+        *self.status.started.lock().unwrap() = Some(Instant::now());
+        self.status.udp_fallback.store(fell_back, Ordering::Relaxed);
+        if fell_back {
+            let addr = sockets.iter().filter_map(|(s, _)| s.local_addr().ok()).find(|a| a.ip().is_loopback());
+            if let Some(addr) = addr {
+                warn!("⚠ Discovery bound to loopback only ({addr}); LAN peers won't find this node");
+                let _ = self.tcp_manager.events.send(NetworkEvent::BindDegraded { addr });
+            }
+        }
+        *self.discovery.lock().await = sockets.iter().map(|(s, _)| s.clone()).collect();
+
+        // Bound before anything announces our TCP port, which may be ephemeral.
+        let tcp_listener = match TokioTcpListener::bind(("0.0.0.0", self.get_tcp_port())).await {
+            Ok(listener) => {
+                if let Ok(addr) = listener.local_addr() {
+                    self.tcp_manager.tcp_port.store(addr.port(), Ordering::Relaxed);
                 }
+                info!("✅ TCP listener started on port {}", self.get_tcp_port());
+                self.status.tcp_listening.store(true, Ordering::Relaxed);
+                Some(listener)
+            }
+            Err(e) => {
+                error!("Failed to start TCP listener: {e:?}");
+                None
             }
         };
-        let socket = Arc::new(socket);
 
-        // Receive loop
-        {
+        // Receive loop (one per family)
+        for (socket, _) in &sockets {
             let socket = socket.clone();
             let tx = tx.clone();
             let peers = self.peers.clone();
             let my_id = self.id.clone();
-            let my_alias = self.alias.clone();
-            let my_pubkey = self.pubkey.clone();
-            let port = self.port;
+            let me = self.announcer();
+            let outbox = self.outbox.clone();
+            let recent = self.recent.clone();
             let tcp_manager = self.tcp_manager.clone();
-            tokio::spawn(async move {
-                recv_loop(socket, tx, peers, my_id, my_alias, my_pubkey, port, tcp_manager).await;
-            });
+            let reachability = self.reachability.clone();
+            let blocked = self.blocked.clone();
+            let announces = self.announces.clone();
+            tasks.push(spawn_until_shutdown(self.shutdown.subscribe(), async move {
+                recv_loop(socket, tx, peers, my_id, me, outbox, recent, tcp_manager, reachability, blocked, announces).await;
+            }));
         }
 
         // Periodic broadcast (announce + ping)
+        #[cfg(feature = "mdns")]
+        let broadcast = self.mdns.broadcasts();
+        #[cfg(not(feature = "mdns"))]
+        let broadcast = true;
+        if broadcast && self.port != 0 {
+            let me = self.announcer();
+            let peers = self.peers.clone();
+            let pacing = self.pacing.clone();
+            let status = self.status.clone();
+            tasks.push(spawn_until_shutdown(self.shutdown.subscribe(), async move {
+                periodic_broadcast(sockets, me, peers, pacing, status).await;
+            }));
+        }
+
+        // mDNS advertisement + browsing
+        #[cfg(feature = "mdns")]
+        if self.mdns.enabled() {
+            let me = self.announcer();
+            let (port, tcp_port, family) = (self.port, self.get_tcp_port(), self.family);
+            let sink = mdns::Sink {
+                tx: tx.clone(),
+                peers: self.peers.clone(),
+                outbox: self.outbox.clone(),
+                blocked: self.blocked.clone(),
+                tcp_manager: self.tcp_manager.clone(),
+                announces: self.announces.clone(),
+            };
+            tasks.push(spawn_until_shutdown(self.shutdown.subscribe(), async move {
+                mdns::run(me, port, tcp_port, family, sink).await;
+            }));
+        }
+
+        // Peer GC: mark quiet peers away, drop them past the timeout, and
+        // report who joined or left
         {
-            let socket = socket.clone();
-            let id = self.id.clone();
-            let alias = self.alias.clone();
-            let pubkey = self.pubkey.clone();
-            let port = self.port;
-            tokio::spawn(async move {
-                periodic_broadcast(socket, id, alias, pubkey, port).await;
-            });
+            let peers = self.peers.clone();
+            let peer_timeout = self.peer_timeout.clone();
+            let tcp_manager = self.tcp_manager.clone();
+            tasks.push(spawn_until_shutdown(self.shutdown.subscribe(), async move {
+                let mut presence = Presence::default();
+                loop {
+                    tokio::time::sleep(PEER_GC_INTERVAL).await;
+                    let timeout = *peer_timeout.lock().await;
+                    gc_stale_peers(&peers, timeout).await;
+                    let roster = peers.lock().await.values().map(|p| p.info.clone()).collect();
+                    // Short test timeouts shouldn't wait out the full window.
+                    for event in presence.update(roster, Instant::now(), PRESENCE_DEBOUNCE.min(timeout)) {
+                        // No subscribers is fine; events are advisory.
+                        let _ = tcp_manager.events.send(event);
+                    }
+                }
+            }));
         }
 
-        // Start TCP listener
+        // TCP keepalive + stale-connection GC
         {
+            let tcp_manager = self.tcp_manager.clone();
+            let my_id = self.id.clone();
+            tasks.push(spawn_until_shutdown(self.shutdown.subscribe(), async move {
+                loop {
+                    tokio::time::sleep(TCP_KEEPALIVE_INTERVAL).await;
+                    tcp_manager.send_keepalives(&my_id).await;
+                    tcp_manager.cleanup_stale_connections(TCP_STALE_AFTER).await;
+                }
+            }));
+        }
+
+        // TCP accept loop
+        if let Some(listener) = tcp_listener {
             let tcp_manager = self.tcp_manager.clone();
             let node_id = self.id.clone();
-            let alias = self.alias.clone();
-            let pubkey = self.pubkey.clone();
+            let recent = self.recent.clone();
+            let blocked = self.blocked.clone();
             let tx_tcp = tx.clone();
-            tokio::spawn(async move {
-                if let Err(e) = TcpConnectionManager::start_tcp_listener_static(tcp_manager, node_id, alias, pubkey, tx_tcp).await {
-                    error!("Failed to start TCP listener: {e:?}");
-                }
-            });
+            let shutdown = self.shutdown.subscribe();
+            tasks.push(spawn_until_shutdown(self.shutdown.subscribe(), async move {
+                TcpConnectionManager::start_tcp_listener_static(listener, tcp_manager, node_id, recent, blocked, tx_tcp, shutdown).await;
+            }));
+        }
+
+        self.tasks.lock().await.extend(tasks);
+    }
+
+    /// Stop every task spawned by `start` and wait for them to exit. Their
+    /// sockets, the TCP listener and all TCP connections are closed, so the
+    /// ports can be bound again (including by a later `start`).
+    pub async fn stop(&self) {
+        self.shutdown.send_replace(true);
+        let tasks: Vec<_> = self.tasks.lock().await.drain(..).collect();
+        for task in tasks {
+            if let Err(e) = task.await {
+                warn!("network task ended abnormally: {e:?}");
+            }
+        }
+        let closed: Vec<String> = self.tcp_manager.connections.read().await.keys().cloned().collect();
+        for peer_id in closed {
+            self.tcp_manager.remove_connection(&peer_id).await;
         }
+        self.tcp_manager.history.flush();
+        *self.bound_addr.lock().await = None;
+        *self.status.started.lock().unwrap() = None;
+        self.status.udp_fallback.store(false, Ordering::Relaxed);
+        self.status.tcp_listening.store(false, Ordering::Relaxed);
+        self.discovery.lock().await.clear();
+        info!("🛑 Network node {} stopped", self.id);
     }
 
     /// Send a direct block payload to a peer we have an address for.
+    ///
+    /// See `send_direct_udp`; `Err` means delivery is unconfirmed.
     pub async fn send_direct_block(
         &self,
         peer_id: &str,
         payload_json: String,
-    ) -> anyhow::Result<()> {
-        let peers = self.peers.lock().await;
-        if let Some(entry) = peers.get(peer_id) {
-            let addr = entry.last_addr;
-            let from_alias = { self.alias.lock().await.clone() };
-            let msg = NetworkMessage::DirectBlock {
-                from: self.id.clone(),
-                to: peer_id.to_string(),
-                payload_json,
-            };
-            let bind_addr = "0.0.0.0:0";
-            let socket = UdpSocket::bind(bind_addr).await?;
-            // we don't need from_alias in payload; SALVAGE if needed in future
-            socket.send_to(&serde_json::to_vec(&msg)?, addr).await?;
-            info!("➡️  direct {} -> {} ({})", self.id, peer_id, from_alias);
-            Ok(())
-        } else {
-            Err(anyhow::anyhow!("Peer not found: {}", peer_id))
-        }
+    ) -> anyhow::Result<Delivery> {
+        let addr = {
+            let peers = self.peers.lock().await;
+            peers
+                .get(peer_id)
+                .map(|entry| entry.last_addr)
+                .ok_or_else(|| UnknownPeer(peer_id.to_string()))?
+        };
+        send_direct_udp(&self.tcp_manager.outbound, &self.id, peer_id, addr, &payload_json).await
     }
 
-    /// Force an immediate announce + ping (used by Find Peers button).
-    pub async fn ping_now(&self) -> anyhow::Result<()> {
-        let bind_addr = "0.0.0.0:0";
-        let socket = UdpSocket::bind(bind_addr).await?;
-        socket.set_broadcast(true)?;
-        let broadcast_addr = SocketAddr::new(IpAddr::V4(Ipv4Addr::BROADCAST), self.port);
-
-        let alias_now = { self.alias.lock().await.clone() };
-
-        let announce = NetworkMessage::Peer {
-            id: self.id.clone(),
-            alias: alias_now.clone(),
-            pubkey: self.pubkey.clone(),
+    /// Tell `peer_id` (the author of chat `msg_id`) that we have read it.
+    /// Fire-and-forget over UDP; needs the node's signing key.
+    pub async fn send_read_receipt(&self, peer_id: &str, msg_id: &str) -> anyhow::Result<()> {
+        let key = self
+            .signing_key
+            .as_ref()
+            .ok_or_else(|| anyhow::anyhow!("read receipts need a signing key"))?;
+        let addr = {
+            let peers = self.peers.lock().await;
+            peers
+                .get(peer_id)
+                .map(|entry| entry.last_addr)
+                .ok_or_else(|| UnknownPeer(peer_id.to_string()))?
         };
-        socket
-            .send_to(&serde_json::to_vec(&announce)?, broadcast_addr)
-            .await?;
-
-        let ping = NetworkMessage::Ping {
-            id: self.id.clone(),
-            alias: alias_now,
+        let ts = now_ms();
+        let sig = hex::encode(key.sign(&receipt_bytes(&self.id, peer_id, msg_id, ts)).to_bytes());
+        let receipt = NetworkMessage::ReadReceipt {
+            from: self.id.clone(),
+            to: peer_id.to_string(),
+            msg_id: msg_id.to_string(),
+            ts,
+            sig,
         };
-        socket
-            .send_to(&serde_json::to_vec(&ping)?, broadcast_addr)
-            .await?;
+        self.tcp_manager.outbound.send_msg(&receipt, addr).await
+    }
 
-        Ok(())
+    async fn last_addr_of(&self, peer_id: &str) -> anyhow::Result<SocketAddr> {
+        let peers = self.peers.lock().await;
+        peers
+            .get(peer_id)
+            .map(|entry| entry.last_addr)
+            .ok_or_else(|| UnknownPeer(peer_id.to_string()).into())
     }
 
-    pub async fn list_peers(&self) -> Vec<PeerInfo> {
-        let map = self.peers.lock().await;
-        map.values().map(|p| p.info.clone()).collect()
+    /// Ask `peer_id` for the blocks of its chain after `since_index`.
+    /// Fire-and-forget over UDP; the `ChainResponse` arrives on the message channel.
+    pub async fn request_chain(&self, peer_id: &str, since_index: u64) -> anyhow::Result<()> {
+        let addr = self.last_addr_of(peer_id).await?;
+        let request = NetworkMessage::ChainRequest { from: self.id.clone(), since_index };
+        self.tcp_manager.outbound.send_msg(&request, addr).await
     }
 
-    /// Send a message via TCP if connection exists, otherwise fallback to UDP.
-    pub async fn send_message(
+    /// Answer a `ChainRequest` from `peer_id` with the leading `blocks` (each
+    /// one block's JSON, in chain order) of our chain on `network_id` that
+    /// fit one TCP frame, or one datagram without a TCP connection. Returns
+    /// how many were sent; the reply has `more` set when that is fewer than
+    /// all of them.
+    pub async fn send_chain_response(
         &self,
         peer_id: &str,
-        payload_json: String,
-    ) -> anyhow::Result<()> {
-        // First, try to establish TCP connection if we don't have one
-        if !self.has_tcp_connection(peer_id).await {
-            info!("🔄 No TCP connection to {}, requesting one...", peer_id);
-            // Try to request TCP connection
-            if let Err(e) = self.request_tcp_connection(peer_id).await {
-                warn!("Failed to request TCP connection to {}: {}, using UDP", peer_id, e);
-            } else {
-                // Wait a bit for TCP connection to be established
-                tokio::time::sleep(tokio::time::Duration::from_millis(1000)).await;
+        network_id: &str,
+        blocks: &[String],
+    ) -> anyhow::Result<usize> {
+        let tcp = self.has_tcp_connection(peer_id).await;
+        let limit = if tcp { MAX_TCP_FRAME } else { MAX_DGRAM };
+        let reply = |count: usize| NetworkMessage::ChainResponse {
+            from: self.id.clone(),
+            blocks_json: format!("[{}]", blocks[..count].join(",")),
+            more: count < blocks.len(),
+            network_id: Some(network_id.to_string()),
+        };
+        // Each block adds its escaped length plus a separating comma; the
+        // one spare byte covers `more` flipping from `true` to `false`.
+        let mut size = serde_json::to_vec(&reply(0))?.len() + 1;
+        let mut count = 0;
+        for block in blocks {
+            size += serde_json::to_string(block)?.len() - 2 + usize::from(count > 0);
+            if size > limit {
+                break;
             }
+            count += 1;
         }
-
+        if count == 0 && !blocks.is_empty() {
+            anyhow::bail!("block too large for a {} reply", if tcp { "TCP" } else { "UDP" });
+        }
+        let response = reply(count);
+        if tcp {
+            self.send_frame(peer_id, &response).await?;
+        } else {
+            let addr = self.last_addr_of(peer_id).await?;
+            self.tcp_manager.outbound.send_msg(&response, addr).await?;
+        }
+        Ok(count)
+    }
+
+    /// Drop all traffic from `peer_id`: forget it, close its TCP connection
+    /// and discard anything queued for it.
+    pub async fn block_peer(&self, peer_id: &str) {
+        self.blocked.write().await.insert(peer_id.to_string());
+        self.peers.lock().await.remove(peer_id);
+        self.outbox.lock().await.remove(peer_id);
+        self.tcp_manager.remove_connection(peer_id).await;
+    }
+
+    /// Accept traffic from `peer_id` again; `false` if it wasn't blocked.
+    pub async fn unblock_peer(&self, peer_id: &str) -> bool {
+        self.blocked.write().await.remove(peer_id)
+    }
+
+    pub async fn is_blocked(&self, peer_id: &str) -> bool {
+        self.blocked.read().await.contains(peer_id)
+    }
+
+    /// Blocked peer ids, sorted.
+    pub async fn blocked_peers(&self) -> Vec<String> {
+        let mut ids: Vec<String> = self.blocked.read().await.iter().cloned().collect();
+        ids.sort();
+        ids
+    }
+
+    /// Number of messages waiting in the outbox for `peer_id`.
+    pub async fn pending_count(&self, peer_id: &str) -> usize {
+        self.outbox.lock().await.get(peer_id).map_or(0, VecDeque::len)
+    }
+
+    /// Hold `payload_json` until `peer_id` announces itself.
+    async fn enqueue(&self, peer_id: &str, payload_json: String) -> anyhow::Result<Delivery> {
+        let mut outbox = self.outbox.lock().await;
+        let queue = outbox.entry(peer_id.to_string()).or_default();
+        if queue.len() >= MAX_QUEUED_PER_PEER {
+            return Err(anyhow::anyhow!(
+                "Outbox for {} is full ({} messages)",
+                peer_id,
+                MAX_QUEUED_PER_PEER
+            ));
+        }
+        queue.push_back(payload_json);
+        info!("📥 Queued message for offline peer {} ({} pending)", peer_id, queue.len());
+        Ok(Delivery::Queued { pending: queue.len() })
+    }
+
+    /// Base interval of the periodic announce + ping. The running broadcast
+    /// task restarts from it right away; it still widens while peers are stable.
+    pub fn set_broadcast_interval(&self, interval: Duration) {
+        self.pacing.update(|pacer| pacer.set_base(interval));
+    }
+
+    /// Current wait between broadcast rounds.
+    pub fn broadcast_interval(&self) -> Duration {
+        self.pacing.pacer.lock().unwrap().current()
+    }
+
+    /// Traffic counters since the node was created, plus the current peer count.
+    pub async fn metrics(&self) -> NetworkMetrics {
+        let peer_count = self.peers.lock().await.len();
+        self.tcp_manager.metrics.snapshot(peer_count)
+    }
+
+    /// Force an immediate announce + ping (used by Find Peers button), and
+    /// return the periodic broadcast to its base interval.
+    pub async fn ping_now(&self) -> anyhow::Result<()> {
+        self.pacing.update(|pacer| pacer.reset());
+        let me = self.announcer();
+        let announce = me.peer().await;
+        let ping = me.ping().await;
+
+        let outbound = &self.tcp_manager.outbound;
+        if self.family.v4() {
+            let broadcast_addr = SocketAddr::new(IpAddr::V4(Ipv4Addr::BROADCAST), self.port);
+            outbound.send_msg(&announce, broadcast_addr).await?;
+            outbound.send_msg(&ping, broadcast_addr).await?;
+        }
+        if self.family.v6() {
+            let group = discovery_target_v6(self.port);
+            outbound.send_msg(&announce, group).await?;
+            outbound.send_msg(&ping, group).await?;
+        }
+
+        Ok(())
+    }
+
+    /// Gossip `block_json` (a serialized block, or a whole chain) to the LAN as
+    /// a `NetworkMessage::Block` on the discovery broadcast/multicast address
+    /// of each enabled family. Fails if it doesn't fit in one datagram.
+    ///
+    /// Receivers pass it on like any other message, but the app currently
+    /// ignores it: chains are synced with `ChainRequest` resyncs instead.
+    pub async fn broadcast_block(&self, block_json: String) -> anyhow::Result<()> {
+        let bytes = serde_json::to_vec(&NetworkMessage::Block { block_json })?;
+        if bytes.len() > MAX_DGRAM {
+            anyhow::bail!("block is {} bytes; a broadcast datagram holds at most {MAX_DGRAM}", bytes.len());
+        }
+        let outbound = &self.tcp_manager.outbound;
+        if self.family.v4() {
+            outbound.send(&bytes, SocketAddr::new(IpAddr::V4(Ipv4Addr::BROADCAST), self.port)).await?;
+        }
+        if self.family.v6() {
+            outbound.send(&bytes, discovery_target_v6(self.port)).await?;
+        }
+        Ok(())
+    }
+
+    /// Set how long a quiet peer stays in the roster (marked `away` after half
+    /// of it). Applies from the next sweep.
+    pub async fn set_peer_timeout(&self, timeout: Duration) {
+        *self.peer_timeout.lock().await = timeout;
+    }
+
+    /// Current peer timeout.
+    pub async fn peer_timeout(&self) -> Duration {
+        *self.peer_timeout.lock().await
+    }
+
+    /// Set how long a reachability result stays fresh before the peer is re‑probed.
+    pub async fn set_reachability_ttl(&self, ttl: Duration) {
+        *self.reachability_ttl.lock().await = ttl;
+    }
+
+    /// Probe a peer with a directed ping and wait for its pong.
+    ///
+    /// Results are cached for the reachability TTL; any inbound datagram from
+    /// the peer also refreshes the cache, so a chatty UI doesn't flood the LAN.
+    pub async fn probe_peer(&self, peer_id: &str) -> anyhow::Result<bool> {
+        let ttl = { *self.reachability_ttl.lock().await };
+        if let Some(cached) = self.reachability.lock().await.get(peer_id) {
+            if cached.checked_at.elapsed() < ttl {
+                return Ok(cached.reachable);
+            }
+        }
+
+        let addr = {
+            let peers = self.peers.lock().await;
+            peers
+                .get(peer_id)
+                .map(|p| p.last_addr)
+                .ok_or_else(|| UnknownPeer(peer_id.to_string()))?
+        };
+
+        // The pong comes back to the shared outbound socket, whose reader
+        // hands it to this waiter.
+        let ping = self.announcer().ping().await;
+        let (done_tx, done_rx) = oneshot::channel();
+        self.tcp_manager.pending_pongs.lock().await.insert(addr, done_tx);
+        if let Err(e) = self.tcp_manager.outbound.send_msg(&ping, addr).await {
+            self.tcp_manager.pending_pongs.lock().await.remove(&addr);
+            return Err(e);
+        }
+        let answered = timeout(PROBE_TIMEOUT, done_rx).await;
+        self.tcp_manager.pending_pongs.lock().await.remove(&addr);
+        let reachable = matches!(answered, Ok(Ok(id)) if id == peer_id);
+
+        self.reachability.lock().await.insert(
+            peer_id.to_string(),
+            Reachability { reachable, checked_at: Instant::now() },
+        );
+        debug!("probe {} -> {}", peer_id, if reachable { "reachable" } else { "unreachable" });
+        Ok(reachable)
+    }
+
+    /// Add the node whose discovery socket is at `addr` without waiting for
+    /// discovery (other subnets, VPNs, broadcast blocked): ping it from our
+    /// discovery socket and, once it pongs, it is in the roster like any
+    /// announced peer (and it has learned our address from the ping).
+    pub async fn add_peer_by_addr(&self, addr: SocketAddr) -> anyhow::Result<PeerInfo> {
+        let socket = self
+            .discovery
+            .lock()
+            .await
+            .iter()
+            .find(|s| s.local_addr().is_ok_and(|local| local.is_ipv4() == addr.is_ipv4()))
+            .cloned()
+            .ok_or_else(|| {
+                let family = if addr.is_ipv4() { "IPv4" } else { "IPv6" };
+                anyhow::anyhow!("not listening on {family} (node stopped or family disabled)")
+            })?;
+        let ping = serde_json::to_vec(&self.announcer().ping().await)?;
+        let (done_tx, mut done_rx) = oneshot::channel();
+        self.tcp_manager.pending_pongs.lock().await.insert(addr, done_tx);
+
+        let mut answered = None;
+        for _ in 0..ADD_PEER_ATTEMPTS {
+            if let Err(e) = socket.send_to(&ping, addr).await {
+                self.tcp_manager.pending_pongs.lock().await.remove(&addr);
+                anyhow::bail!("cannot ping {addr}: {e}");
+            }
+            self.tcp_manager.metrics.datagram_sent(ping.len());
+            if let Ok(Ok(id)) = timeout(ADD_PEER_RETRY, &mut done_rx).await {
+                answered = Some(id);
+                break;
+            }
+        }
+        self.tcp_manager.pending_pongs.lock().await.remove(&addr);
+        let Some(id) = answered else {
+            anyhow::bail!(
+                "no reply from {addr} within {:?}; is a WiChain node listening on that port?",
+                ADD_PEER_RETRY * ADD_PEER_ATTEMPTS
+            );
+        };
+        let peers = self.peers.lock().await;
+        peers
+            .get(&id)
+            .map(|p| p.info.clone())
+            .ok_or_else(|| anyhow::anyhow!("{id} answered from {addr} but has already left the roster"))
+    }
+
+    /// Cached reachability check (unknown peers are unreachable).
+    pub async fn is_peer_reachable(&self, peer_id: &str) -> bool {
+        self.probe_peer(peer_id).await.unwrap_or(false)
+    }
+
+    pub async fn list_peers(&self) -> Vec<PeerInfo> {
+        let map = self.peers.lock().await;
+        map.values().map(|p| p.info.clone()).collect()
+    }
+
+    /// Send a message via TCP if connection exists, otherwise fallback to UDP.
+    pub async fn send_message(
+        &self,
+        peer_id: &str,
+        payload_json: String,
+    ) -> anyhow::Result<Delivery> {
+        // Not discovered (or already gone): park it until the peer announces itself.
+        let known = self.peers.lock().await.contains_key(peer_id);
+        if !known && !self.has_tcp_connection(peer_id).await {
+            return self.enqueue(peer_id, payload_json).await;
+        }
+
+        // First, try to establish TCP connection if we don't have one
+        if !self.has_tcp_connection(peer_id).await {
+            info!("🔄 No TCP connection to {}, requesting one...", peer_id);
+            // Try to request TCP connection
+            if let Err(e) = self.request_tcp_connection(peer_id).await {
+                warn!("Failed to request TCP connection to {}: {}, using UDP", peer_id, e);
+            } else {
+                // Wait a bit for TCP connection to be established
+                tokio::time::sleep(tokio::time::Duration::from_millis(1000)).await;
+            }
+        }
+
         // Try TCP first if we have a connection
         if self.has_tcp_connection(peer_id).await {
             if let Ok(()) = self.send_via_tcp(peer_id, &payload_json).await {
                 info!("✅ Message sent via TCP to {}", peer_id);
-                return Ok(());
+                return Ok(Delivery::Tcp);
             } else {
                 warn!("TCP connection exists but send failed, falling back to UDP");
             }
@@ -346,118 +1519,183 @@ impl NetworkNode {
         self.send_direct_block(peer_id, payload_json).await
     }
 
-    /// Send message via TCP connection.
-    async fn send_via_tcp(&self, peer_id: &str, payload: &str) -> anyhow::Result<()> {
-        let connections = self.tcp_manager.connections.read().await;
-        if let Some(conn) = connections.get(peer_id) {
-            if conn.is_connected {
-                let mut stream = conn.stream.lock().await;
-                
-                // Wrap payload in NetworkMessage::DirectBlock (same as UDP)
-                let wrapped_message = NetworkMessage::DirectBlock {
-                    from: self.id.clone(),
-                    to: peer_id.to_string(),
-                    payload_json: payload.to_string(),
-                };
-                
-                let message = format!("{}\n", serde_json::to_string(&wrapped_message)?);
-                
-                // Use timeout for TCP operations
-                let result = timeout(
-                    TokioDuration::from_secs(TCP_MESSAGE_TIMEOUT.as_secs()),
-                    stream.write_all(message.as_bytes())
-                ).await;
-                
-                match result {
-                    Ok(Ok(())) => {
-                        stream.flush().await?;
-                        debug!("Message sent via TCP to {} ({} bytes)", peer_id, message.len());
-                        return Ok(());
-                    }
-                    Ok(Err(e)) => {
-                        warn!("TCP write error to {}: {}", peer_id, e);
-                        return Err(anyhow::anyhow!("TCP write error: {}", e));
-                    }
-                    Err(_) => {
-                        warn!("TCP write timeout to {}", peer_id);
-                        return Err(anyhow::anyhow!("TCP write timeout"));
+    /// Send one group ciphertext to `members` of `group_id`.
+    ///
+    /// The `GroupBlock` goes out once to the discovery broadcast/multicast
+    /// address of each bound family; members that haven't acked it within
+    /// `ACK_TIMEOUT` (elsewhere on the network, on another port, or the
+    /// datagram was lost or too large) get `payload_json` via `send_message`.
+    /// Returns each member's outcome, in `members` order.
+    pub async fn send_group_block(
+        &self,
+        group_id: &str,
+        members: &[String],
+        payload_json: String,
+    ) -> Vec<(String, anyhow::Result<Delivery>)> {
+        let msg_id = uuid::Uuid::new_v4().to_string();
+        let msg = NetworkMessage::GroupBlock {
+            from: self.id.clone(),
+            group_id: group_id.to_string(),
+            msg_id: msg_id.clone(),
+            payload_json: payload_json.clone(),
+        };
+        let mut acked = vec![false; members.len()];
+        match serde_json::to_vec(&msg) {
+            Ok(bytes) if bytes.len() <= MAX_DGRAM => {
+                let mut waiters: Vec<_> = members
+                    .iter()
+                    .map(|m| self.tcp_manager.outbound.expect_ack(&group_ack_id(&msg_id, m)))
+                    .collect();
+                for target in self.broadcast_targets().await {
+                    if let Err(e) = self.tcp_manager.outbound.send(&bytes, target).await {
+                        debug!("Group block broadcast to {} failed: {}", target, e);
                     }
                 }
+                let deadline = Instant::now() + ACK_TIMEOUT;
+                for (acked, waiter) in acked.iter_mut().zip(&mut waiters) {
+                    *acked = waiter.wait(deadline.saturating_duration_since(Instant::now())).await;
+                }
+            }
+            Ok(_) => debug!("Group block for {} too large to broadcast", group_id),
+            Err(e) => warn!("Group block for {} not serializable: {}", group_id, e),
+        }
+
+        info!(
+            "👥 group {} -> {} of {} member(s) acked the broadcast",
+            group_id,
+            acked.iter().filter(|a| **a).count(),
+            members.len()
+        );
+        let mut results = Vec::with_capacity(members.len());
+        for (member, acked) in members.iter().zip(acked) {
+            let result = if acked {
+                Ok(Delivery::Udp { attempts: 1 })
+            } else {
+                self.send_message(member, payload_json.clone()).await
+            };
+            results.push((member.clone(), result));
+        }
+        results
+    }
+
+    /// Where a `GroupBlock` is broadcast: the discovery target of each bound family.
+    async fn broadcast_targets(&self) -> Vec<SocketAddr> {
+        self.discovery
+            .lock()
+            .await
+            .iter()
+            .filter_map(|socket| socket.local_addr().ok())
+            .map(|addr| match addr {
+                SocketAddr::V4(_) => SocketAddr::new(IpAddr::V4(Ipv4Addr::BROADCAST), self.port),
+                SocketAddr::V6(_) => discovery_target_v6(self.port),
+            })
+            .collect()
+    }
+
+    /// Send message via TCP connection.
+    async fn send_via_tcp(&self, peer_id: &str, payload: &str) -> anyhow::Result<()> {
+        // Wrap payload in NetworkMessage::DirectBlock (same as UDP)
+        let wrapped_message = NetworkMessage::DirectBlock {
+            from: self.id.clone(),
+            to: peer_id.to_string(),
+            msg_id: uuid::Uuid::new_v4().to_string(),
+            payload_json: payload.to_string(),
+        };
+        self.send_frame(peer_id, &wrapped_message).await
+    }
+
+    /// Write one framed message on the peer's live TCP connection. A failed
+    /// or timed-out write drops the connection, so later sends use UDP.
+    async fn send_frame(&self, peer_id: &str, msg: &NetworkMessage) -> anyhow::Result<()> {
+        let stream = {
+            let connections = self.tcp_manager.connections.read().await;
+            match connections.get(peer_id) {
+                Some(conn) if conn.is_connected => conn.stream.clone(),
+                _ => return Err(anyhow::anyhow!("No TCP connection to peer {}", peer_id)),
+            }
+        };
+        let message = encode_frame(msg)?;
+        let result = timeout(TCP_MESSAGE_TIMEOUT, async {
+            let mut stream = stream.lock().await;
+            stream.write_all(&message).await?;
+            stream.flush().await
+        })
+        .await;
+
+        let error = match result {
+            Ok(Ok(())) => {
+                self.tcp_manager.metrics.tcp_sent(message.len());
+                self.tcp_manager.history.sent(peer_id);
+                debug!("Message sent via TCP to {} ({} bytes)", peer_id, message.len());
+                return Ok(());
             }
+            Ok(Err(e)) => anyhow::anyhow!("TCP write error: {}", e),
+            Err(_) => anyhow::anyhow!("TCP write timeout"),
+        };
+        warn!("{} to {}; dropping the connection", error, peer_id);
+        if self.tcp_manager.is_current(peer_id, &Arc::downgrade(&stream)).await {
+            self.tcp_manager.remove_connection(peer_id).await;
         }
-        Err(anyhow::anyhow!("No TCP connection to peer {}", peer_id))
+        Err(error)
     }
 
     /// Request TCP connection to a peer.
     pub async fn request_tcp_connection(&self, peer_id: &str) -> anyhow::Result<()> {
-        let peers = self.peers.lock().await;
-        if let Some(peer) = peers.get(peer_id) {
-            let alias = { self.alias.lock().await.clone() };
-            let tcp_port = self.tcp_manager.tcp_port;
-            
-            let request = NetworkMessage::TcpConnectionRequest {
-                from: self.id.clone(),
-                from_alias: alias.clone(),
-                tcp_port,
-            };
+        let (last_addr, peer_tcp_port, peer_alias) = {
+            let peers = self.peers.lock().await;
+            let peer = peers.get(peer_id).ok_or_else(|| UnknownPeer(peer_id.to_string()))?;
+            (peer.last_addr, peer.tcp_port, peer.info.alias.clone())
+        };
+        let alias = { self.alias.lock().await.clone() };
+        let tcp_port = self.tcp_manager.tcp_port();
 
-            // Send via UDP
-            let bind_addr = "0.0.0.0:0";
-            let socket = UdpSocket::bind(bind_addr).await?;
-            socket.send_to(&serde_json::to_vec(&request)?, peer.last_addr).await?;
-            
-            info!("TCP connection request sent to {} ({})", peer_id, peer.info.alias);
-            
-            // Wait a bit for the response and then try to establish TCP connection
-            tokio::time::sleep(tokio::time::Duration::from_millis(200)).await;
-            
-            // Try to establish TCP connection directly
-            if let Some(peer_tcp_port) = peer.tcp_port {
-                let peer_addr = format!("{}:{}", peer.last_addr.ip(), peer_tcp_port);
-                match TokioTcpStream::connect(&peer_addr).await {
-                    Ok(mut stream) => {
-                        // Send handshake message
-                        let handshake = NetworkMessage::TcpHandshake {
-                            from: self.id.clone(),
-                            from_alias: alias,
-                            pubkey: self.pubkey.clone(),
-                        };
-                        
-                        let handshake_json = serde_json::to_string(&handshake)?;
-                        let handshake_msg = format!("{}\n", handshake_json);
-                        stream.write_all(handshake_msg.as_bytes()).await?;
-                        stream.flush().await?;
-                        
-                        let conn = TcpConnection {
-                            stream: Arc::new(Mutex::new(stream)),
-                            peer_id: peer_id.to_string(),
-                            last_activity: Instant::now(),
-                            is_connected: true,
-                            message_count: 0,
-                            last_test_time: None,
-                            handshake_completed: true,
-                        };
-                        
-                        let mut connections = self.tcp_manager.connections.write().await;
-                        connections.insert(peer_id.to_string(), conn);
-                        
-                        info!("✅ TCP connection established to {} ({}) with handshake", peer_id, peer.info.alias);
-                    }
-                    Err(e) => {
-                        warn!("Failed to establish TCP connection to {}: {}", peer_id, e);
+        let request = NetworkMessage::TcpConnectionRequest {
+            from: self.id.clone(),
+            from_alias: alias.clone(),
+            tcp_port,
+        };
+
+        // Send via UDP
+        self.tcp_manager.outbound.send_msg(&request, last_addr).await?;
+
+        info!("TCP connection request sent to {} ({})", peer_id, peer_alias);
+
+        // Wait a bit for the response and then try to establish TCP connection
+        tokio::time::sleep(tokio::time::Duration::from_millis(200)).await;
+
+        // Try to establish TCP connection directly
+        if let Some(peer_tcp_port) = peer_tcp_port {
+            let peer_addr = SocketAddr::new(last_addr.ip(), peer_tcp_port);
+            match TokioTcpStream::connect(peer_addr).await {
+                Ok(mut stream) => {
+                    // Send handshake message
+                    let handshake = NetworkMessage::TcpHandshake {
+                        from: self.id.clone(),
+                        from_alias: alias,
+                        pubkey: self.pubkey.clone(),
+                    };
+
+                    stream.write_all(&encode_frame(&handshake)?).await?;
+                    stream.flush().await?;
+
+                    if self.tcp_manager.attach(peer_id, stream, &self.id).await {
+                        info!("✅ TCP connection established to {} ({}) with handshake", peer_id, peer_alias);
+                    } else {
+                        warn!("TCP connection to {} ({}) was not confirmed", peer_id, peer_alias);
                     }
                 }
+                Err(e) => {
+                    warn!("Failed to establish TCP connection to {}: {}", peer_id, e);
+                }
             }
-            
-            Ok(())
-        } else {
-            Err(anyhow::anyhow!("Peer not found: {}", peer_id))
         }
+
+        Ok(())
     }
 
     /// Get TCP port for this node.
     pub fn get_tcp_port(&self) -> u16 {
-        self.tcp_manager.tcp_port
+        self.tcp_manager.tcp_port()
     }
 
     /// Check if we have a TCP connection to a peer.
@@ -467,26 +1705,39 @@ impl NetworkNode {
     }
 
     /// Test TCP connection to a peer and measure response time.
+    ///
+    /// Sends a `TcpConnectionTest` and waits for the peer to echo it back on
+    /// the same stream; the round trip is recorded in the connection stats.
     pub async fn test_tcp_connection(&self, peer_id: &str) -> anyhow::Result<u64> {
-        let start_time = std::time::Instant::now();
-        let timestamp = std::time::SystemTime::now()
-            .duration_since(std::time::UNIX_EPOCH)
-            .unwrap_or_default()
-            .as_millis() as u64;
+        let (timestamp, done_rx) = self.tcp_manager.expect_test_response(peer_id).await;
+        let key = (peer_id.to_string(), timestamp);
 
         let test_message = NetworkMessage::TcpConnectionTest {
             from: self.id.clone(),
             timestamp,
         };
+        let start_time = Instant::now();
+        if let Err(e) = self.send_frame(peer_id, &test_message).await {
+            self.tcp_manager.pending_tests.lock().await.remove(&key);
+            return Err(e);
+        }
 
-        // Send test message via TCP
-        self.send_via_tcp(peer_id, &serde_json::to_string(&test_message)?).await?;
-
-        // Wait for response (simplified - in real implementation, you'd need to handle responses)
-        let response_time = start_time.elapsed().as_millis() as u64;
-        
-        info!("TCP connection test to {} completed in {}ms", peer_id, response_time);
-        Ok(response_time)
+        match timeout(TCP_TEST_TIMEOUT, done_rx).await {
+            Ok(Ok(())) => {
+                let response_time = start_time.elapsed().as_millis() as u64;
+                self.tcp_manager.record_rtt(peer_id, response_time).await;
+                info!("TCP connection test to {} completed in {}ms", peer_id, response_time);
+                Ok(response_time)
+            }
+            _ => {
+                self.tcp_manager.pending_tests.lock().await.remove(&key);
+                Err(anyhow::anyhow!(
+                    "No TCP test response from {} within {:?}",
+                    peer_id,
+                    TCP_TEST_TIMEOUT
+                ))
+            }
+        }
     }
 
     /// Get detailed connection statistics for a peer.
@@ -499,6 +1750,8 @@ impl NetworkNode {
                 message_count: conn.message_count,
                 last_activity_ms: conn.last_activity.elapsed().as_millis() as u64,
                 last_test_time_ms: conn.last_test_time.map(|t| t.elapsed().as_millis() as u64),
+                avg_rtt_ms: conn.avg_rtt_ms(),
+                messages_per_min: conn.messages_per_min(),
             })
         } else {
             None
@@ -516,18 +1769,20 @@ impl NetworkNode {
 }
 
 impl TcpConnectionManager {
+    fn tcp_port(&self) -> u16 {
+        self.tcp_port.load(Ordering::Relaxed)
+    }
+
     /// Start TCP listener for incoming connections (static method).
     async fn start_tcp_listener_static(
+        listener: TokioTcpListener,
         tcp_manager: Arc<TcpConnectionManager>,
-        _node_id: String,
-        _alias: Arc<Mutex<String>>,
-        _pubkey: String,
+        node_id: String,
+        recent: Arc<Mutex<RecentMessages>>,
+        blocked: Blocklist,
         tx: mpsc::Sender<NetworkMessage>,
-    ) -> anyhow::Result<()> {
-        let bind_addr = format!("0.0.0.0:{}", tcp_manager.tcp_port);
-        let listener = TokioTcpListener::bind(&bind_addr).await?;
-        info!("✅ TCP listener started on {}", bind_addr);
-        
+        shutdown: watch::Receiver<bool>,
+    ) {
         // Start accepting connections
         loop {
             match listener.accept().await {
@@ -538,8 +1793,11 @@ impl TcpConnectionManager {
                     // We'll determine the real peer_id during handshake
                     let tx_clone = tx.clone();
                     let tcp_manager_clone = tcp_manager.clone();
-                    tokio::spawn(async move {
-                        if let Err(e) = Self::handle_tcp_connection_reading(stream, addr, tx_clone, tcp_manager_clone).await {
+                    let recent = recent.clone();
+                    let blocked = blocked.clone();
+                    let node_id = node_id.clone();
+                    spawn_until_shutdown(shutdown.clone(), async move {
+                        if let Err(e) = Self::handle_tcp_connection_reading(stream, addr, node_id, tx_clone, tcp_manager_clone, recent, blocked).await {
                             error!("TCP connection reading error: {e:?}");
                         }
                     });
@@ -557,10 +1815,13 @@ impl TcpConnectionManager {
     async fn handle_tcp_connection_reading(
         mut stream: TokioTcpStream,
         addr: SocketAddr,
+        node_id: String,
         tx: mpsc::Sender<NetworkMessage>,
         tcp_manager: Arc<TcpConnectionManager>,
+        recent: Arc<Mutex<RecentMessages>>,
+        blocked: Blocklist,
     ) -> anyhow::Result<()> {
-        let mut buffer = String::new();
+        let mut buffer: Vec<u8> = Vec::new();
         let mut read_buf = vec![0u8; 4096];
         let mut peer_id: Option<String> = None;
         let mut handshake_completed = false;
@@ -572,18 +1833,35 @@ impl TcpConnectionManager {
                     break;
                 }
                 Ok(n) => {
-                    let data = String::from_utf8_lossy(&read_buf[..n]);
-                    buffer.push_str(&data);
+                    buffer.extend_from_slice(&read_buf[..n]);
                     
-                    // Process complete messages (separated by newlines)
-                    while let Some(newline_pos) = buffer.find('\n') {
-                        let message = buffer[..newline_pos].trim().to_string();
-                        buffer = buffer[newline_pos + 1..].to_string();
+                    // Process complete length-prefixed frames; partial ones wait for more reads
+                    loop {
+                        let message = match take_frame(&mut buffer) {
+                            Ok(Some(frame)) => frame,
+                            Ok(None) => break,
+                            Err(e) => {
+                                warn!("Closing TCP connection from {}: {}", addr, e);
+                                if let Some(ref pid) = peer_id {
+                                    tcp_manager.remove_connection(pid).await;
+                                }
+                                return Ok(());
+                            }
+                        };
                         
                         if !message.is_empty() {
+                            tcp_manager.metrics.tcp_received(4 + message.len());
                             // Try to parse as NetworkMessage
-                            if let Ok(network_msg) = serde_json::from_str::<NetworkMessage>(&message) {
+                            let parsed = serde_json::from_slice::<NetworkMessage>(&message);
+                            if parsed.is_err() {
+                                tcp_manager.metrics.parse_failure();
+                            }
+                            if let Ok(network_msg) = parsed {
                                 match &network_msg {
+                                    NetworkMessage::TcpHandshake { from, .. } if blocked.read().await.contains(from) => {
+                                        debug!("Refusing TCP connection from blocked peer {}", from);
+                                        return Ok(());
+                                    }
                                     NetworkMessage::TcpHandshake { from, from_alias, pubkey: _ } => {
                                         if !handshake_completed {
                                             peer_id = Some(from.clone());
@@ -595,9 +1873,39 @@ impl TcpConnectionManager {
                                             // This will be handled by the main application when it receives the handshake message
                                         }
                                     }
+                                    NetworkMessage::TcpConnectionTest { from, timestamp } if handshake_completed => {
+                                        // Echo on this stream; the tester's `read_replies` resolves its waiter.
+                                        let response = NetworkMessage::TcpConnectionTestResponse {
+                                            from: node_id.clone(),
+                                            to: from.clone(),
+                                            timestamp: *timestamp,
+                                            response_time_ms: now_ms().saturating_sub(*timestamp),
+                                        };
+                                        let frame = encode_frame(&response)?;
+                                        stream.write_all(&frame).await?;
+                                        stream.flush().await?;
+                                    }
                                     _ => {
                                         if let Some(ref pid) = peer_id {
                                             info!("📨 TCP message received from {}: {:?}", pid, network_msg);
+
+                                            if let NetworkMessage::TcpConnectionTestResponse { timestamp, .. } = &network_msg {
+                                                tcp_manager.record_rtt(pid, now_ms().saturating_sub(*timestamp)).await;
+                                            }
+
+                                            if let NetworkMessage::TcpKeepalive { .. } = &network_msg {
+                                                // The sender only counts the connection as alive on our echo.
+                                                let echo = NetworkMessage::TcpKeepalive { from: node_id.clone() };
+                                                stream.write_all(&encode_frame(&echo)?).await?;
+                                                stream.flush().await?;
+                                            }
+
+                                            if let NetworkMessage::DirectBlock { from, msg_id, payload_json, .. } = &network_msg {
+                                                if !recent.lock().await.first_sighting(from, msg_id, payload_json, Instant::now()) {
+                                                    debug!("Dropping duplicate TCP direct block from {}", pid);
+                                                    continue;
+                                                }
+                                            }
                                             
                                             // Send to main message handler
                                             if let Err(e) = tx.send(network_msg).await {
@@ -608,17 +1916,17 @@ impl TcpConnectionManager {
                                             {
                                                 let mut connections = tcp_manager.connections.write().await;
                                                 if let Some(conn) = connections.get_mut(pid) {
-                                                    conn.last_activity = Instant::now();
-                                                    conn.message_count += 1;
+                                                    conn.record_message();
                                                 }
                                             }
+                                            tcp_manager.history.received(pid);
                                         } else {
                                             warn!("Received message before handshake completed from {}", addr);
                                         }
                                     }
                                 }
                             } else {
-                                warn!("Failed to parse TCP message from {}: {}", addr, message);
+                                warn!("Failed to parse TCP message from {} ({} bytes)", addr, message.len());
                             }
                         }
                     }
@@ -630,74 +1938,220 @@ impl TcpConnectionManager {
             }
         }
         
-        // Store connection if handshake was completed
-        if let Some(ref pid) = peer_id {
-            if handshake_completed {
-                let conn = TcpConnection {
-                    stream: Arc::new(Mutex::new(stream)),
-                    peer_id: pid.clone(),
-                    last_activity: Instant::now(),
-                    is_connected: true,
-                    message_count: 0,
-                    last_test_time: None,
-                    handshake_completed: true,
-                };
-                
-                let mut connections = tcp_manager.connections.write().await;
-                connections.insert(pid.clone(), conn);
-            }
-        }
-        
-        // Remove connection when done
+        // Remove connection when done (peer falls back to UDP)
         if let Some(ref pid) = peer_id {
-            let mut connections = tcp_manager.connections.write().await;
-            connections.remove(pid);
+            tcp_manager.remove_connection(pid).await;
         }
         
         Ok(())
     }
 
-    /// Handle incoming TCP connection.
-    #[allow(dead_code)]
-    async fn handle_incoming_tcp_connection(
-        &self,
-        stream: TokioTcpStream,
-        peer_id: String,
-    ) -> anyhow::Result<()> {
-        let conn = TcpConnection {
-            stream: Arc::new(Mutex::new(stream)),
-            peer_id: peer_id.clone(),
-            last_activity: Instant::now(),
-            is_connected: true,
-            message_count: 0,
-            last_test_time: None,
-            handshake_completed: false,
+    /// Register an outbound, handshaken stream to `peer_id`, start reading
+    /// the replies (test responses) the peer writes back on it, and probe it
+    /// with a `TcpConnectionTest`. The connection only counts as connected
+    /// once the peer echoes the probe within `TCP_TEST_TIMEOUT`; otherwise it
+    /// is dropped. Returns whether it was confirmed.
+    async fn attach(self: &Arc<Self>, peer_id: &str, stream: TokioTcpStream, my_id: &str) -> bool {
+        let (read_half, write_half) = stream.into_split();
+        let conn = TcpConnection::new(write_half, peer_id.to_string(), true);
+        let writer = conn.stream.clone();
+        self.register_connection(peer_id, conn).await;
+
+        let manager = self.clone();
+        let reader_peer = peer_id.to_string();
+        let reader_writer = Arc::downgrade(&writer);
+        spawn_until_shutdown(self.shutdown.clone(), async move {
+            if let Err(e) = manager.read_replies(read_half, &reader_peer).await {
+                debug!("TCP reply reader for {} stopped: {}", reader_peer, e);
+            }
+            // Only drop the entry if it is still this connection, not a newer one.
+            if manager.is_current(&reader_peer, &reader_writer).await {
+                manager.remove_connection(&reader_peer).await;
+            }
+        });
+
+        let (timestamp, echoed) = self.expect_test_response(peer_id).await;
+        let probe = NetworkMessage::TcpConnectionTest { from: my_id.to_string(), timestamp };
+        let sent = match encode_frame(&probe) {
+            Ok(frame) => timeout(TCP_MESSAGE_TIMEOUT, async {
+                let mut stream = writer.lock().await;
+                stream.write_all(&frame).await?;
+                stream.flush().await?;
+                Ok::<_, std::io::Error>(frame.len())
+            })
+            .await
+            .ok()
+            .and_then(|r| r.ok()),
+            Err(_) => None,
         };
+        if let Some(len) = sent {
+            self.metrics.tcp_sent(len);
+            self.history.sent(peer_id);
+            if matches!(timeout(TCP_TEST_TIMEOUT, echoed).await, Ok(Ok(()))) {
+                return self.confirm_connection(peer_id, &writer).await;
+            }
+        }
+        debug!("TCP connection to {} never answered its probe; dropping it", peer_id);
+        self.pending_tests.lock().await.remove(&(peer_id.to_string(), timestamp));
+        if self.is_current(peer_id, &Arc::downgrade(&writer)).await {
+            self.remove_connection(peer_id).await;
+        }
+        false
+    }
+
+    /// Whether `peer_id`'s registered connection is the one writing through `writer`.
+    async fn is_current(&self, peer_id: &str, writer: &Weak<Mutex<OwnedWriteHalf>>) -> bool {
+        let connections = self.connections.read().await;
+        connections
+            .get(peer_id)
+            .is_some_and(|c| std::ptr::eq(Arc::as_ptr(&c.stream), writer.as_ptr()))
+    }
+
+    /// Register a waiter for the echo of a `TcpConnectionTest` to `peer_id`.
+    /// Returns the timestamp to send, unique among the peer's pending tests.
+    async fn expect_test_response(&self, peer_id: &str) -> (u64, oneshot::Receiver<()>) {
+        let (done_tx, done_rx) = oneshot::channel();
+        let mut pending = self.pending_tests.lock().await;
+        let mut timestamp = now_ms();
+        while pending.contains_key(&(peer_id.to_string(), timestamp)) {
+            timestamp += 1;
+        }
+        pending.insert((peer_id.to_string(), timestamp), done_tx);
+        (timestamp, done_rx)
+    }
 
-        // Add to connections
+    /// Read frames the peer writes back on our outbound stream until it closes.
+    async fn read_replies(&self, mut read_half: OwnedReadHalf, peer_id: &str) -> anyhow::Result<()> {
+        let mut buffer: Vec<u8> = Vec::new();
+        let mut read_buf = vec![0u8; 4096];
+        loop {
+            let n = read_half.read(&mut read_buf).await?;
+            if n == 0 {
+                return Ok(());
+            }
+            buffer.extend_from_slice(&read_buf[..n]);
+            while let Some(frame) = take_frame(&mut buffer)? {
+                self.metrics.tcp_received(4 + frame.len());
+                let parsed = serde_json::from_slice::<NetworkMessage>(&frame);
+                if parsed.is_err() {
+                    self.metrics.parse_failure();
+                }
+                if let Ok(NetworkMessage::TcpConnectionTestResponse { timestamp, .. }) = parsed {
+                    if let Some(waiter) = self.pending_tests.lock().await.remove(&(peer_id.to_string(), timestamp)) {
+                        let _ = waiter.send(());
+                    }
+                }
+                if let Some(conn) = self.connections.write().await.get_mut(peer_id) {
+                    conn.record_message();
+                }
+                self.history.received(peer_id);
+            }
+        }
+    }
+
+    /// Record a connection-test round trip for a peer's live connection and history.
+    async fn record_rtt(&self, peer_id: &str, rtt_ms: u64) {
+        if let Some(conn) = self.connections.write().await.get_mut(peer_id) {
+            conn.record_rtt(rtt_ms);
+        }
+        self.history.rtt(peer_id, rtt_ms);
+    }
+
+    /// Register a connection that is not confirmed live yet. Replacing a live
+    /// one counts as a disconnect until the new one is confirmed.
+    async fn register_connection(&self, peer_id: &str, conn: TcpConnection) {
+        let replaced = { self.connections.write().await.insert(peer_id.to_string(), conn) };
+        if replaced.is_some_and(|c| c.is_connected) {
+            self.history.disconnected(peer_id);
+            self.emit_transition(peer_id, Transport::Tcp, Transport::Udp);
+        }
+    }
+
+    /// Mark the connection writing through `writer` live, emitting a UDP → TCP
+    /// transition. Returns false if it has been replaced or dropped meanwhile.
+    async fn confirm_connection(&self, peer_id: &str, writer: &Arc<Mutex<OwnedWriteHalf>>) -> bool {
         {
             let mut connections = self.connections.write().await;
-            connections.insert(peer_id.clone(), conn);
+            match connections.get_mut(peer_id) {
+                Some(conn) if Arc::ptr_eq(&conn.stream, writer) => conn.is_connected = true,
+                _ => return false,
+            }
         }
+        self.history.connected(peer_id);
+        self.emit_transition(peer_id, Transport::Udp, Transport::Tcp);
+        true
+    }
 
-        info!("TCP connection established with {}", peer_id);
-        Ok(())
+    /// Drop a connection, emitting a TCP → UDP transition if it was live.
+    async fn remove_connection(&self, peer_id: &str) {
+        let removed = { self.connections.write().await.remove(peer_id) };
+        if removed.is_some_and(|c| c.is_connected) {
+            self.history.disconnected(peer_id);
+            self.emit_transition(peer_id, Transport::Tcp, Transport::Udp);
+        }
     }
 
-    /// Clean up stale TCP connections.
-    #[allow(dead_code)]
-    async fn cleanup_stale_connections(&self) {
-        let mut connections = self.connections.write().await;
-        let now = Instant::now();
-        connections.retain(|peer_id, conn| {
-            if now.duration_since(conn.last_activity) > Duration::from_secs(300) {
-                info!("Removing stale TCP connection to {}", peer_id);
-                false
-            } else {
-                true
-            }
+    fn emit_transition(&self, peer_id: &str, from: Transport, to: Transport) {
+        info!("🔀 {} transport {:?} → {:?}", peer_id, from, to);
+        // No subscribers is fine; events are advisory.
+        let _ = self.events.send(NetworkEvent::ConnectionChanged {
+            peer_id: peer_id.to_string(),
+            from,
+            to,
         });
     }
+
+    /// Write a `TcpKeepalive` on every live connection; a failed or timed-out
+    /// write drops the connection. Only the peer's echo, read by
+    /// `read_replies`, counts as activity, so a peer that stops reading is
+    /// reaped by `cleanup_stale_connections` even while writes still succeed.
+    async fn send_keepalives(&self, my_id: &str) {
+        let frame = match encode_frame(&NetworkMessage::TcpKeepalive { from: my_id.to_string() }) {
+            Ok(f) => f,
+            Err(e) => {
+                warn!("Failed to encode keepalive: {e}");
+                return;
+            }
+        };
+        let live: Vec<(String, Arc<Mutex<OwnedWriteHalf>>)> = {
+            let connections = self.connections.read().await;
+            connections
+                .iter()
+                .filter(|(_, conn)| conn.is_connected)
+                .map(|(peer_id, conn)| (peer_id.clone(), conn.stream.clone()))
+                .collect()
+        };
+        for (peer_id, stream) in live {
+            let sent = timeout(TCP_MESSAGE_TIMEOUT, async {
+                let mut stream = stream.lock().await;
+                stream.write_all(&frame).await?;
+                stream.flush().await
+            })
+            .await;
+            if !matches!(sent, Ok(Ok(()))) {
+                info!("Keepalive to {} failed; dropping TCP connection", peer_id);
+                self.remove_connection(&peer_id).await;
+            }
+        }
+    }
+
+    /// Remove connections that are dead or idle for longer than `stale_after`,
+    /// so `has_tcp_connection` stops reporting them.
+    async fn cleanup_stale_connections(&self, stale_after: Duration) {
+        let now = Instant::now();
+        let stale: Vec<String> = {
+            let connections = self.connections.read().await;
+            connections
+                .iter()
+                .filter(|(_, conn)| !conn.is_connected || now.duration_since(conn.last_activity) > stale_after)
+                .map(|(peer_id, _)| peer_id.clone())
+                .collect()
+        };
+        for peer_id in stale {
+            info!("Removing stale TCP connection to {}", peer_id);
+            self.remove_connection(&peer_id).await;
+        }
+    }
 }
 
 async fn recv_loop(
@@ -705,12 +2159,16 @@ async fn recv_loop(
     tx: mpsc::Sender<NetworkMessage>,
     peers: Arc<Mutex<HashMap<String, PeerEntry>>>,
     my_id: String,
-    my_alias: Arc<Mutex<String>>,
-    my_pubkey: String,
-    _port: u16,
+    me: Announcer,
+    outbox: Outbox,
+    recent: Arc<Mutex<RecentMessages>>,
     tcp_manager: Arc<TcpConnectionManager>,
+    reachability: Arc<Mutex<HashMap<String, Reachability>>>,
+    blocked: Blocklist,
+    announces: SharedAnnounceMarks,
 ) {
     let mut buf = vec![0u8; MAX_DGRAM];
+    let mut reassembler = Reassembler::default();
     loop {
         let (len, src) = match socket.recv_from(&mut buf).await {
             Ok(v) => v,
@@ -719,44 +2177,135 @@ async fn recv_loop(
                 continue;
             }
         };
+        tcp_manager.metrics.datagram_received(len);
         let msg: NetworkMessage = match serde_json::from_slice(&buf[..len]) {
             Ok(m) => m,
-            Err(_) => continue,
+            Err(_) => {
+                tcp_manager.metrics.parse_failure();
+                continue;
+            }
         };
+        // Our own broadcasts loop back: never forward them or list ourselves as a peer.
+        if msg.sender_id() == Some(my_id.as_str()) {
+            continue;
+        }
+        if let Some(from) = msg.sender_id() {
+            if blocked.read().await.contains(from) {
+                continue;
+            }
+        }
+        if !msg.announce_is_authentic() {
+            debug!("Dropping unsigned or forged announce from {}", src);
+            continue;
+        }
+        if !announces.lock().await.admit(&msg, now_ms(), Instant::now()) {
+            debug!("Dropping stale or replayed announce from {}", src);
+            continue;
+        }
 
-        match &msg {
-            NetworkMessage::Peer { id, alias, pubkey } => {
-                update_peer(&peers, id, alias, pubkey, src).await;
-            }
-            NetworkMessage::Ping { id, alias } => {
-                update_peer(&peers, id, alias, id, src).await;
-                let pong = NetworkMessage::Pong {
-                    id: my_id.clone(),
-                    alias: { my_alias.lock().await.clone() },
-                };
-                let _ = send_to(&socket, &pong, src).await;
+        // Any datagram from a peer proves it is reachable right now.
+        if let Some(from) = msg.sender_id() {
+            reachability.lock().await.insert(
+                from.to_string(),
+                Reachability { reachable: true, checked_at: Instant::now() },
+            );
+        }
+
+        // Chunks are buffered until the whole DirectBlock is here.
+        let msg = match msg {
+            NetworkMessage::DirectBlockChunk { from, to, msg_id, seq, total, data } => {
+                update_peer(&peers, &from, &from, &from, src).await;
+                let chunk = Chunk { from: &from, to: &to, msg_id: &msg_id, seq, total, data: &data };
+                match reassembler.accept(chunk, Instant::now()) {
+                    Some((to, payload_json)) => NetworkMessage::DirectBlock { from, to, msg_id, payload_json },
+                    None => continue,
+                }
             }
-            NetworkMessage::Pong { id, alias } => {
-                update_peer(&peers, id, alias, id, src).await;
+            other => other,
+        };
+
+        // Acknowledge every copy (the first ACK may have been lost) but deliver once.
+        if let NetworkMessage::DirectBlock { from, msg_id, payload_json, .. } = &msg {
+            if !msg_id.is_empty() {
+                let ack = NetworkMessage::Ack { from: my_id.clone(), msg_id: msg_id.clone() };
+                if let Ok(n) = send_to(&socket, &ack, src).await {
+                    tcp_manager.metrics.datagram_sent(n);
+                }
             }
-            NetworkMessage::DirectBlock { from, .. } => {
-                update_peer(&peers, from, from, from, src).await;
+            if !recent.lock().await.first_sighting(from, msg_id, payload_json, Instant::now()) {
+                debug!("Dropping duplicate direct block from {} ({})", from, src);
+                continue;
             }
-            NetworkMessage::TcpConnectionRequest { from, from_alias, tcp_port } => {
-                update_peer_with_tcp_port(&peers, from, from_alias, from, src, Some(*tcp_port)).await;
-                info!("TCP connection request from {} ({}) on port {}", from, from_alias, tcp_port);
-                
-                // Accept the TCP connection request by sending a response
+        }
+
+        if let NetworkMessage::GroupBlock { from, msg_id, payload_json, .. } = &msg {
+            let ack = NetworkMessage::Ack { from: my_id.clone(), msg_id: group_ack_id(msg_id, &my_id) };
+            if let Ok(n) = send_to(&socket, &ack, src).await {
+                tcp_manager.metrics.datagram_sent(n);
+            }
+            if !recent.lock().await.first_sighting(from, msg_id, payload_json, Instant::now()) {
+                debug!("Dropping duplicate group block from {} ({})", from, src);
+                continue;
+            }
+        }
+
+        match &msg {
+            NetworkMessage::Peer { id, alias, pubkey, .. } => {
+                if let Some(renamed) = update_peer(&peers, id, alias, pubkey, src).await {
+                    let _ = tcp_manager.events.send(renamed);
+                }
+                if let Some(ciphers) = msg.announced_capabilities() {
+                    if let Some(entry) = peers.lock().await.get_mut(id) {
+                        entry.capabilities = Some(ciphers.to_vec());
+                    }
+                }
+                flush_outbox(&outbox, &my_id, id, src, &tcp_manager).await;
+            }
+            NetworkMessage::Ping { id, alias, time_ms, .. } => {
+                if let Some(renamed) = update_peer(&peers, id, alias, id, src).await {
+                    let _ = tcp_manager.events.send(renamed);
+                }
+                flush_outbox(&outbox, &my_id, id, src, &tcp_manager).await;
+                let pong = me.pong(*time_ms).await;
+                if let Ok(n) = send_to(&socket, &pong, src).await {
+                    tcp_manager.metrics.datagram_sent(n);
+                }
+            }
+            NetworkMessage::Pong { id, alias, time_ms, echo_ms, .. } => {
+                if let Some(renamed) = update_peer(&peers, id, alias, id, src).await {
+                    let _ = tcp_manager.events.send(renamed);
+                }
+                // Older peers send neither timestamp, and an echo later
+                // than now can't be from one of our pings.
+                let received = now_ms();
+                if *time_ms != 0 && *echo_ms != 0 && *echo_ms <= received {
+                    if let Some(entry) = peers.lock().await.get_mut(id) {
+                        entry.clock_offset_ms = Some(clock_offset(*echo_ms, *time_ms, received));
+                    }
+                }
+                if let Some(waiter) = tcp_manager.pending_pongs.lock().await.remove(&src) {
+                    let _ = waiter.send(id.clone());
+                }
+            }
+            NetworkMessage::DirectBlock { from, .. } => {
+                update_peer(&peers, from, from, from, src).await;
+            }
+            NetworkMessage::TcpConnectionRequest { from, from_alias, tcp_port } => {
+                // Unsigned, so its alias is only logged: renames come from
+                // authenticated announces.
+                update_peer_with_tcp_port(&peers, from, from, from, src, Some(*tcp_port)).await;
+                info!("TCP connection request from {} ({}) on port {}", from, from_alias, tcp_port);
+                
+                // Accept the TCP connection request by sending a response
                 let response = NetworkMessage::TcpConnectionResponse {
                     from: my_id.clone(),
                     to: from.clone(),
                     accepted: true,
-                    tcp_port: 60000 + TCP_PORT_OFFSET, // Our TCP port (60000 + 1000 = 61000)
+                    tcp_port: tcp_manager.tcp_port(),
                 };
                 
-                let bind_addr = "0.0.0.0:0";
-                if let Ok(socket) = UdpSocket::bind(bind_addr).await {
-                    let _ = socket.send_to(&serde_json::to_vec(&response).unwrap(), src).await;
+                if let Ok(n) = send_to(&socket, &response, src).await {
+                    tcp_manager.metrics.datagram_sent(n);
                     info!("✅ TCP connection response sent to {}", from);
                 }
             }
@@ -766,19 +2315,18 @@ async fn recv_loop(
                 
                 // If accepted, try to establish the TCP connection
                 if *accepted {
-                    let peer_addr = format!("{}:{}", src.ip(), tcp_port);
-                    match TokioTcpStream::connect(&peer_addr).await {
+                    let peer_addr = SocketAddr::new(src.ip(), *tcp_port);
+                    match TokioTcpStream::connect(peer_addr).await {
                         Ok(mut stream) => {
                             // Send handshake message
                             let handshake = NetworkMessage::TcpHandshake {
                                 from: my_id.clone(),
-                                from_alias: { my_alias.lock().await.clone() },
-                                pubkey: my_pubkey.clone(),
+                                from_alias: { me.alias.lock().await.clone() },
+                                pubkey: me.pubkey.clone(),
                             };
                             
-                            if let Ok(handshake_json) = serde_json::to_string(&handshake) {
-                                let handshake_msg = format!("{}\n", handshake_json);
-                                if let Err(e) = stream.write_all(handshake_msg.as_bytes()).await {
+                            if let Ok(handshake_msg) = encode_frame(&handshake) {
+                                if let Err(e) = stream.write_all(&handshake_msg).await {
                                     warn!("Failed to send handshake: {}", e);
                                 } else if let Err(e) = stream.flush().await {
                                     warn!("Failed to flush handshake: {}", e);
@@ -787,20 +2335,13 @@ async fn recv_loop(
                                 warn!("Failed to serialize handshake");
                             }
                             
-                            let conn = TcpConnection {
-                                stream: Arc::new(Mutex::new(stream)),
-                                peer_id: from.clone(),
-                                last_activity: Instant::now(),
-                                is_connected: true,
-                                message_count: 0,
-                                last_test_time: None,
-                                handshake_completed: true,
-                            };
-                            
-                            let mut connections = tcp_manager.connections.write().await;
-                            connections.insert(from.clone(), conn);
-                            
-                            info!("✅ TCP connection established to {} on port {} with handshake", from, tcp_port);
+                            // Confirming it takes a round trip; don't hold up the receive loop.
+                            let (tcp_manager, from, my_id, tcp_port) = (tcp_manager.clone(), from.clone(), my_id.clone(), *tcp_port);
+                            spawn_until_shutdown(tcp_manager.shutdown.clone(), async move {
+                                if tcp_manager.attach(&from, stream, &my_id).await {
+                                    info!("✅ TCP connection established to {} on port {} with handshake", from, tcp_port);
+                                }
+                            });
                         }
                         Err(e) => {
                             warn!("Failed to establish TCP connection to {} on port {}: {}", from, tcp_port, e);
@@ -815,32 +2356,283 @@ async fn recv_loop(
                 update_peer(&peers, from, from, from, src).await;
                 info!("TCP connection test received from {}", from);
             }
-            NetworkMessage::TcpConnectionTestResponse { from, to, timestamp: _, response_time_ms } => {
+            NetworkMessage::TcpConnectionTestResponse { from, to, timestamp, response_time_ms } => {
                 update_peer(&peers, from, from, from, src).await;
+                tcp_manager.record_rtt(from, now_ms().saturating_sub(*timestamp)).await;
                 info!("TCP connection test response from {} to {}: {}ms", from, to, response_time_ms);
             }
-            NetworkMessage::TcpHandshake { from, from_alias, pubkey } => {
-                update_peer(&peers, from, from_alias, pubkey, src).await;
+            NetworkMessage::TcpHandshake { from, from_alias, .. } => {
+                // Unsigned like the request: neither its alias nor its pubkey is trusted.
+                update_peer(&peers, from, from, from, src).await;
                 info!("TCP handshake received from {} ({})", from, from_alias);
             }
-            NetworkMessage::Block { .. } => {
-                // legacy ignore
+            NetworkMessage::ReadReceipt { from, .. } => {
+                update_peer(&peers, from, from, from, src).await;
+            }
+            NetworkMessage::ChainRequest { .. }
+            | NetworkMessage::ChainResponse { .. }
+            | NetworkMessage::GroupBlock { .. } => {
+                // Sent from the peer's outbound socket: replying to `src` would
+                // miss its receive loop, so the announced address is kept.
+            }
+            NetworkMessage::Block { .. }
+            | NetworkMessage::DirectBlockChunk { .. }
+            | NetworkMessage::Ack { .. } => {
+                // passed on below / reassembled above / acks are awaited on the sending socket
             }
         }
 
         let _ = tx.send(msg.clone()).await;
-        maybe_gc_stale(&peers).await;
     }
 }
 
+/// Run `fut` until it completes or `shutdown` turns `true`; in the latter case
+/// the future is dropped, closing whatever sockets it owns.
+fn spawn_until_shutdown<F>(mut shutdown: watch::Receiver<bool>, fut: F) -> JoinHandle<()>
+where
+    F: std::future::Future<Output = ()> + Send + 'static,
+{
+    tokio::spawn(async move {
+        tokio::select! {
+            _ = fut => {}
+            _ = shutdown.wait_for(|stop| *stop) => {}
+        }
+    })
+}
+
+/// Wildcard address (port 0) of the same family as `addr`, for ephemeral sockets.
+fn unspecified_for(addr: SocketAddr) -> SocketAddr {
+    match addr {
+        SocketAddr::V4(_) => SocketAddr::from((Ipv4Addr::UNSPECIFIED, 0)),
+        SocketAddr::V6(_) => SocketAddr::from((Ipv6Addr::UNSPECIFIED, 0)),
+    }
+}
+
+/// `DISCOVERY_GROUP_V6` on `port`; scope 0 = the default multicast interface.
+fn discovery_target_v6(port: u16) -> SocketAddr {
+    SocketAddr::V6(SocketAddrV6::new(DISCOVERY_GROUP_V6, port, 0, 0))
+}
+
+/// IPv4 discovery socket on `0.0.0.0:port`, falling back to loopback
+/// (`true` alongside the socket).
+async fn bind_discovery_v4(port: u16) -> Option<(UdpSocket, bool)> {
+    let bind_addr = format!("0.0.0.0:{}", port);
+    match UdpSocket::bind(&bind_addr).await {
+        Ok(s) => {
+            let _ = s.set_broadcast(true);
+            info!("✅ Listening on {}", bind_addr);
+            Some((s, false))
+        }
+        Err(e) => {
+            warn!("Primary binding failed: {}, trying fallback", e);
+            // Fallback for macOS/Windows compatibility issues
+            let fallback_addr = format!("127.0.0.1:{}", port);
+            match UdpSocket::bind(&fallback_addr).await {
+                Ok(s) => {
+                    let _ = s.set_broadcast(true);
+                    info!("✅ Listening on fallback {}", fallback_addr);
+                    Some((s, true))
+                }
+                Err(e2) => {
+                    error!("❌ Failed to bind UDP socket on both addresses: {e:?}, {e2:?}");
+                    None
+                }
+            }
+        }
+    }
+}
+
+/// IPv6 discovery socket on `[::]:port` (v6-only, so it can share the port
+/// with the v4 socket) joined to `DISCOVERY_GROUP_V6`, falling back to `[::1]`
+/// (`true` alongside the socket).
+async fn bind_discovery_v6(port: u16) -> Option<(UdpSocket, bool)> {
+    let bind_addr = SocketAddr::from((Ipv6Addr::UNSPECIFIED, port));
+    match bind_multicast_v6(bind_addr) {
+        Ok(s) => {
+            info!("✅ Listening on {} (multicast {})", bind_addr, DISCOVERY_GROUP_V6);
+            Some((s, false))
+        }
+        Err(e) => {
+            warn!("IPv6 binding failed: {}, trying fallback", e);
+            let fallback_addr = SocketAddr::from((Ipv6Addr::LOCALHOST, port));
+            match UdpSocket::bind(fallback_addr).await {
+                Ok(s) => {
+                    info!("✅ Listening on fallback {}", fallback_addr);
+                    Some((s, true))
+                }
+                Err(e2) => {
+                    error!("❌ Failed to bind IPv6 UDP socket on both addresses: {e:?}, {e2:?}");
+                    None
+                }
+            }
+        }
+    }
+}
+
+fn bind_multicast_v6(bind_addr: SocketAddr) -> std::io::Result<UdpSocket> {
+    use socket2::{Domain, Protocol, Socket, Type};
+
+    let socket = Socket::new(Domain::IPV6, Type::DGRAM, Some(Protocol::UDP))?;
+    socket.set_only_v6(true)?;
+    socket.set_nonblocking(true)?;
+    socket.bind(&bind_addr.into())?;
+    if let Err(e) = socket.join_multicast_v6(&DISCOVERY_GROUP_V6, 0) {
+        // Still receives all-nodes traffic; just log it.
+        warn!("Joining {} failed: {}", DISCOVERY_GROUP_V6, e);
+    }
+    socket.set_multicast_loop_v6(true)?;
+    UdpSocket::from_std(socket.into())
+}
+
+/// Send one `DirectBlock` over UDP and wait for the peer's `Ack` on the sending
+/// socket, retransmitting (same `msg_id`, so the receiver can drop duplicates)
+/// with doubling timeouts up to `MAX_SEND_ATTEMPTS`.
+async fn send_direct_udp(
+    outbound: &Outbound,
+    from: &str,
+    peer_id: &str,
+    addr: SocketAddr,
+    payload_json: &str,
+) -> anyhow::Result<Delivery> {
+    let msg_id = uuid::Uuid::new_v4().to_string();
+    let msg = NetworkMessage::DirectBlock {
+        from: from.to_string(),
+        to: peer_id.to_string(),
+        msg_id: msg_id.clone(),
+        payload_json: payload_json.to_string(),
+    };
+    let bytes = serde_json::to_vec(&msg)?;
+    let mut ack = outbound.expect_ack(&msg_id);
+
+    let mut wait = ACK_TIMEOUT;
+    for attempt in 1..=MAX_SEND_ATTEMPTS {
+        if bytes.len() <= MAX_DGRAM {
+            outbound.send(&bytes, addr).await?;
+        } else {
+            send_chunked(outbound, addr, from, peer_id, &msg_id, payload_json).await?;
+        }
+        if ack.wait(wait).await {
+            info!("➡️  direct {} -> {} (acked, attempt {})", from, peer_id, attempt);
+            return Ok(Delivery::Udp { attempts: attempt });
+        }
+        debug!("No ACK for {} from {} within {:?}", msg_id, peer_id, wait);
+        wait *= 2;
+    }
+    Err(anyhow::anyhow!(
+        "No ACK from {} after {} attempts",
+        peer_id,
+        MAX_SEND_ATTEMPTS
+    ))
+}
+
+/// Deliver everything queued for `peer_id` (just seen at `addr`) in the
+/// background, in order. Stops at the first unacknowledged message and puts
+/// it and the rest back at the front of the queue.
+async fn flush_outbox(
+    outbox: &Outbox,
+    my_id: &str,
+    peer_id: &str,
+    addr: SocketAddr,
+    tcp_manager: &Arc<TcpConnectionManager>,
+) {
+    let Some(mut queued) = outbox.lock().await.remove(peer_id) else {
+        return;
+    };
+    let (outbox, my_id, peer_id, tcp_manager) = (outbox.clone(), my_id.to_string(), peer_id.to_string(), tcp_manager.clone());
+    tokio::spawn(async move {
+        let mut delivered = 0;
+        while let Some(payload) = queued.front() {
+            if let Err(e) = send_direct_udp(&tcp_manager.outbound, &my_id, &peer_id, addr, payload).await {
+                warn!("Outbox flush to {} stopped: {}", peer_id, e);
+                break;
+            }
+            queued.pop_front();
+            delivered += 1;
+        }
+        let remaining = queued.len();
+        if remaining > 0 {
+            // Anything queued meanwhile goes after the older messages.
+            let mut guard = outbox.lock().await;
+            let entry = guard.entry(peer_id.clone()).or_default();
+            queued.extend(entry.drain(..));
+            queued.truncate(MAX_QUEUED_PER_PEER);
+            *entry = queued;
+        }
+        info!("📤 Flushed {} queued message(s) to {} ({} remaining)", delivered, peer_id, remaining);
+        let _ = tcp_manager.events.send(NetworkEvent::QueueFlushed { peer_id, delivered, remaining });
+    });
+}
+
+/// TCP frame: 4-byte big-endian body length followed by the JSON body.
+fn encode_frame(msg: &NetworkMessage) -> anyhow::Result<Vec<u8>> {
+    let body = serde_json::to_vec(msg)?;
+    if body.len() > MAX_TCP_FRAME {
+        anyhow::bail!("TCP frame too large ({} bytes)", body.len());
+    }
+    let mut frame = Vec::with_capacity(4 + body.len());
+    frame.extend_from_slice(&(body.len() as u32).to_be_bytes());
+    frame.extend_from_slice(&body);
+    Ok(frame)
+}
+
+/// Pop the next complete frame body off `buf` (`None` until it has fully arrived).
+fn take_frame(buf: &mut Vec<u8>) -> anyhow::Result<Option<Vec<u8>>> {
+    let Some(prefix) = buf.get(..4) else {
+        return Ok(None);
+    };
+    let len = u32::from_be_bytes([prefix[0], prefix[1], prefix[2], prefix[3]]) as usize;
+    if len > MAX_TCP_FRAME {
+        anyhow::bail!("TCP frame of {len} bytes exceeds {MAX_TCP_FRAME}");
+    }
+    if buf.len() < 4 + len {
+        return Ok(None);
+    }
+    let body = buf[4..4 + len].to_vec();
+    buf.drain(..4 + len);
+    Ok(Some(body))
+}
+
+/// Send an oversized direct payload as `DirectBlockChunk` datagrams.
+async fn send_chunked(
+    outbound: &Outbound,
+    addr: SocketAddr,
+    from: &str,
+    to: &str,
+    msg_id: &str,
+    payload_json: &str,
+) -> anyhow::Result<()> {
+    let chunks = fragment::split_payload(payload_json);
+    let total = u32::try_from(chunks.len())
+        .ok()
+        .filter(|n| *n <= fragment::MAX_CHUNKS)
+        .ok_or_else(|| anyhow::anyhow!("payload too large ({} bytes)", payload_json.len()))?;
+    for (seq, data) in chunks.into_iter().enumerate() {
+        let chunk = NetworkMessage::DirectBlockChunk {
+            from: from.to_string(),
+            to: to.to_string(),
+            msg_id: msg_id.to_string(),
+            seq: seq as u32,
+            total,
+            data: data.to_string(),
+        };
+        let bytes = serde_json::to_vec(&chunk)?;
+        if bytes.len() > MAX_DGRAM {
+            anyhow::bail!("chunk {seq} still exceeds datagram size ({} bytes)", bytes.len());
+        }
+        outbound.send(&bytes, addr).await?;
+    }
+    debug!("➡️  sent {} as {total} chunks", msg_id);
+    Ok(())
+}
+
 async fn update_peer(
     peers: &Arc<Mutex<HashMap<String, PeerEntry>>>,
     id: &str,
     alias: &str,
     pubkey: &str,
     addr: SocketAddr,
-) {
-    update_peer_with_tcp_port(peers, id, alias, pubkey, addr, None).await;
+) -> Option<NetworkEvent> {
+    update_peer_with_tcp_port(peers, id, alias, pubkey, addr, None).await
 }
 
 
@@ -851,7 +2643,7 @@ async fn update_peer_with_tcp_port(
     pubkey: &str,
     addr: SocketAddr,
     tcp_port: Option<u16>,
-) {
+) -> Option<NetworkEvent> {
     let mut map = peers.lock().await;
     let now = Instant::now();
     let entry = map.entry(id.to_string()).or_insert_with(|| PeerEntry {
@@ -859,61 +2651,1312 @@ async fn update_peer_with_tcp_port(
             id: id.to_string(),
             alias: alias.to_string(),
             pubkey: pubkey.to_string(),
-            last_seen_ms: 0,
+            last_seen_ms: now_ms(),
             connection_type: "UDP".to_string(),
             tcp_port: None,
+            away: false,
         },
         last_seen: now,
         last_addr: addr,
         tcp_port: None,
+        capabilities: None,
+        clock_offset_ms: None,
     });
-    entry.info.alias = alias.to_string();
-    entry.info.pubkey = pubkey.to_string();
-    entry.last_seen = now;
     entry.last_addr = addr;
-    entry.info.last_seen_ms = 0;
+    refresh_peer(entry, id, alias, pubkey, tcp_port)
+}
+
+/// Mark a known peer seen now, taking its alias, pubkey and TCP port; its
+/// address is left alone. Returns `AliasChanged` if that renamed it.
+fn refresh_peer(entry: &mut PeerEntry, id: &str, alias: &str, pubkey: &str, tcp_port: Option<u16>) -> Option<NetworkEvent> {
+    // Messages without an alias pass the id in its place; those keep the
+    // known alias. Learning the first real alias isn't a rename either.
+    let mut renamed = None;
+    if alias != id && entry.info.alias != alias {
+        let old_alias = std::mem::replace(&mut entry.info.alias, alias.to_string());
+        if old_alias != id {
+            renamed = Some(NetworkEvent::AliasChanged {
+                peer_id: id.to_string(),
+                old_alias,
+                new_alias: alias.to_string(),
+            });
+        }
+    }
+    entry.info.pubkey = pubkey.to_string();
+    entry.last_seen = Instant::now();
+    entry.info.last_seen_ms = now_ms();
+    entry.info.away = false;
     if let Some(port) = tcp_port {
         entry.tcp_port = Some(port);
         entry.info.tcp_port = Some(port);
     }
+    renamed
 }
 
-async fn maybe_gc_stale(peers: &Arc<Mutex<HashMap<String, PeerEntry>>>) {
+/// Drop peers quiet for longer than `timeout` and mark the rest away once
+/// they've been quiet for over half of it.
+async fn gc_stale_peers(peers: &Arc<Mutex<HashMap<String, PeerEntry>>>, timeout: Duration) {
     let mut map = peers.lock().await;
-    let cutoff = Instant::now() - Duration::from_secs(PEER_STALE_SECS);
-    map.retain(|_, p| p.last_seen >= cutoff);
+    map.retain(|_, p| {
+        let quiet = p.last_seen.elapsed();
+        p.info.away = quiet > timeout / 2;
+        quiet <= timeout
+    });
+}
+
+/// Wall-clock milliseconds since the UNIX epoch.
+fn now_ms() -> u64 {
+    std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map(|d| d.as_millis() as u64)
+        .unwrap_or_default()
 }
 
-async fn send_to(socket: &UdpSocket, msg: &NetworkMessage, addr: SocketAddr) -> std::io::Result<()> {
+/// Returns the number of bytes sent.
+async fn send_to(socket: &UdpSocket, msg: &NetworkMessage, addr: SocketAddr) -> std::io::Result<usize> {
     let bytes = serde_json::to_vec(msg).unwrap();
-    socket.send_to(&bytes, addr).await?;
-    Ok(())
+    socket.send_to(&bytes, addr).await
 }
 
+/// Announce + ping on every discovery socket to its broadcast / multicast
+/// target, paced by `pacing` (woken early when it changes).
 async fn periodic_broadcast(
-    socket: Arc<UdpSocket>,
-    id: String,
-    alias: Arc<Mutex<String>>,
-    pubkey: String,
-    port: u16,
+    targets: Vec<(Arc<UdpSocket>, SocketAddr)>,
+    me: Announcer,
+    peers: Arc<Mutex<HashMap<String, PeerEntry>>>,
+    pacing: Arc<Pacing>,
+    status: Arc<NodeStatus>,
 ) {
-    let broadcast_addr = SocketAddr::new(IpAddr::V4(Ipv4Addr::BROADCAST), port);
     loop {
-        let alias_now = { alias.lock().await.clone() };
+        let announce = me.peer().await;
+        let ping = me.ping().await;
+        for (socket, target) in &targets {
+            let _ = send_to(socket, &announce, *target).await;
+            let _ = send_to(socket, &ping, *target).await;
+        }
+        status.last_broadcast_ms.store(now_ms(), Ordering::Relaxed);
+
+        let known = peers.lock().await.keys().cloned().collect();
+        let wait = pacing.pacer.lock().unwrap().next_interval(known);
+        tokio::select! {
+            _ = tokio::time::sleep(wait) => {}
+            _ = pacing.wake.notified() => {}
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    async fn started_node(port: u16, id: &str) -> (NetworkNode, mpsc::Receiver<NetworkMessage>) {
+        let node = NetworkNode::new(port, id.into(), id.into(), id.into());
+        let (tx, rx) = mpsc::channel(64);
+        node.start(tx).await;
+        // Let the TCP listener task bind.
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        (node, rx)
+    }
 
-        let announce = NetworkMessage::Peer {
+    /// Fresh identity whose id is its base64 pubkey, as the app uses.
+    fn keyed_announcer(alias: &str) -> (String, Announcer) {
+        let key = SigningKey::from_bytes(&rand::random::<[u8; 32]>());
+        let id = wichain_core::encode_pubkey_b64(&key.verifying_key().to_bytes());
+        let me = Announcer {
             id: id.clone(),
-            alias: alias_now.clone(),
-            pubkey: pubkey.clone(),
+            alias: Arc::new(Mutex::new(alias.into())),
+            pubkey: id.clone(),
+            key: Some(Arc::new(key)),
+            capabilities: Vec::new(),
+            last_nonce: Arc::default(),
         };
-        let _ = send_to(&socket, &announce, broadcast_addr).await;
+        (id, me)
+    }
 
-        let ping = NetworkMessage::Ping {
-            id: id.clone(),
-            alias: alias_now,
+    #[tokio::test]
+    async fn updating_a_peer_stamps_wall_clock_last_seen() {
+        let peers = Arc::new(Mutex::new(HashMap::new()));
+        let addr: SocketAddr = "127.0.0.1:60000".parse().unwrap();
+        update_peer(&peers, "p", "alias", "p", addr).await;
+        let first = peers.lock().await["p"].info.last_seen_ms;
+        assert!(first > 0);
+        assert!(now_ms().abs_diff(first) < 5_000);
+
+        tokio::time::sleep(Duration::from_millis(5)).await;
+        update_peer_with_tcp_port(&peers, "p", "alias", "p", addr, Some(60001)).await;
+        assert!(peers.lock().await["p"].info.last_seen_ms > first);
+    }
+
+    #[tokio::test]
+    async fn quiet_peers_go_away_then_expire_at_the_timeout() {
+        let peers = Arc::new(Mutex::new(HashMap::new()));
+        let addr: SocketAddr = "127.0.0.1:60000".parse().unwrap();
+        let timeout = Duration::from_secs(10);
+        let quiet_for = |peers: &Arc<Mutex<HashMap<String, PeerEntry>>>, quiet: Duration| {
+            let peers = peers.clone();
+            async move { peers.lock().await.get_mut("p").unwrap().last_seen = Instant::now() - quiet }
+        };
+        update_peer(&peers, "p", "alias", "p", addr).await;
+
+        gc_stale_peers(&peers, timeout).await;
+        assert!(!peers.lock().await["p"].info.away, "fresh");
+
+        quiet_for(&peers, timeout - Duration::from_millis(200)).await;
+        gc_stale_peers(&peers, timeout).await;
+        assert!(peers.lock().await["p"].info.away, "just under the timeout: kept, but away");
+
+        update_peer(&peers, "p", "alias", "p", addr).await;
+        assert!(!peers.lock().await["p"].info.away, "heard from again");
+
+        quiet_for(&peers, timeout + Duration::from_millis(200)).await;
+        gc_stale_peers(&peers, timeout).await;
+        assert!(peers.lock().await.is_empty(), "just over the timeout: gone");
+
+        // The sweep runs on its own timer, with no traffic needed.
+        let node = NetworkNode::new(47_851, "node-a".into(), "node-a".into(), "node-a".into());
+        node.set_peer_timeout(Duration::from_millis(300)).await;
+        assert_eq!(node.peer_timeout().await, Duration::from_millis(300));
+        let (tx, _rx) = mpsc::channel(64);
+        node.start(tx).await;
+        update_peer(&node.peers, "p", "alias", "p", addr).await;
+        tokio::time::sleep(PEER_GC_INTERVAL * 2).await;
+        assert!(node.list_peers().await.is_empty());
+        node.stop().await;
+    }
+
+    #[tokio::test]
+    async fn presence_reports_a_join_then_a_leave_after_the_timeout() {
+        let node = NetworkNode::new(47_981, "node-a".into(), "node-a".into(), "node-a".into());
+        node.set_peer_timeout(Duration::from_millis(300)).await;
+        let mut events = node.subscribe_events();
+        let (tx, _rx) = mpsc::channel(64);
+        node.start(tx).await;
+        let (id, alice) = keyed_announcer("alice");
+        let sock = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        let announce = serde_json::to_vec(&alice.peer().await).unwrap();
+
+        // Alice keeps announcing until the GC sweep has seen her.
+        let joined = async {
+            loop {
+                sock.send_to(&announce, "127.0.0.1:47981").await.unwrap();
+                if let Ok(event) = timeout(Duration::from_millis(100), events.recv()).await {
+                    return event.unwrap();
+                }
+            }
+        };
+        let NetworkEvent::PeerJoined { peer } = timeout(Duration::from_secs(3), joined).await.unwrap() else {
+            panic!("expected a join");
+        };
+        assert_eq!((peer.id.as_str(), peer.alias.as_str()), (id.as_str(), "alice"));
+
+        // Then goes quiet.
+        let quiet = Instant::now();
+        let Ok(Ok(NetworkEvent::PeerLeft { peer })) = timeout(Duration::from_secs(5), events.recv()).await else {
+            panic!("expected a leave");
+        };
+        assert_eq!(peer.id, id);
+        assert!(quiet.elapsed() >= Duration::from_millis(500), "timeout plus debounce");
+        node.stop().await;
+    }
+
+    #[tokio::test]
+    async fn signed_announce_accepted_and_tampered_alias_rejected() {
+        let (id, me) = keyed_announcer("alice");
+        let announce = me.peer().await;
+        assert!(announce.announce_is_authentic());
+        assert!(me.ping().await.announce_is_authentic());
+        assert!(me.pong(0).await.announce_is_authentic());
+
+        let NetworkMessage::Peer { nonce, sig, .. } = announce.clone() else { unreachable!() };
+        let tampered = NetworkMessage::Peer { id: id.clone(), alias: "mallory".into(), pubkey: id.clone(), nonce, sig, capabilities: None };
+        assert!(!tampered.announce_is_authentic());
+
+        // A valid signature doesn't help if `id` claims someone else.
+        let NetworkMessage::Peer { alias, pubkey, nonce, sig, .. } = announce.clone() else { unreachable!() };
+        let (other_id, _) = keyed_announcer("bob");
+        assert!(!NetworkMessage::Peer { id: other_id, alias, pubkey, nonce, sig, capabilities: None }.announce_is_authentic());
+
+        let (_, unsigned) = keyed_announcer("eve");
+        let unsigned = Announcer { key: None, ..unsigned };
+        assert!(!unsigned.peer().await.announce_is_authentic());
+
+        // End to end: only the genuine announce makes it into the roster.
+        let (node, mut rx) = started_node(47_291, "node-a").await;
+        let sock = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        sock.send_to(&serde_json::to_vec(&tampered).unwrap(), "127.0.0.1:47291").await.unwrap();
+        sock.send_to(&serde_json::to_vec(&announce).unwrap(), "127.0.0.1:47291").await.unwrap();
+        let NetworkMessage::Peer { alias, .. } = timeout(Duration::from_secs(2), rx.recv()).await.unwrap().unwrap() else {
+            panic!("expected the genuine announce");
+        };
+        assert_eq!(alias, "alice");
+        assert_eq!(node.peers.lock().await.get(&id).unwrap().info.alias, "alice");
+    }
+
+    #[test]
+    fn announce_marks_drop_stale_and_replayed_announces() {
+        let rt = tokio::runtime::Runtime::new().unwrap();
+        let (_, alice) = keyed_announcer("alice");
+        let (_, bob) = keyed_announcer("bob");
+        let (first, second, bobs) = rt.block_on(async { (alice.peer().await, alice.ping().await, bob.peer().await) });
+        let (now_ms, now) = (super::now_ms(), Instant::now());
+        let mut marks = AnnounceMarks::default();
+
+        assert!(marks.admit(&first, now_ms, now));
+        assert!(marks.admit(&second, now_ms, now));
+        assert!(marks.admit(&second, now_ms, now), "the latest may come again");
+        assert!(!marks.admit(&first, now_ms, now), "an older one is a replay");
+        assert!(marks.admit(&NetworkMessage::TcpKeepalive { from: "x".into() }, now_ms, now));
+
+        // Outside the skew window even a first announce is dropped, and marks
+        // are forgotten only once their announces couldn't pass it anyway.
+        let skew = ANNOUNCE_MAX_SKEW.as_millis() as u64;
+        assert!(!marks.admit(&bobs, now_ms + skew + 1_000, now));
+        assert!(!marks.admit(&bobs, now_ms - skew - 1_000, now));
+        assert!(!marks.admit(&first, now_ms, now + ANNOUNCE_MAX_SKEW));
+        let later = now + 2 * ANNOUNCE_MAX_SKEW;
+        assert!(!marks.admit(&first, now_ms + 2 * skew, later));
+        assert!(marks.0.is_empty());
+    }
+
+    #[tokio::test]
+    async fn replayed_announce_does_not_roll_back_a_rename() {
+        let (node, mut rx, _, port) = ephemeral_node("node-a").await;
+        let mut events = node.subscribe_events();
+        let (id, alice) = keyed_announcer("alice");
+        let target = SocketAddr::from((Ipv4Addr::LOCALHOST, port));
+        let genuine = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        let old = alice.peer().await;
+        *alice.alias.lock().await = "alice2".into();
+        let renamed = alice.peer().await;
+        for msg in [&old, &renamed] {
+            send_to(&genuine, msg, target).await.unwrap();
+            timeout(Duration::from_secs(2), rx.recv()).await.unwrap().unwrap();
+        }
+
+        // Someone who captured the first announce sends it from elsewhere.
+        let attacker = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        send_to(&attacker, &old, target).await.unwrap();
+        assert!(timeout(Duration::from_millis(300), rx.recv()).await.is_err());
+        let peers = node.peers.lock().await;
+        let entry = peers.get(&id).unwrap();
+        assert_eq!(entry.info.alias, "alice2");
+        assert_eq!(entry.last_addr, genuine.local_addr().unwrap());
+        drop(peers);
+        let mut renames = Vec::new();
+        while let Ok(event) = events.try_recv() {
+            if let NetworkEvent::AliasChanged { new_alias, .. } = event {
+                renames.push(new_alias);
+            }
+        }
+        assert_eq!(renames, ["alice2"]);
+        node.stop().await;
+    }
+
+    #[tokio::test]
+    async fn capabilities_are_signed_apart_from_the_announce() {
+        let (id, me) = keyed_announcer("alice");
+        let me = Announcer { capabilities: vec!["aes".into(), "xor".into()], ..me };
+        let announce = me.peer().await;
+        assert!(announce.announce_is_authentic());
+        assert_eq!(announce.announced_capabilities(), Some(&["aes".to_string(), "xor".to_string()][..]));
+
+        // Dropping or editing them leaves a valid announce without capabilities.
+        let with = |capabilities| match announce.clone() {
+            NetworkMessage::Peer { id, alias, pubkey, nonce, sig, .. } => NetworkMessage::Peer { id, alias, pubkey, nonce, sig, capabilities },
+            _ => unreachable!(),
+        };
+        let downgraded = with(Some(Capabilities { ciphers: vec!["xor".into()], sig: String::new() }));
+        assert!(downgraded.announce_is_authentic());
+        assert_eq!(downgraded.announced_capabilities(), None);
+        assert_eq!(with(None).announced_capabilities(), None);
+        // Announces from before capabilities still parse.
+        let legacy = serde_json::to_string(&with(None)).unwrap();
+        assert!(!legacy.contains("capabilities"));
+
+        let (node, _rx) = started_node(47_961, "node-a").await;
+        let sock = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        for msg in [&announce, &downgraded] {
+            sock.send_to(&serde_json::to_vec(msg).unwrap(), "127.0.0.1:47961").await.unwrap();
+            tokio::time::sleep(Duration::from_millis(100)).await;
+            assert_eq!(node.peer_capabilities(&id).await, Some(vec!["aes".to_string(), "xor".to_string()]));
+        }
+        assert_eq!(node.peer_capabilities("nobody").await, None);
+        node.stop().await;
+    }
+
+    #[tokio::test]
+    async fn own_announces_are_not_forwarded() {
+        let key = SigningKey::from_bytes(&rand::random::<[u8; 32]>());
+        let id = wichain_core::encode_pubkey_b64(&key.verifying_key().to_bytes());
+        let node = NetworkNode::new(47_841, id.clone(), "me".into(), id.clone()).with_signing_key(key);
+        let (tx, mut rx) = mpsc::channel(64);
+        node.start(tx).await;
+        let (other_id, other) = keyed_announcer("other");
+
+        let sock = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        for msg in [node.announcer().peer().await, node.announcer().ping().await, other.peer().await] {
+            sock.send_to(&serde_json::to_vec(&msg).unwrap(), "127.0.0.1:47841").await.unwrap();
+        }
+        let Some(NetworkMessage::Peer { id: first, .. }) = timeout(Duration::from_secs(2), rx.recv()).await.unwrap() else {
+            panic!("expected the other node's announce");
+        };
+        assert_eq!(first, other_id);
+        let listed: Vec<String> = node.list_peers().await.into_iter().map(|p| p.id).collect();
+        assert_eq!(listed, [other_id]);
+        node.stop().await;
+    }
+
+    /// A registered outbound connection from `node` to a listener we control.
+    async fn connect_raw(node: &NetworkNode, peer_id: &str) -> TokioTcpStream {
+        let listener = TokioTcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let (client, accepted) = tokio::join!(TokioTcpStream::connect(addr), listener.accept());
+        let mut server_side = accepted.unwrap().0;
+        let (confirmed, ()) = tokio::join!(
+            node.tcp_manager.attach(peer_id, client.unwrap(), &node.id),
+            answer_probe(&mut server_side)
+        );
+        assert!(confirmed);
+        server_side
+    }
+
+    /// Echo the `TcpConnectionTest` a new outbound connection is probed with.
+    async fn answer_probe(server_side: &mut TokioTcpStream) {
+        let mut len = [0u8; 4];
+        server_side.read_exact(&mut len).await.unwrap();
+        let mut frame = vec![0u8; u32::from_be_bytes(len) as usize];
+        server_side.read_exact(&mut frame).await.unwrap();
+        let NetworkMessage::TcpConnectionTest { from, timestamp } = serde_json::from_slice(&frame).unwrap() else {
+            panic!("expected the connection probe");
+        };
+        let echo = NetworkMessage::TcpConnectionTestResponse { from: "peer".into(), to: from, timestamp, response_time_ms: 0 };
+        server_side.write_all(&encode_frame(&echo).unwrap()).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn tcp_test_measures_round_trip() {
+        let (a, _rx_a) = started_node(47_371, "node-a").await;
+        let (b, _rx_b) = started_node(47_381, "node-b").await;
+        let b_udp: SocketAddr = "127.0.0.1:47381".parse().unwrap();
+        update_peer_with_tcp_port(&a.peers, "node-b", "node-b", "node-b", b_udp, Some(b.get_tcp_port())).await;
+        a.request_tcp_connection("node-b").await.unwrap();
+
+        let rtt = a.test_tcp_connection("node-b").await.expect("B echoes the test");
+        assert!(rtt < TCP_TEST_TIMEOUT.as_millis() as u64);
+        let stats = a.get_connection_stats("node-b").await.unwrap();
+        assert_eq!(stats.avg_rtt_ms, Some(rtt));
+        assert!(stats.last_test_time_ms.is_some());
+        assert!(a.tcp_manager.pending_tests.lock().await.is_empty());
+    }
+
+    #[tokio::test]
+    async fn tcp_test_times_out_without_response() {
+        let node = NetworkNode::new(47_391, "node-a".into(), "node-a".into(), "node-a".into());
+        let _silent = connect_raw(&node, "mute").await;
+        assert!(node.test_tcp_connection("mute").await.is_err());
+        assert!(node.get_connection_stats("mute").await.unwrap().last_test_time_ms.is_none());
+        assert!(node.tcp_manager.pending_tests.lock().await.is_empty());
+    }
+
+    #[tokio::test]
+    async fn idle_connection_is_reaped() {
+        let node = NetworkNode::new(47_351, "node-a".into(), "node-a".into(), "node-a".into());
+        let _server_side = connect_raw(&node, "quiet").await;
+        assert!(node.has_tcp_connection("quiet").await);
+
+        node.tcp_manager.cleanup_stale_connections(Duration::from_secs(60)).await;
+        assert!(node.has_tcp_connection("quiet").await, "fresh connection kept");
+
+        tokio::time::sleep(Duration::from_millis(60)).await;
+        node.tcp_manager.cleanup_stale_connections(Duration::from_millis(20)).await;
+        assert!(!node.has_tcp_connection("quiet").await);
+        assert!(node.get_connection_stats("quiet").await.is_none());
+    }
+
+    #[tokio::test]
+    async fn keepalive_detects_closed_peer() {
+        let node = NetworkNode::new(47_361, "node-a".into(), "node-a".into(), "node-a".into());
+        let mut events = node.subscribe_events();
+        let mut server_side = connect_raw(&node, "gone").await;
+
+        // A live peer receives the keepalive frame.
+        node.tcp_manager.send_keepalives("node-a").await;
+        let mut buf = vec![0u8; 256];
+        let n = timeout(Duration::from_secs(2), server_side.read(&mut buf)).await.unwrap().unwrap();
+        let mut frame = buf[..n].to_vec();
+        let body = take_frame(&mut frame).unwrap().unwrap();
+        assert!(matches!(serde_json::from_slice(&body).unwrap(), NetworkMessage::TcpKeepalive { .. }));
+
+        // Once it goes away, writes start failing and the connection is dropped.
+        drop(server_side);
+        for _ in 0..5 {
+            tokio::time::sleep(Duration::from_millis(20)).await;
+            node.tcp_manager.send_keepalives("node-a").await;
+        }
+        assert!(!node.has_tcp_connection("gone").await);
+
+        let mut last = None;
+        while let Ok(ev) = events.try_recv() {
+            last = Some(ev);
+        }
+        assert!(matches!(last, Some(NetworkEvent::ConnectionChanged { to: Transport::Udp, .. })));
+    }
+
+    #[tokio::test]
+    async fn keepalives_only_count_when_the_peer_echoes_them() {
+        let (a, _rx_a) = started_node(47_461, "node-a").await;
+        let (b, _rx_b) = started_node(47_471, "node-b").await;
+        let b_udp: SocketAddr = "127.0.0.1:47471".parse().unwrap();
+        update_peer_with_tcp_port(&a.peers, "node-b", "node-b", "node-b", b_udp, Some(b.get_tcp_port())).await;
+        a.request_tcp_connection("node-b").await.unwrap();
+        // A peer that accepts our writes but never reads or answers them.
+        let _silent = connect_raw(&a, "mute").await;
+
+        tokio::time::sleep(Duration::from_millis(200)).await;
+        a.tcp_manager.send_keepalives("node-a").await;
+        for _ in 0..50 {
+            if a.get_connection_stats("node-b").await.unwrap().last_activity_ms < 100 {
+                break;
+            }
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+        a.tcp_manager.cleanup_stale_connections(Duration::from_millis(150)).await;
+        assert!(a.has_tcp_connection("node-b").await, "B echoed the keepalive");
+        assert!(!a.has_tcp_connection("mute").await, "a successful write alone is not activity");
+        a.stop().await;
+        b.stop().await;
+    }
+
+    #[tokio::test]
+    async fn dropped_tcp_peer_falls_back_to_udp() {
+        let a = NetworkNode::new(0, "node-a".into(), "node-a".into(), "node-a".into());
+        let fake_peer = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        update_peer(&a.peers, "peer", "peer", "peer", fake_peer.local_addr().unwrap()).await;
+
+        // A stream the peer never answers the probe on doesn't count.
+        let listener = TokioTcpListener::bind("127.0.0.1:0").await.unwrap();
+        let (client, _silent) = tokio::join!(TokioTcpStream::connect(listener.local_addr().unwrap()), listener.accept());
+        assert!(!a.tcp_manager.attach("peer", client.unwrap(), &a.id).await);
+        assert!(!a.has_tcp_connection("peer").await);
+        assert!(a.tcp_manager.connections.read().await.is_empty());
+
+        // One that answered stops counting as soon as the peer closes it.
+        let server_side = connect_raw(&a, "peer").await;
+        assert!(a.has_tcp_connection("peer").await);
+        drop(server_side);
+        for _ in 0..50 {
+            if !a.has_tcp_connection("peer").await {
+                break;
+            }
+            tokio::time::sleep(Duration::from_millis(20)).await;
+        }
+        assert!(!a.has_tcp_connection("peer").await);
+
+        let peer_task = tokio::spawn(async move {
+            let mut buf = vec![0u8; MAX_DGRAM];
+            loop {
+                let (n, src) = fake_peer.recv_from(&mut buf).await.unwrap();
+                if let Ok(NetworkMessage::DirectBlock { msg_id, payload_json, .. }) = serde_json::from_slice(&buf[..n]) {
+                    let ack = NetworkMessage::Ack { from: "peer".into(), msg_id };
+                    fake_peer.send_to(&serde_json::to_vec(&ack).unwrap(), src).await.unwrap();
+                    return payload_json;
+                }
+            }
+        });
+        let delivery = a.send_message("peer", "{\"text\":\"still there?\"}".into()).await.unwrap();
+        assert_eq!(delivery, Delivery::Udp { attempts: 1 });
+        assert_eq!(peer_task.await.unwrap(), "{\"text\":\"still there?\"}");
+    }
+
+    #[tokio::test]
+    async fn broadcast_block_reaches_the_discovery_port() {
+        let (listener, mut rx) = started_node(47_991, "listener").await;
+        let node = NetworkNode::new(47_991, "node-a".into(), "node-a".into(), "node-a".into());
+        node.broadcast_block("{\"index\":1}".into()).await.unwrap();
+        let got = timeout(Duration::from_secs(2), async {
+            loop {
+                if let Some(NetworkMessage::Block { block_json }) = rx.recv().await {
+                    return block_json;
+                }
+            }
+        })
+        .await
+        .unwrap();
+        assert_eq!(got, "{\"index\":1}");
+
+        assert!(node.broadcast_block("x".repeat(MAX_DGRAM)).await.is_err());
+        listener.stop().await;
+    }
+
+    #[tokio::test]
+    async fn renaming_produces_one_alias_change() {
+        let (node, mut rx, _, port) = ephemeral_node("node-a").await;
+        let mut events = node.subscribe_events();
+        let (bob_id, bob) = keyed_announcer("bob");
+        let sock = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        let target = SocketAddr::from((Ipv4Addr::LOCALHOST, port));
+
+        // First sighting, the rename (announce and ping, as `ping_now` sends
+        // them), then traffic that doesn't carry an alias at all.
+        let mut sent = vec![bob.peer().await];
+        *bob.alias.lock().await = "robert".into();
+        sent.extend([bob.peer().await, bob.ping().await, NetworkMessage::TcpKeepalive { from: bob_id.clone() }]);
+        for msg in &sent {
+            send_to(&sock, msg, target).await.unwrap();
+            timeout(Duration::from_secs(2), rx.recv()).await.unwrap().unwrap();
+        }
+
+        let mut renames = Vec::new();
+        while let Ok(event) = events.try_recv() {
+            if let NetworkEvent::AliasChanged { peer_id, old_alias, new_alias } = event {
+                renames.push((peer_id, old_alias, new_alias));
+            }
+        }
+        assert_eq!(renames, [(bob_id.clone(), "bob".to_string(), "robert".to_string())]);
+        assert_eq!(node.peers.lock().await.get(&bob_id).unwrap().info.alias, "robert");
+        node.stop().await;
+    }
+
+    #[tokio::test]
+    async fn pongs_estimate_the_peer_clock_offset() {
+        // Pinged at 1000, answered at the peer's 6040, back at 1080: the
+        // answer came around our 1040, so the peer runs 5 s ahead.
+        assert_eq!(clock_offset(1_000, 6_040, 1_080), 5_000);
+        assert_eq!(clock_offset(1_000, 0, 1_080), -1_040);
+
+        let (node, mut rx, _, port) = ephemeral_node("node-a").await;
+        let (bob_id, bob) = keyed_announcer("bob");
+        let sock = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        let target = SocketAddr::from((Ipv4Addr::LOCALHOST, port));
+
+        // A pong without timestamps says nothing about the clock.
+        send_to(&sock, &bob.pong_at(0, 0).await, target).await.unwrap();
+        timeout(Duration::from_secs(2), rx.recv()).await.unwrap().unwrap();
+        assert_eq!(node.peer_clock_offset(&bob_id).await, None);
+
+        // Bob's clock is ten minutes behind; our ping left 200 ms ago.
+        let sent = now_ms() - 200;
+        let pong = bob.pong_at(sent + 100 - 600_000, sent).await;
+        // The timestamps are signed, so nobody else can skew the estimate.
+        let NetworkMessage::Pong { id, alias, nonce, sig, time_ms, .. } = pong.clone() else { unreachable!() };
+        let forged = NetworkMessage::Pong { id, alias, nonce, sig, time_ms, echo_ms: sent - 60_000 };
+        assert!(!forged.announce_is_authentic());
+        send_to(&sock, &pong, target).await.unwrap();
+        timeout(Duration::from_secs(2), rx.recv()).await.unwrap().unwrap();
+        let offset = node.peer_clock_offset(&bob_id).await.unwrap();
+        assert!((offset + 600_000).abs() < 1_000, "offset {offset}");
+        node.stop().await;
+    }
+
+    #[tokio::test]
+    async fn connection_history_accumulates_across_connections() {
+        let path = std::env::temp_dir().join(format!("wichain-history-{}.json", rand::random::<u64>()));
+        let node = NetworkNode::new(47_971, "node-a".into(), "node-a".into(), "node-a".into())
+            .with_history_file(path.clone());
+        let ping = NetworkMessage::TcpKeepalive { from: "peer".into() };
+        assert_eq!(node.connection_history("peer"), None);
+
+        for (sent, received) in [(2, 1), (1, 2)] {
+            let mut server_side = connect_raw(&node, "peer").await;
+            for _ in 0..sent {
+                node.send_frame("peer", &ping).await.unwrap();
+            }
+            for _ in 0..received {
+                server_side.write_all(&encode_frame(&ping).unwrap()).await.unwrap();
+            }
+            tokio::time::sleep(Duration::from_millis(100)).await;
+            node.tcp_manager.record_rtt("peer", 10 * sent).await;
+            // Closing our end ends the reply reader, which drops the connection.
+            drop(server_side);
+            for _ in 0..50 {
+                if !node.has_tcp_connection("peer").await {
+                    break;
+                }
+                tokio::time::sleep(Duration::from_millis(20)).await;
+            }
+            assert!(!node.has_tcp_connection("peer").await);
+        }
+
+        let history = node.connection_history("peer").unwrap();
+        // Each connection's probe and its echo count too.
+        assert_eq!((history.messages_sent, history.messages_received), (5, 5));
+        assert_eq!((history.connects, history.disconnects), (2, 2));
+        assert_eq!(history.avg_rtt_ms, Some(15));
+        assert!(!history.is_connected);
+        assert!(history.uptime_ms >= 200, "both connections counted");
+
+        // A live connection is closed out by `stop`, and the totals survive a restart.
+        let _server_side = connect_raw(&node, "peer").await;
+        assert!(node.connection_history("peer").unwrap().is_connected);
+        node.stop().await;
+        let reloaded = NetworkNode::new(47_971, "node-a".into(), "node-a".into(), "node-a".into())
+            .with_history_file(path.clone())
+            .connection_history("peer")
+            .unwrap();
+        assert_eq!((reloaded.messages_sent, reloaded.messages_received), (6, 6));
+        assert_eq!((reloaded.connects, reloaded.disconnects), (3, 3));
+        assert!(!reloaded.is_connected);
+        let _ = std::fs::remove_file(&path);
+    }
+
+    #[tokio::test]
+    async fn stop_releases_ports_for_rebinding() {
+        let (node, _rx) = started_node(47_311, "node-a").await;
+        assert!(node.bound_addr().await.is_some());
+        node.stop().await;
+        assert!(node.bound_addr().await.is_none());
+
+        let udp = UdpSocket::bind("0.0.0.0:47311").await.expect("UDP port released");
+        let tcp = TokioTcpListener::bind(("0.0.0.0", node.get_tcp_port())).await.expect("TCP port released");
+        drop((udp, tcp));
+
+        // And the node itself can come back up on the same ports.
+        let (tx, _rx2) = mpsc::channel(64);
+        node.start(tx).await;
+        assert_eq!(node.bound_addr().await.map(|a| a.port()), Some(47_311));
+        node.stop().await;
+    }
+
+    #[tokio::test]
+    async fn stop_ends_the_reply_readers_of_outbound_connections() {
+        let node = NetworkNode::new(47_481, "node-a".into(), "node-a".into(), "node-a".into());
+        let mut server_side = connect_raw(&node, "peer").await;
+        let received = node.connection_history("peer").unwrap().messages_received;
+        node.stop().await;
+
+        // Nothing reads our side of the stream any more.
+        let keepalive = NetworkMessage::TcpKeepalive { from: "peer".into() };
+        let _ = server_side.write_all(&encode_frame(&keepalive).unwrap()).await;
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        assert_eq!(node.connection_history("peer").unwrap().messages_received, received);
+    }
+
+    #[tokio::test]
+    async fn health_reflects_a_node_that_bound() {
+        let idle = NetworkNode::new(47_441, "idle".into(), "idle".into(), "idle".into());
+        let health = idle.health().await;
+        assert!(!health.udp_bound && !health.tcp_listening);
+        assert_eq!((health.uptime_secs, health.last_broadcast_ms), (0, None));
+
+        let (node, _rx) = started_node(47_441, "node-a").await;
+        let health = node.health().await;
+        assert!(health.udp_bound);
+        assert_eq!(health.udp_addr.map(|a| a.port()), Some(47_441));
+        assert!(!health.udp_fallback, "wildcard bind succeeded");
+        assert!(health.tcp_listening);
+        assert_eq!(health.peer_count, 0);
+        assert!(health.last_broadcast_ms.is_some(), "announced on start");
+
+        node.stop().await;
+        let health = node.health().await;
+        assert!(!health.udp_bound && !health.tcp_listening);
+    }
+
+    // Holding the port on another loopback address (all of 127/8 is, on Linux)
+    // makes the wildcard bind fail while 127.0.0.1 stays free.
+    #[cfg(target_os = "linux")]
+    #[tokio::test]
+    async fn pre_bound_port_degrades_to_loopback() {
+        let squatter = UdpSocket::bind("127.0.0.2:47451").await.unwrap();
+        let node = NetworkNode::new(47_451, "node-a".into(), "node-a".into(), "node-a".into());
+        let mut events = node.subscribe_events();
+        let (tx, _rx) = mpsc::channel(64);
+        node.start(tx).await;
+
+        let health = node.health().await;
+        assert!(health.udp_bound && health.udp_fallback);
+        let addr = health.udp_addr.unwrap();
+        assert!(addr.ip().is_loopback());
+        match timeout(Duration::from_secs(1), events.recv()).await {
+            Ok(Ok(NetworkEvent::BindDegraded { addr: degraded })) => assert_eq!(degraded, addr),
+            other => panic!("expected BindDegraded, got {other:?}"),
+        }
+        node.stop().await;
+        assert!(!node.health().await.udp_fallback);
+        drop(squatter);
+    }
+
+    #[tokio::test]
+    async fn repeated_datagrams_are_forwarded_once() {
+        let (_node, mut rx) = started_node(47_301, "node-a").await;
+        let sock = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        let with_id = NetworkMessage::DirectBlock {
+            from: "peer".into(),
+            to: "node-a".into(),
+            msg_id: "m-1".into(),
+            payload_json: "one".into(),
+        };
+        let legacy = NetworkMessage::DirectBlock {
+            from: "peer".into(),
+            to: "node-a".into(),
+            msg_id: String::new(),
+            payload_json: "two".into(),
         };
-        let _ = send_to(&socket, &ping, broadcast_addr).await;
+        for msg in [&with_id, &with_id, &legacy, &legacy] {
+            sock.send_to(&serde_json::to_vec(msg).unwrap(), "127.0.0.1:47301").await.unwrap();
+        }
+
+        let mut forwarded = Vec::new();
+        while let Ok(Some(msg)) = timeout(Duration::from_millis(300), rx.recv()).await {
+            if let NetworkMessage::DirectBlock { payload_json, .. } = msg {
+                forwarded.push(payload_json);
+            }
+        }
+        assert_eq!(forwarded, ["one", "two"]);
+    }
+
+    #[tokio::test]
+    async fn blocked_peer_direct_blocks_are_dropped() {
+        let (node, mut rx) = started_node(47_401, "node-a").await;
+        let sock = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        let from = |peer: &str, text: &str| NetworkMessage::DirectBlock {
+            from: peer.into(),
+            to: "node-a".into(),
+            msg_id: format!("{peer}-{text}"),
+            payload_json: text.into(),
+        };
+
+        node.block_peer("spammer").await;
+        assert!(node.is_blocked("spammer").await);
+        for msg in [from("spammer", "spam"), from("friend", "hi")] {
+            sock.send_to(&serde_json::to_vec(&msg).unwrap(), "127.0.0.1:47401").await.unwrap();
+        }
+        let mut forwarded = Vec::new();
+        while let Ok(Some(msg)) = timeout(Duration::from_millis(300), rx.recv()).await {
+            if let NetworkMessage::DirectBlock { payload_json, .. } = msg {
+                forwarded.push(payload_json);
+            }
+        }
+        assert_eq!(forwarded, ["hi"]);
+        assert!(node.list_peers().await.iter().all(|p| p.id != "spammer"));
+
+        assert!(node.unblock_peer("spammer").await);
+        assert!(node.blocked_peers().await.is_empty());
+        sock.send_to(&serde_json::to_vec(&from("spammer", "sorry")).unwrap(), "127.0.0.1:47401").await.unwrap();
+        let Ok(Some(NetworkMessage::DirectBlock { payload_json, .. })) = timeout(Duration::from_millis(300), rx.recv()).await else {
+            panic!("unblocked peer's message not forwarded");
+        };
+        assert_eq!(payload_json, "sorry");
+    }
+
+    #[tokio::test]
+    async fn oversized_direct_block_is_chunked_and_reassembled() {
+        let (a, _rx_a) = started_node(47_231, "node-a").await;
+        let (_b, mut rx_b) = started_node(47_341, "node-b").await;
+        update_peer(&a.peers, "node-b", "node-b", "node-b", "127.0.0.1:47341".parse().unwrap()).await;
+
+        let big = "{\"text\":\"".to_string() + &"λ".repeat(3 * MAX_DGRAM) + "\"}";
+        a.send_direct_block("node-b", big.clone()).await.unwrap();
+        let got = timeout(Duration::from_secs(2), async {
+            loop {
+                if let Some(NetworkMessage::DirectBlock { payload_json, .. }) = rx_b.recv().await {
+                    return payload_json;
+                }
+            }
+        })
+        .await
+        .expect("reassembled message");
+        assert_eq!(got, big);
+    }
+
+    #[tokio::test]
+    async fn broadcast_interval_widens_while_peers_are_stable() {
+        let node = NetworkNode::new(47_711, "node-a".into(), "node-a".into(), "node-a".into());
+        let base = Duration::from_millis(10);
+        node.set_broadcast_interval(base);
+        let (tx, _rx) = mpsc::channel(64);
+        node.start(tx).await;
+
+        let widened = timeout(Duration::from_secs(3), async {
+            while node.broadcast_interval() < base * 4 {
+                tokio::time::sleep(Duration::from_millis(10)).await;
+            }
+        })
+        .await;
+        assert!(widened.is_ok(), "still at {:?}", node.broadcast_interval());
+
+        node.ping_now().await.ok();
+        assert_eq!(node.broadcast_interval(), base);
+        node.set_broadcast_interval(Duration::from_millis(20));
+        assert_eq!(node.broadcast_interval(), Duration::from_millis(20));
+        node.stop().await;
+    }
+
+    #[tokio::test]
+    async fn metrics_count_sent_and_received_traffic() {
+        let (a, _rx_a) = started_node(47_811, "node-a").await;
+        let (b, mut rx_b) = started_node(47_821, "node-b").await;
+        update_peer(&a.peers, "node-b", "node-b", "node-b", "127.0.0.1:47821".parse().unwrap()).await;
+        let (a_before, b_before) = (a.metrics().await, b.metrics().await);
+
+        let payload = "{\"text\":\"hi\"}".to_string();
+        a.send_direct_block("node-b", payload).await.unwrap();
+        timeout(Duration::from_secs(2), rx_b.recv()).await.unwrap();
+        let sock = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        sock.send_to(b"not json", "127.0.0.1:47821").await.unwrap();
+        tokio::time::sleep(Duration::from_millis(100)).await;
+
+        // Other nodes' broadcasts may land too, so only lower bounds hold.
+        let (a_after, b_after) = (a.metrics().await, b.metrics().await);
+        assert!(a_after.datagrams_sent > a_before.datagrams_sent);
+        assert!(a_after.bytes_sent > a_before.bytes_sent);
+        assert!(a_after.datagrams_received > a_before.datagrams_received, "ack counted");
+        assert!(b_after.datagrams_received >= b_before.datagrams_received + 2);
+        assert!(b_after.bytes_received > b_before.bytes_received);
+        assert!(b_after.datagrams_sent > b_before.datagrams_sent, "ack counted");
+        assert!(b_after.parse_failures > b_before.parse_failures);
+        assert_eq!(a_after.tcp_messages_sent, 0);
+        assert!(a_after.peer_count >= 1);
+    }
 
-        tokio::time::sleep(BROADCAST_INTERVAL).await;
+    #[tokio::test]
+    async fn read_receipts_are_signed_by_the_reader() {
+        let key = SigningKey::from_bytes(&rand::random::<[u8; 32]>());
+        let reader = wichain_core::encode_pubkey_b64(&key.verifying_key().to_bytes());
+        let a = NetworkNode::new(47_411, reader.clone(), "reader".into(), reader.clone()).with_signing_key(key);
+        let (_b, mut rx_b) = started_node(47_421, "author").await;
+        update_peer(&a.peers, "author", "author", "author", "127.0.0.1:47421".parse().unwrap()).await;
+
+        // A receipt whose signature doesn't match `from` is dropped.
+        let forged = NetworkMessage::ReadReceipt {
+            from: reader.clone(),
+            to: "author".into(),
+            msg_id: "forged".into(),
+            ts: 1,
+            sig: "00".repeat(64),
+        };
+        assert!(!forged.announce_is_authentic());
+        let sock = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        sock.send_to(&serde_json::to_vec(&forged).unwrap(), "127.0.0.1:47421").await.unwrap();
+
+        a.send_read_receipt("author", "m1").await.unwrap();
+        let got = timeout(Duration::from_secs(2), async {
+            loop {
+                if let Some(NetworkMessage::ReadReceipt { from, to, msg_id, .. }) = rx_b.recv().await {
+                    return (from, to, msg_id);
+                }
+            }
+        })
+        .await
+        .expect("receipt forwarded");
+        assert_eq!(got, (reader, "author".to_string(), "m1".to_string()));
+
+        let unkeyed = NetworkNode::new(47_431, "x".into(), "x".into(), "x".into());
+        assert!(unkeyed.send_read_receipt("author", "m1").await.is_err());
+    }
+
+    #[tokio::test]
+    async fn rapid_sends_share_one_outbound_socket() {
+        const SENDS: usize = 500;
+        let a = NetworkNode::new(47_831, "node-a".into(), "node-a".into(), "node-a".into());
+        let fake_peer = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        update_peer(&a.peers, "peer", "peer", "peer", fake_peer.local_addr().unwrap()).await;
+
+        let peer_task = tokio::spawn(async move {
+            let mut buf = vec![0u8; MAX_DGRAM];
+            let mut acked = HashSet::new();
+            let mut ports = HashSet::new();
+            while acked.len() < SENDS {
+                let (n, src) = fake_peer.recv_from(&mut buf).await.unwrap();
+                let NetworkMessage::DirectBlock { msg_id, .. } = serde_json::from_slice(&buf[..n]).unwrap() else {
+                    continue;
+                };
+                ports.insert(src.port());
+                let ack = NetworkMessage::Ack { from: "peer".into(), msg_id: msg_id.clone() };
+                fake_peer.send_to(&serde_json::to_vec(&ack).unwrap(), src).await.unwrap();
+                acked.insert(msg_id);
+            }
+            ports
+        });
+
+        // All in flight at once: each waits for its own ack on the shared socket.
+        let sends = (0..SENDS).map(|i| a.send_direct_block("peer", format!("{{\"n\":{i}}}")));
+        for result in futures::future::join_all(sends).await {
+            result.unwrap();
+        }
+        assert_eq!(peer_task.await.unwrap().len(), 1, "every send from the same port");
+        // Broadcast goes through the same (broadcast-enabled) socket.
+        a.ping_now().await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn udp_send_retries_until_acked() {
+        let a = NetworkNode::new(47_251, "node-a".into(), "node-a".into(), "node-a".into());
+        let fake_peer = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        update_peer(&a.peers, "peer", "peer", "peer", fake_peer.local_addr().unwrap()).await;
+
+        let peer_task = tokio::spawn(async move {
+            let mut buf = vec![0u8; MAX_DGRAM];
+            // First copy is "lost": received but never acknowledged.
+            let (n, _) = fake_peer.recv_from(&mut buf).await.unwrap();
+            let first: NetworkMessage = serde_json::from_slice(&buf[..n]).unwrap();
+            let (n, src) = fake_peer.recv_from(&mut buf).await.unwrap();
+            let NetworkMessage::DirectBlock { msg_id, .. } = serde_json::from_slice(&buf[..n]).unwrap() else {
+                panic!("expected DirectBlock");
+            };
+            let ack = NetworkMessage::Ack { from: "peer".into(), msg_id: msg_id.clone() };
+            fake_peer.send_to(&serde_json::to_vec(&ack).unwrap(), src).await.unwrap();
+            matches!(first, NetworkMessage::DirectBlock { msg_id: ref m, .. } if *m == msg_id)
+        });
+
+        let delivery = a.send_direct_block("peer", "hi".into()).await.unwrap();
+        assert_eq!(delivery, Delivery::Udp { attempts: 2 });
+        assert!(peer_task.await.unwrap(), "retransmit reuses the msg_id");
+    }
+
+    #[tokio::test]
+    async fn udp_send_fails_without_ack() {
+        let a = NetworkNode::new(47_261, "node-a".into(), "node-a".into(), "node-a".into());
+        let silent = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        update_peer(&a.peers, "peer", "peer", "peer", silent.local_addr().unwrap()).await;
+        assert!(a.send_direct_block("peer", "hi".into()).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn queued_messages_flush_when_peer_announces() {
+        let (a, _rx_a) = started_node(47_271, "node-a").await;
+        let mut events = a.subscribe_events();
+
+        let (late_id, late_me) = keyed_announcer("late");
+
+        let queued = a.send_message(&late_id, "hello".into()).await.unwrap();
+        assert_eq!(queued, Delivery::Queued { pending: 1 });
+        assert_eq!(a.pending_count(&late_id).await, 1);
+
+        // "Discover" the peer: it announces itself from a socket that ACKs.
+        let late = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        let announce = late_me.peer().await;
+        late.send_to(&serde_json::to_vec(&announce).unwrap(), "127.0.0.1:47271").await.unwrap();
+
+        let mut buf = vec![0u8; MAX_DGRAM];
+        let (n, src) = timeout(Duration::from_secs(2), late.recv_from(&mut buf)).await.unwrap().unwrap();
+        let NetworkMessage::DirectBlock { msg_id, payload_json, .. } = serde_json::from_slice(&buf[..n]).unwrap() else {
+            panic!("expected the queued DirectBlock");
+        };
+        assert_eq!(payload_json, "hello");
+        let ack = NetworkMessage::Ack { from: late_id.clone(), msg_id };
+        late.send_to(&serde_json::to_vec(&ack).unwrap(), src).await.unwrap();
+
+        let flushed = timeout(Duration::from_secs(2), events.recv()).await.unwrap().unwrap();
+        assert!(matches!(
+            flushed,
+            NetworkEvent::QueueFlushed { ref peer_id, delivered: 1, remaining: 0 } if *peer_id == late_id
+        ));
+        assert_eq!(a.pending_count(&late_id).await, 0);
+    }
+
+    #[tokio::test]
+    async fn ipv6_node_discovers_and_acks_over_loopback() {
+        let node = NetworkNode::new(47_281, "v6".into(), "v6".into(), "v6".into()).with_ip_family(IpFamily::V6);
+        let (tx, mut rx) = mpsc::channel(64);
+        node.start(tx).await;
+        assert!(node.bound_addr().await.unwrap().is_ipv6());
+
+        let (p_id, p_me) = keyed_announcer("p");
+        let peer = UdpSocket::bind("[::1]:0").await.unwrap();
+        let announce = p_me.peer().await;
+        peer.send_to(&serde_json::to_vec(&announce).unwrap(), "[::1]:47281").await.unwrap();
+        timeout(Duration::from_secs(2), rx.recv()).await.unwrap().unwrap();
+        let entry = node.peers.lock().await.get(&p_id).unwrap().last_addr;
+        assert_eq!(entry, peer.local_addr().unwrap());
+
+        // Unicast replies go back over v6 from a v6 ephemeral socket.
+        let target = p_id.clone();
+        let sender = tokio::spawn(async move { node.send_direct_block(&target, "hi".into()).await });
+        let mut buf = vec![0u8; MAX_DGRAM];
+        let (n, src) = timeout(Duration::from_secs(2), peer.recv_from(&mut buf)).await.unwrap().unwrap();
+        assert!(src.is_ipv6());
+        let NetworkMessage::DirectBlock { msg_id, .. } = serde_json::from_slice(&buf[..n]).unwrap() else {
+            panic!("expected DirectBlock");
+        };
+        let ack = NetworkMessage::Ack { from: p_id, msg_id };
+        peer.send_to(&serde_json::to_vec(&ack).unwrap(), src).await.unwrap();
+        assert_eq!(sender.await.unwrap().unwrap(), Delivery::Udp { attempts: 1 });
+    }
+
+    #[test]
+    fn discovery_targets_per_family() {
+        assert!(IpFamily::Dual.v4() && IpFamily::Dual.v6());
+        assert!(!IpFamily::V4.v6() && !IpFamily::V6.v4());
+        let group = discovery_target_v6(47_000);
+        assert_eq!(group.ip(), IpAddr::V6(DISCOVERY_GROUP_V6));
+        assert!(unspecified_for(group).is_ipv6());
+        assert!(unspecified_for("10.0.0.1:1".parse().unwrap()).is_ipv4());
+    }
+
+    #[test]
+    fn frames_survive_split_reads_and_reject_oversize() {
+        let msg = NetworkMessage::DirectBlock {
+            from: "a".into(),
+            to: "b".into(),
+            msg_id: String::new(),
+            payload_json: "line one\nline two — ünïcode".into(),
+        };
+        let frame = encode_frame(&msg).unwrap();
+        let mut buf = Vec::new();
+        // Feed byte by byte: nothing pops until the whole frame is there.
+        for (i, byte) in frame.iter().enumerate() {
+            buf.push(*byte);
+            let got = take_frame(&mut buf).unwrap();
+            assert_eq!(got.is_some(), i == frame.len() - 1);
+            if let Some(body) = got {
+                match serde_json::from_slice(&body).unwrap() {
+                    NetworkMessage::DirectBlock { payload_json, .. } => {
+                        assert_eq!(payload_json, "line one\nline two — ünïcode")
+                    }
+                    other => panic!("unexpected {other:?}"),
+                }
+            }
+        }
+        assert!(buf.is_empty());
+
+        let mut huge = ((MAX_TCP_FRAME + 1) as u32).to_be_bytes().to_vec();
+        assert!(take_frame(&mut huge).is_err());
+    }
+
+    /// Started node with a signing key (so its announces are accepted) on
+    /// ephemeral ports; also returns its id and the UDP port it got.
+    async fn ephemeral_node(alias: &str) -> (NetworkNode, mpsc::Receiver<NetworkMessage>, String, u16) {
+        let key = SigningKey::from_bytes(&rand::random::<[u8; 32]>());
+        let id = wichain_core::encode_pubkey_b64(&key.verifying_key().to_bytes());
+        let node = NetworkNode::new(0, id.clone(), alias.into(), id.clone()).with_signing_key(key);
+        let (tx, rx) = mpsc::channel(64);
+        node.start(tx).await;
+        let port = node.bound_addr().await.expect("discovery socket bound").port();
+        (node, rx, id, port)
+    }
+
+    #[tokio::test]
+    async fn ephemeral_nodes_find_each_other_and_deliver_a_direct_block() {
+        // Twice over: nothing is left holding a fixed port.
+        for round in 0..2 {
+            let (a, _rx_a, a_id, _) = ephemeral_node("a").await;
+            let (b, mut rx_b, b_id, b_port) = ephemeral_node("b").await;
+            assert_ne!(b_port, 0);
+            assert_ne!(b.get_tcp_port(), 0);
+
+            let found = a.add_peer_by_addr(SocketAddr::from((Ipv4Addr::LOCALHOST, b_port))).await.unwrap();
+            assert_eq!(found.id, b_id);
+            let payload = format!("{{\"text\":\"hello b, round {round}\"}}");
+            a.send_message(&b_id, payload.clone()).await.unwrap();
+
+            let got = timeout(Duration::from_secs(2), async {
+                loop {
+                    if let Some(NetworkMessage::DirectBlock { from, payload_json, .. }) = rx_b.recv().await {
+                        return (from, payload_json);
+                    }
+                }
+            })
+            .await
+            .expect("B received the block");
+            assert_eq!(got, (a_id, payload));
+            a.stop().await;
+            b.stop().await;
+        }
+    }
+
+    #[tokio::test]
+    async fn probes_answer_through_the_shared_outbound_socket() {
+        let (a, _rx_a, _, _) = ephemeral_node("a").await;
+        let (b, _rx_b, b_id, b_port) = ephemeral_node("b").await;
+        a.add_peer_by_addr(SocketAddr::from((Ipv4Addr::LOCALHOST, b_port))).await.unwrap();
+        a.set_reachability_ttl(Duration::ZERO).await;
+
+        assert!(a.probe_peer(&b_id).await.unwrap());
+        assert!(a.tcp_manager.pending_pongs.lock().await.is_empty());
+
+        b.stop().await;
+        assert!(!a.probe_peer(&b_id).await.unwrap());
+        assert!(a.tcp_manager.pending_pongs.lock().await.is_empty());
+        a.stop().await;
+    }
+
+    #[tokio::test]
+    async fn tcp_handshake_between_nodes_on_different_ports() {
+        let (a, _rx_a) = started_node(47_211, "node-a").await;
+        let (b, mut rx_b) = started_node(47_321, "node-b").await;
+        let b_udp: SocketAddr = "127.0.0.1:47321".parse().unwrap();
+
+        // B must answer a connection request with its *own* TCP port.
+        // The request is unsigned, so its alias can't rename a known peer.
+        update_peer(&b.peers, "node-a", "alice", "node-a", "127.0.0.1:47211".parse().unwrap()).await;
+        let probe = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        let request = NetworkMessage::TcpConnectionRequest {
+            from: "node-a".into(),
+            from_alias: "mallory".into(),
+            tcp_port: a.get_tcp_port(),
+        };
+        probe.send_to(&serde_json::to_vec(&request).unwrap(), b_udp).await.unwrap();
+        let mut buf = vec![0u8; MAX_DGRAM];
+        let (n, _) = timeout(Duration::from_secs(2), probe.recv_from(&mut buf))
+            .await
+            .expect("response in time")
+            .unwrap();
+        let b_tcp = match serde_json::from_slice(&buf[..n]).unwrap() {
+            NetworkMessage::TcpConnectionResponse { tcp_port, accepted, .. } => {
+                assert!(accepted);
+                tcp_port
+            }
+            other => panic!("unexpected reply {other:?}"),
+        };
+        assert_eq!(b_tcp, b.get_tcp_port());
+        assert_ne!(b_tcp, 60_000 + TCP_PORT_OFFSET);
+        assert_eq!(b.peers.lock().await["node-a"].info.alias, "alice");
+
+        // A, knowing B's real ports, connects and handshakes over TCP.
+        update_peer_with_tcp_port(&a.peers, "node-b", "node-b", "node-b", b_udp, Some(b_tcp)).await;
+        a.request_tcp_connection("node-b").await.unwrap();
+        assert!(a.has_tcp_connection("node-b").await);
+
+        // B only forwards post-handshake traffic, so receiving this proves it.
+        // Embedded newlines must survive the framing intact.
+        a.send_via_tcp("node-b", "hello\nworld\n").await.unwrap();
+        let got = timeout(Duration::from_secs(2), async {
+            loop {
+                if let Some(NetworkMessage::DirectBlock { payload_json, .. }) = rx_b.recv().await {
+                    return payload_json;
+                }
+            }
+        })
+        .await
+        .expect("B received the message over TCP");
+        assert_eq!(got, "hello\nworld\n");
+    }
+
+    #[tokio::test]
+    async fn chain_requests_are_answered_in_datagram_sized_pages() {
+        let (a, mut rx_a) = started_node(47_861, "node-a").await;
+        let (b, mut rx_b) = started_node(47_871, "node-b").await;
+        update_peer(&a.peers, "node-b", "node-b", "node-b", "127.0.0.1:47871".parse().unwrap()).await;
+        update_peer(&b.peers, "node-a", "node-a", "node-a", "127.0.0.1:47861".parse().unwrap()).await;
+
+        a.request_chain("node-b", 3).await.unwrap();
+        let since = timeout(Duration::from_secs(2), async {
+            loop {
+                if let Some(NetworkMessage::ChainRequest { from, since_index }) = rx_b.recv().await {
+                    assert_eq!(from, "node-a");
+                    return since_index;
+                }
+            }
+        })
+        .await
+        .expect("request forwarded");
+        assert_eq!(since, 3);
+
+        // Quotes double in size when escaped, so these can't all share a datagram.
+        let pad = "\\\"".repeat(400);
+        let blocks: Vec<String> = (0..8).map(|i| format!("{{\"i\":{i},\"pad\":\"{pad}\"}}")).collect();
+        let sent = b.send_chain_response("node-a", "field-team", &blocks).await.unwrap();
+        assert!(sent > 0 && sent < blocks.len());
+        let (blocks_json, more, network_id) = timeout(Duration::from_secs(2), async {
+            loop {
+                if let Some(NetworkMessage::ChainResponse { blocks_json, more, network_id, .. }) = rx_a.recv().await {
+                    return (blocks_json, more, network_id);
+                }
+            }
+        })
+        .await
+        .expect("response fits a datagram");
+        assert!(more);
+        assert_eq!(network_id.as_deref(), Some("field-team"));
+        let got: Vec<serde_json::Value> = serde_json::from_str(&blocks_json).unwrap();
+        assert_eq!(got.len(), sent);
+
+        let huge = vec!["0".repeat(2 * MAX_DGRAM)];
+        assert!(b.send_chain_response("node-a", "field-team", &huge).await.is_err());
+        assert_eq!(b.send_chain_response("node-a", "field-team", &[]).await.unwrap(), 0);
+    }
+
+    #[tokio::test]
+    async fn peers_can_be_added_by_address_without_discovery() {
+        let keyed = |port| {
+            let key = SigningKey::from_bytes(&rand::random::<[u8; 32]>());
+            let id = wichain_core::encode_pubkey_b64(&key.verifying_key().to_bytes());
+            NetworkNode::new(port, id.clone(), "node".into(), id).with_signing_key(key)
+        };
+        // Different discovery ports, so neither hears the other's broadcasts.
+        let (a, b) = (keyed(47_911), keyed(47_921));
+        let (tx_a, mut rx_a) = mpsc::channel(64);
+        let (tx_b, _rx_b) = mpsc::channel(64);
+        a.start(tx_a).await;
+        b.start(tx_b).await;
+        assert!(a.add_peer_by_addr("[::1]:47921".parse().unwrap()).await.is_err(), "IPv4-only node");
+
+        let added = a.add_peer_by_addr("127.0.0.1:47921".parse().unwrap()).await.unwrap();
+        assert_eq!(added.id, b.id);
+        assert_eq!(a.list_peers().await.len(), 1);
+        // The ping told `b` where `a` listens, so it can reach `a` back.
+        assert!(b.list_peers().await.iter().any(|p| p.id == a.id));
+        b.send_direct_block(&a.id, "hi".into()).await.unwrap();
+        let got = timeout(Duration::from_secs(2), async {
+            loop {
+                if let Some(NetworkMessage::DirectBlock { payload_json, .. }) = rx_a.recv().await {
+                    return payload_json;
+                }
+            }
+        })
+        .await
+        .unwrap();
+        assert_eq!(got, "hi");
+
+        let err = a.add_peer_by_addr("127.0.0.1:47931".parse().unwrap()).await.unwrap_err();
+        assert!(err.to_string().contains("no reply from 127.0.0.1:47931"), "{err}");
+        assert!(a.tcp_manager.pending_pongs.lock().await.is_empty());
+        a.stop().await;
+        b.stop().await;
+    }
+
+    #[tokio::test]
+    async fn group_blocks_are_acked_per_member_and_fall_back_to_direct_sends() {
+        let (b, mut rx_b) = started_node(47_941, "b").await;
+        let (a, _rx_a) = started_node(47_951, "a").await;
+        async fn next_payload(rx: &mut mpsc::Receiver<NetworkMessage>) -> Option<(&'static str, String)> {
+            timeout(Duration::from_secs(3), async {
+                loop {
+                    match rx.recv().await {
+                        Some(NetworkMessage::GroupBlock { payload_json, .. }) => return ("group", payload_json),
+                        Some(NetworkMessage::DirectBlock { payload_json, .. }) => return ("direct", payload_json),
+                        _ => {}
+                    }
+                }
+            })
+            .await
+            .ok()
+        }
+
+        // A broadcast copy is acked under the member's own ack id, every time, but delivered once.
+        let sock = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        let block = NetworkMessage::GroupBlock {
+            from: "carol".into(),
+            group_id: "g1".into(),
+            msg_id: "m1".into(),
+            payload_json: "sealed".into(),
+        };
+        let mut buf = vec![0u8; MAX_DGRAM];
+        for _ in 0..2 {
+            send_to(&sock, &block, "127.0.0.1:47941".parse().unwrap()).await.unwrap();
+            let (n, _) = timeout(Duration::from_secs(2), sock.recv_from(&mut buf)).await.unwrap().unwrap();
+            let NetworkMessage::Ack { from, msg_id } = serde_json::from_slice(&buf[..n]).unwrap() else {
+                panic!("expected an ack");
+            };
+            assert_eq!((from.as_str(), msg_id), ("b", group_ack_id("m1", "b")));
+        }
+        assert_eq!(next_payload(&mut rx_b).await, Some(("group", "sealed".to_string())));
+        assert_eq!(next_payload(&mut rx_b).await, None, "duplicate delivered");
+
+        // `b` listens on another port than `a` broadcasts to, so it gets a direct copy;
+        // an undiscovered member's copy waits in the outbox.
+        update_peer(&a.peers, "b", "b", "b", "127.0.0.1:47941".parse().unwrap()).await;
+        let members = vec!["b".to_string(), "ghost".to_string()];
+        let results = a.send_group_block("g1", &members, "sealed once".into()).await;
+        assert_eq!(results.len(), 2);
+        assert_eq!(results[0].0, "b");
+        assert!(results[0].1.is_ok(), "{:?}", results[0].1);
+        assert_eq!(results[1].0, "ghost");
+        assert_eq!(results[1].1.as_ref().unwrap(), &Delivery::Queued { pending: 1 });
+        assert_eq!(next_payload(&mut rx_b).await, Some(("direct", "sealed once".to_string())));
+
+        a.stop().await;
+        b.stop().await;
     }
 }
@@ -0,0 +1,335 @@
+//! mDNS/DNS-SD discovery (`mdns` feature).
+//!
+//! UDP broadcast doesn't cross some managed switches and carries no service
+//! metadata. With `MdnsMode::Supplement` or `MdnsMode::Only` the node also
+//! advertises itself as a `_wichain._udp.local.` service on its discovery
+//! port, with TXT records `id`, `alias`, `tcp_port` and the `nonce`/`sig` of
+//! a `Peer` announce, and browses for other nodes. A resolved service must
+//! pass `announce_is_authentic` and `AnnounceMarks` like a broadcast
+//! announce does.
+//!
+//! The peers map is keyed by node id, so a peer found both ways is one entry.
+//! mDNS refreshes a known peer (alias, TCP port, last seen) but keeps the
+//! address its datagrams actually came from; only a peer mDNS finds first
+//! takes the advertised address. A new alias is a rename like any other
+//! (`NetworkEvent::AliasChanged`).
+
+use std::collections::HashMap;
+use std::net::{IpAddr, SocketAddr};
+use std::sync::Arc;
+use std::time::Instant;
+
+use mdns_sd::{ServiceDaemon, ServiceEvent, ServiceInfo};
+use tokio::sync::{mpsc, Mutex};
+use tracing::{debug, info, warn};
+
+use crate::{
+    flush_outbox, now_ms, refresh_peer, update_peer_with_tcp_port, Announcer, Blocklist, IpFamily, NetworkMessage,
+    Outbox, PeerEntry, SharedAnnounceMarks, TcpConnectionManager, ANNOUNCE_MAX_SKEW,
+};
+
+/// DNS-SD service type WiChain nodes advertise.
+pub const SERVICE_TYPE: &str = "_wichain._udp.local.";
+
+/// How often the advertisement is re-registered when the alias has changed
+/// or its signed nonce is getting too old for peers to accept.
+const READVERTISE_INTERVAL: std::time::Duration = std::time::Duration::from_secs(15);
+
+/// How mDNS discovery is used next to UDP broadcast.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum MdnsMode {
+    /// Broadcast only.
+    #[default]
+    Off,
+    /// Broadcast and mDNS.
+    Supplement,
+    /// mDNS instead of periodic broadcast (the discovery sockets still
+    /// receive, so peers that broadcast can still reach us).
+    Only,
+}
+
+impl MdnsMode {
+    pub fn enabled(self) -> bool {
+        self != MdnsMode::Off
+    }
+
+    pub fn broadcasts(self) -> bool {
+        self != MdnsMode::Only
+    }
+}
+
+/// Shuts the daemon's thread down when discovery stops.
+struct Daemon(ServiceDaemon);
+
+impl Drop for Daemon {
+    fn drop(&mut self) {
+        let _ = self.0.shutdown();
+    }
+}
+
+/// Everything `run` feeds discovered peers into.
+pub(crate) struct Sink {
+    pub tx: mpsc::Sender<NetworkMessage>,
+    pub peers: Arc<Mutex<HashMap<String, PeerEntry>>>,
+    pub outbox: Outbox,
+    pub blocked: Blocklist,
+    pub tcp_manager: Arc<TcpConnectionManager>,
+    pub announces: SharedAnnounceMarks,
+}
+
+/// Advertise `me` on `port` and feed browsed peers into `sink` until dropped.
+pub(crate) async fn run(me: Announcer, port: u16, tcp_port: u16, family: IpFamily, sink: Sink) {
+    let daemon = match ServiceDaemon::new() {
+        Ok(daemon) => Daemon(daemon),
+        Err(e) => {
+            warn!("mDNS unavailable: {e}");
+            return;
+        }
+    };
+    let events = match daemon.0.browse(SERVICE_TYPE) {
+        Ok(events) => events,
+        Err(e) => {
+            warn!("mDNS browse failed: {e}");
+            return;
+        }
+    };
+    let mut advertised: Option<(String, Instant)> = None;
+    let mut readvertise = tokio::time::interval(READVERTISE_INTERVAL);
+    loop {
+        tokio::select! {
+            _ = readvertise.tick() => {
+                let alias = me.alias.lock().await.clone();
+                let current = advertised
+                    .as_ref()
+                    .is_some_and(|(a, at)| *a == alias && at.elapsed() < ANNOUNCE_MAX_SKEW / 2);
+                if !current {
+                    match advertise(&daemon.0, &me, port, tcp_port).await {
+                        Ok(()) => advertised = Some((alias, Instant::now())),
+                        Err(e) => warn!("mDNS advertise failed: {e}"),
+                    }
+                }
+            }
+            event = events.recv_async() => match event {
+                Ok(ServiceEvent::ServiceResolved(service)) => accept(&service, &me.id, family, &sink).await,
+                Ok(_) => {}
+                Err(_) => {
+                    warn!("mDNS daemon stopped");
+                    return;
+                }
+            },
+        }
+    }
+}
+
+async fn advertise(daemon: &ServiceDaemon, me: &Announcer, port: u16, tcp_port: u16) -> anyhow::Result<()> {
+    let (alias, nonce, sig) = me.signed().await;
+    let properties = [
+        ("id", me.id.clone()),
+        ("alias", alias),
+        ("tcp_port", tcp_port.to_string()),
+        ("nonce", nonce.to_string()),
+        ("sig", sig),
+    ];
+    // Instance names are at most 63 bytes, and DNS labels shouldn't hold `/`.
+    let instance: String = me.id.chars().filter(char::is_ascii_alphanumeric).take(32).collect();
+    let host = format!("wichain-{instance}.local.");
+    let service = ServiceInfo::new(SERVICE_TYPE, &instance, &host, (), port, &properties[..])?.enable_addr_auto();
+    daemon.register(service)?;
+    info!("📣 mDNS: advertising {instance} on port {port}");
+    Ok(())
+}
+
+/// The announce a resolved service stands for, and where to reach it.
+fn parse(service: &ServiceInfo, family: IpFamily) -> Option<(NetworkMessage, SocketAddr, u16)> {
+    let id = service.get_property_val_str("id")?.to_string();
+    let announce = NetworkMessage::Peer {
+        pubkey: id.clone(),
+        id,
+        alias: service.get_property_val_str("alias")?.to_string(),
+        nonce: service.get_property_val_str("nonce")?.parse().ok()?,
+        sig: service.get_property_val_str("sig")?.to_string(),
+        capabilities: None,
+    };
+    let tcp_port = service.get_property_val_str("tcp_port")?.parse().ok()?;
+    let usable = |ip: &&IpAddr| match ip {
+        IpAddr::V4(_) => family.v4(),
+        IpAddr::V6(v6) => family.v6() && !v6.is_unicast_link_local(),
+    };
+    // Prefer IPv4 so the choice doesn't depend on set iteration order.
+    let ip = service
+        .get_addresses()
+        .iter()
+        .filter(usable)
+        .min_by_key(|ip| (ip.is_ipv6(), **ip))?;
+    Some((announce, SocketAddr::new(*ip, service.get_port()), tcp_port))
+}
+
+async fn accept(service: &ServiceInfo, my_id: &str, family: IpFamily, sink: &Sink) {
+    let Some((announce, addr, tcp_port)) = parse(service, family) else {
+        debug!("mDNS: ignoring {} (incomplete record)", service.get_fullname());
+        return;
+    };
+    let NetworkMessage::Peer { id, alias, .. } = &announce else { unreachable!() };
+    if id == my_id || sink.blocked.read().await.contains(id) {
+        return;
+    }
+    if !announce.announce_is_authentic() {
+        debug!("mDNS: dropping unsigned or forged record {}", service.get_fullname());
+        return;
+    }
+    if !sink.announces.lock().await.admit(&announce, now_ms(), Instant::now()) {
+        debug!("mDNS: dropping stale or replayed record {}", service.get_fullname());
+        return;
+    }
+    let known = {
+        let mut peers = sink.peers.lock().await;
+        peers.get_mut(id).map(|entry| refresh_peer(entry, id, alias, id, Some(tcp_port)))
+    };
+    match known {
+        Some(renamed) => {
+            if let Some(renamed) = renamed {
+                let _ = sink.tcp_manager.events.send(renamed);
+            }
+        }
+        None => {
+            update_peer_with_tcp_port(&sink.peers, id, alias, id, addr, Some(tcp_port)).await;
+            flush_outbox(&sink.outbox, my_id, id, addr, &sink.tcp_manager).await;
+            info!("📡 mDNS: discovered {} at {}", id, addr);
+        }
+    }
+    let _ = sink.tx.send(announce).await;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{update_peer, NetworkEvent, NetworkNode};
+    use ed25519_dalek::SigningKey;
+    use std::time::Duration;
+    use tokio::time::timeout;
+
+    fn keyed_node(port: u16, alias: &str) -> NetworkNode {
+        let key = SigningKey::from_bytes(&rand::random::<[u8; 32]>());
+        let id = wichain_core::encode_pubkey_b64(&key.verifying_key().to_bytes());
+        NetworkNode::new(port, id.clone(), alias.into(), id)
+            .with_signing_key(key)
+            .with_mdns(MdnsMode::Only)
+    }
+
+    async fn tcp_port_of(node: &NetworkNode, peer_id: &str) -> Option<u16> {
+        node.peers.lock().await.get(peer_id).and_then(|p| p.tcp_port)
+    }
+
+    #[test]
+    fn records_must_carry_a_valid_announce_signature() {
+        let node = keyed_node(47_901, "alice");
+        let me = node.announcer();
+        let rt = tokio::runtime::Builder::new_current_thread().build().unwrap();
+        let (alias, nonce, sig) = rt.block_on(me.signed());
+        let record = |alias: &str| {
+            let properties = [
+                ("id", me.id.clone()),
+                ("alias", alias.to_string()),
+                ("tcp_port", "48901".to_string()),
+                ("nonce", nonce.to_string()),
+                ("sig", sig.clone()),
+            ];
+            ServiceInfo::new(SERVICE_TYPE, "alice", "alice.local.", "192.0.2.9,fd00::9", 47_901, &properties[..]).unwrap()
+        };
+
+        let (announce, addr, tcp_port) = parse(&record(&alias), IpFamily::Dual).unwrap();
+        assert!(announce.announce_is_authentic());
+        assert_eq!(addr, "192.0.2.9:47901".parse().unwrap());
+        assert_eq!(tcp_port, 48_901);
+        let (_, addr, _) = parse(&record(&alias), IpFamily::V6).unwrap();
+        assert_eq!(addr, "[fd00::9]:47901".parse().unwrap());
+
+        let (forged, ..) = parse(&record("mallory"), IpFamily::V4).unwrap();
+        assert!(!forged.announce_is_authentic());
+    }
+
+    #[tokio::test]
+    async fn a_new_alias_in_a_record_renames_the_known_peer() {
+        let a = keyed_node(47_911, "alice");
+        let b = keyed_node(47_921, "bob");
+        let heard_at: SocketAddr = "127.0.0.1:47911".parse().unwrap();
+        update_peer(&b.peers, &a.id, "al", &a.id, heard_at).await;
+        let mut events = b.subscribe_events();
+
+        let (alias, nonce, sig) = a.announcer().signed().await;
+        let properties = [
+            ("id", a.id.clone()),
+            ("alias", alias),
+            ("tcp_port", "48911".to_string()),
+            ("nonce", nonce.to_string()),
+            ("sig", sig),
+        ];
+        let record = ServiceInfo::new(SERVICE_TYPE, "alice", "alice.local.", "192.0.2.9", 47_911, &properties[..]).unwrap();
+        let (tx, _rx) = mpsc::channel(4);
+        let sink = Sink {
+            tx,
+            peers: b.peers.clone(),
+            outbox: b.outbox.clone(),
+            blocked: b.blocked.clone(),
+            tcp_manager: b.tcp_manager.clone(),
+            announces: b.announces.clone(),
+        };
+        accept(&record, &b.id, IpFamily::V4, &sink).await;
+
+        match events.try_recv() {
+            Ok(NetworkEvent::AliasChanged { peer_id, old_alias, new_alias }) => {
+                assert_eq!((peer_id, old_alias.as_str(), new_alias.as_str()), (a.id.clone(), "al", "alice"));
+            }
+            other => panic!("expected a rename, got {other:?}"),
+        }
+        let peers = b.peers.lock().await;
+        assert_eq!(peers[&a.id].info.alias, "alice");
+        assert_eq!(peers[&a.id].last_addr, heard_at, "address kept");
+        drop(peers);
+
+        // The same record again is no rename.
+        accept(&record, &b.id, IpFamily::V4, &sink).await;
+        assert!(events.try_recv().is_err());
+    }
+
+    #[tokio::test]
+    async fn nodes_discover_each_other_over_mdns() {
+        let a = keyed_node(47_881, "alice");
+        let b = keyed_node(47_891, "bob");
+        // `a` already heard `b` broadcast from here; mDNS must not move it.
+        let heard_at: SocketAddr = "127.0.0.1:47891".parse().unwrap();
+        update_peer(&a.peers, &b.id, "bob", &b.id, heard_at).await;
+
+        let (tx_a, _rx_a) = mpsc::channel(64);
+        let (tx_b, mut rx_b) = mpsc::channel(64);
+        a.start(tx_a).await;
+        b.start(tx_b).await;
+
+        let found = timeout(Duration::from_secs(10), async {
+            loop {
+                if let Some(NetworkMessage::Peer { id, alias, .. }) = rx_b.recv().await {
+                    if id == a.id {
+                        return alias;
+                    }
+                }
+            }
+        })
+        .await
+        .expect("b resolves a's service");
+        assert_eq!(found, "alice");
+        assert_eq!(tcp_port_of(&b, &a.id).await, Some(48_881));
+
+        timeout(Duration::from_secs(10), async {
+            while tcp_port_of(&a, &b.id).await.is_none() {
+                tokio::time::sleep(Duration::from_millis(50)).await;
+            }
+        })
+        .await
+        .expect("a resolves b's service");
+        assert_eq!(a.list_peers().await.len(), 1, "one entry per peer");
+        assert_eq!(a.peers.lock().await[&b.id].last_addr, heard_at);
+
+        a.stop().await;
+        b.stop().await;
+    }
+}
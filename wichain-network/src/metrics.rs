@@ -0,0 +1,78 @@
+//! Traffic counters for diagnostics.
+//!
+//! Every send and receive path bumps a relaxed atomic, so counting costs next
+//! to nothing and `NetworkNode::metrics` can be polled freely. Bytes cover
+//! UDP datagrams and TCP frames alike (frame length prefix included).
+
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use serde::{Deserialize, Serialize};
+
+/// Snapshot of a node's traffic since it was created.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct NetworkMetrics {
+    pub datagrams_sent: u64,
+    pub datagrams_received: u64,
+    pub bytes_sent: u64,
+    pub bytes_received: u64,
+    pub tcp_messages_sent: u64,
+    pub tcp_messages_received: u64,
+    /// Datagrams or TCP frames that weren't a valid `NetworkMessage`.
+    pub parse_failures: u64,
+    pub peer_count: usize,
+}
+
+#[derive(Debug, Default)]
+pub(crate) struct Counters {
+    datagrams_sent: AtomicU64,
+    datagrams_received: AtomicU64,
+    bytes_sent: AtomicU64,
+    bytes_received: AtomicU64,
+    tcp_messages_sent: AtomicU64,
+    tcp_messages_received: AtomicU64,
+    parse_failures: AtomicU64,
+}
+
+fn bump(counter: &AtomicU64, by: usize) {
+    counter.fetch_add(by as u64, Ordering::Relaxed);
+}
+
+impl Counters {
+    pub(crate) fn datagram_sent(&self, bytes: usize) {
+        bump(&self.datagrams_sent, 1);
+        bump(&self.bytes_sent, bytes);
+    }
+
+    pub(crate) fn datagram_received(&self, bytes: usize) {
+        bump(&self.datagrams_received, 1);
+        bump(&self.bytes_received, bytes);
+    }
+
+    pub(crate) fn tcp_sent(&self, bytes: usize) {
+        bump(&self.tcp_messages_sent, 1);
+        bump(&self.bytes_sent, bytes);
+    }
+
+    pub(crate) fn tcp_received(&self, bytes: usize) {
+        bump(&self.tcp_messages_received, 1);
+        bump(&self.bytes_received, bytes);
+    }
+
+    pub(crate) fn parse_failure(&self) {
+        bump(&self.parse_failures, 1);
+    }
+
+    pub(crate) fn snapshot(&self, peer_count: usize) -> NetworkMetrics {
+        let get = |c: &AtomicU64| c.load(Ordering::Relaxed);
+        NetworkMetrics {
+            datagrams_sent: get(&self.datagrams_sent),
+            datagrams_received: get(&self.datagrams_received),
+            bytes_sent: get(&self.bytes_sent),
+            bytes_received: get(&self.bytes_received),
+            tcp_messages_sent: get(&self.tcp_messages_sent),
+            tcp_messages_received: get(&self.tcp_messages_received),
+            parse_failures: get(&self.parse_failures),
+            peer_count,
+        }
+    }
+}
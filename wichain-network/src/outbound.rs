@@ -0,0 +1,143 @@
+//! Shared outbound UDP sockets.
+//!
+//! Direct blocks, announces, receipts and TCP connection requests go out
+//! through one ephemeral socket per address family, bound on first use,
+//! rather than a freshly bound socket per send (which burns an ephemeral port
+//! each time). Peers ack on the socket a block came from, so a background
+//! reader on each socket hands every `Ack` to the send waiting on its
+//! `msg_id`; concurrent sends share the socket without stealing each other's
+//! acks. Pongs to pings sent from here resolve the same `pending_pongs`
+//! waiters the discovery loop does.
+
+use std::collections::HashMap;
+use std::net::SocketAddr;
+use std::sync::{Arc, Mutex as StdMutex};
+use std::time::Duration;
+
+use tokio::net::UdpSocket;
+use tokio::sync::{oneshot, Mutex};
+use tokio::task::JoinHandle;
+use tokio::time::timeout;
+
+use super::{now_ms, unspecified_for, Counters, NetworkMessage, MAX_DGRAM};
+
+type AckWaiters = Arc<StdMutex<HashMap<String, oneshot::Sender<()>>>>;
+/// Pong waiters keyed by the address pinged; get the id of the node that answered.
+pub(crate) type PongWaiters = Arc<Mutex<HashMap<SocketAddr, oneshot::Sender<String>>>>;
+
+#[derive(Debug)]
+pub(crate) struct Outbound {
+    v4: Mutex<Option<Arc<UdpSocket>>>,
+    v6: Mutex<Option<Arc<UdpSocket>>>,
+    acks: AckWaiters,
+    pongs: PongWaiters,
+    metrics: Arc<Counters>,
+    readers: StdMutex<Vec<JoinHandle<()>>>,
+}
+
+impl Outbound {
+    pub(crate) fn new(metrics: Arc<Counters>, pongs: PongWaiters) -> Self {
+        Self {
+            v4: Mutex::new(None),
+            v6: Mutex::new(None),
+            acks: Arc::default(),
+            pongs,
+            metrics,
+            readers: StdMutex::new(Vec::new()),
+        }
+    }
+
+    /// The socket for `addr`'s family, bound (broadcast enabled on IPv4) on first use.
+    pub(crate) async fn socket_for(&self, addr: SocketAddr) -> std::io::Result<Arc<UdpSocket>> {
+        let mut slot = if addr.is_ipv4() { self.v4.lock().await } else { self.v6.lock().await };
+        if let Some(socket) = slot.as_ref() {
+            return Ok(socket.clone());
+        }
+        let socket = Arc::new(UdpSocket::bind(unspecified_for(addr)).await?);
+        if addr.is_ipv4() {
+            socket.set_broadcast(true)?;
+        }
+        let reader = tokio::spawn(read_replies(
+            socket.clone(),
+            self.acks.clone(),
+            self.pongs.clone(),
+            self.metrics.clone(),
+        ));
+        self.readers.lock().unwrap().push(reader);
+        *slot = Some(socket.clone());
+        Ok(socket)
+    }
+
+    /// Send one datagram to `addr`.
+    pub(crate) async fn send(&self, bytes: &[u8], addr: SocketAddr) -> std::io::Result<()> {
+        self.socket_for(addr).await?.send_to(bytes, addr).await?;
+        self.metrics.datagram_sent(bytes.len());
+        Ok(())
+    }
+
+    /// Serialize and send `msg` to `addr`.
+    pub(crate) async fn send_msg(&self, msg: &NetworkMessage, addr: SocketAddr) -> anyhow::Result<()> {
+        self.send(&serde_json::to_vec(msg)?, addr).await?;
+        Ok(())
+    }
+
+    /// Start listening for the `Ack` of `msg_id`. Register before the first
+    /// send so a fast ack isn't missed; dropping the waiter gives up.
+    pub(crate) fn expect_ack(&self, msg_id: &str) -> AckWaiter {
+        let (tx, rx) = oneshot::channel();
+        self.acks.lock().unwrap().insert(msg_id.to_string(), tx);
+        AckWaiter { msg_id: msg_id.to_string(), rx, acks: self.acks.clone() }
+    }
+}
+
+impl Drop for Outbound {
+    fn drop(&mut self) {
+        for reader in self.readers.get_mut().unwrap().drain(..) {
+            reader.abort();
+        }
+    }
+}
+
+pub(crate) struct AckWaiter {
+    msg_id: String,
+    rx: oneshot::Receiver<()>,
+    acks: AckWaiters,
+}
+
+impl AckWaiter {
+    /// Wait up to `wait` for the ack (`false` on timeout; call again to keep waiting).
+    pub(crate) async fn wait(&mut self, wait: Duration) -> bool {
+        matches!(timeout(wait, &mut self.rx).await, Ok(Ok(())))
+    }
+}
+
+impl Drop for AckWaiter {
+    fn drop(&mut self) {
+        self.acks.lock().unwrap().remove(&self.msg_id);
+    }
+}
+
+/// Route `Ack`s and authentic, recent `Pong`s arriving on an outbound socket to their waiters.
+async fn read_replies(socket: Arc<UdpSocket>, acks: AckWaiters, pongs: PongWaiters, metrics: Arc<Counters>) {
+    let mut buf = vec![0u8; MAX_DGRAM];
+    loop {
+        let Ok((n, src)) = socket.recv_from(&mut buf).await else {
+            continue;
+        };
+        metrics.datagram_received(n);
+        match serde_json::from_slice(&buf[..n]) {
+            Ok(NetworkMessage::Ack { msg_id, .. }) => {
+                if let Some(waiter) = acks.lock().unwrap().remove(&msg_id) {
+                    let _ = waiter.send(());
+                }
+            }
+            Ok(pong @ NetworkMessage::Pong { .. }) if pong.announce_is_authentic() && pong.announce_is_recent(now_ms()) => {
+                if let (Some(id), Some(waiter)) = (pong.sender_id(), pongs.lock().await.remove(&src)) {
+                    let _ = waiter.send(id.to_string());
+                }
+            }
+            Ok(_) => {}
+            Err(_) => metrics.parse_failure(),
+        }
+    }
+}
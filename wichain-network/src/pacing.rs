@@ -0,0 +1,122 @@
+//! Adaptive interval for the periodic announce + ping broadcast.
+//!
+//! Broadcasts start at the configured base interval. Each round in which no
+//! new peer showed up counts as stable; after `STABLE_ROUNDS` stable rounds
+//! the interval doubles, up to `MAX_BROADCAST_INTERVAL` (or the base, if that
+//! is larger). A new peer, `ping_now` or a new base drops straight back to the
+//! base. Peers leaving don't reset it: the survivors are still known.
+
+use std::collections::HashSet;
+use std::sync::Mutex;
+use std::time::Duration;
+
+use tokio::sync::Notify;
+
+pub(crate) const MAX_BROADCAST_INTERVAL: Duration = Duration::from_secs(8);
+pub(crate) const STABLE_ROUNDS: u32 = 5;
+
+#[derive(Debug)]
+pub(crate) struct BroadcastPacer {
+    base: Duration,
+    current: Duration,
+    stable_rounds: u32,
+    known: HashSet<String>,
+}
+
+impl BroadcastPacer {
+    pub(crate) fn new(base: Duration) -> Self {
+        Self { base, current: base, stable_rounds: 0, known: HashSet::new() }
+    }
+
+    pub(crate) fn current(&self) -> Duration {
+        self.current
+    }
+
+    /// Back to the base interval (`ping_now`, new peer).
+    pub(crate) fn reset(&mut self) {
+        self.current = self.base;
+        self.stable_rounds = 0;
+    }
+
+    pub(crate) fn set_base(&mut self, base: Duration) {
+        self.base = base;
+        self.reset();
+    }
+
+    /// Wait before the next round, given the peers known after this one.
+    pub(crate) fn next_interval(&mut self, peers: HashSet<String>) -> Duration {
+        let newcomer = peers.iter().any(|p| !self.known.contains(p));
+        self.known = peers;
+        if newcomer {
+            self.reset();
+            return self.current;
+        }
+        self.stable_rounds += 1;
+        if self.stable_rounds >= STABLE_ROUNDS {
+            self.stable_rounds = 0;
+            self.current = (self.current * 2).min(MAX_BROADCAST_INTERVAL.max(self.base));
+        }
+        self.current
+    }
+}
+
+/// Pacer shared by a node and its broadcast task. `wake` cuts the current
+/// wait short so changes apply without restarting the task.
+#[derive(Debug)]
+pub(crate) struct Pacing {
+    pub(crate) pacer: Mutex<BroadcastPacer>,
+    pub(crate) wake: Notify,
+}
+
+impl Pacing {
+    pub(crate) fn new(base: Duration) -> Self {
+        Self { pacer: Mutex::new(BroadcastPacer::new(base)), wake: Notify::new() }
+    }
+
+    /// Apply `change` to the pacer and wake the broadcast task.
+    pub(crate) fn update(&self, change: impl FnOnce(&mut BroadcastPacer)) {
+        change(&mut self.pacer.lock().unwrap());
+        self.wake.notify_one();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn peers(ids: &[&str]) -> HashSet<String> {
+        ids.iter().map(|s| s.to_string()).collect()
+    }
+
+    #[test]
+    fn widens_while_stable_and_resets_on_newcomers() {
+        let base = Duration::from_millis(500);
+        let mut pacer = BroadcastPacer::new(base);
+        assert_eq!(pacer.next_interval(peers(&["a"])), base, "first sighting of a");
+
+        let mut seen = Vec::new();
+        for _ in 0..STABLE_ROUNDS * 6 {
+            seen.push(pacer.next_interval(peers(&["a"])));
+        }
+        assert_eq!(seen[STABLE_ROUNDS as usize - 2], base);
+        assert_eq!(seen[STABLE_ROUNDS as usize - 1], base * 2);
+        assert_eq!(*seen.last().unwrap(), MAX_BROADCAST_INTERVAL, "capped");
+
+        // A peer leaving is not news; one arriving is.
+        assert_eq!(pacer.next_interval(peers(&[])), MAX_BROADCAST_INTERVAL);
+        assert_eq!(pacer.next_interval(peers(&["a"])), base);
+
+        for _ in 0..STABLE_ROUNDS {
+            pacer.next_interval(peers(&["a"]));
+        }
+        assert!(pacer.current() > base);
+        pacer.reset();
+        assert_eq!(pacer.current(), base);
+
+        // A base above the cap is never narrowed.
+        pacer.set_base(Duration::from_secs(20));
+        for _ in 0..STABLE_ROUNDS * 2 {
+            assert_eq!(pacer.next_interval(peers(&["a"])), Duration::from_secs(20));
+        }
+    }
+}
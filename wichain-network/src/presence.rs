@@ -0,0 +1,103 @@
+//! Join/leave transitions for the peer roster.
+//!
+//! The peer GC task diffs the roster against what it has already reported
+//! once per `PEER_GC_INTERVAL`. An id it hasn't reported is a `PeerJoined`.
+//! One that dropped out of the roster becomes a `PeerLeft` only once it has
+//! stayed gone for the debounce window; a flapping peer that times out and
+//! is back within the window produces neither event.
+
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+
+use super::{NetworkEvent, PeerInfo};
+
+/// Longest a dropped peer has to come back before its `PeerLeft` is sent.
+pub(crate) const PRESENCE_DEBOUNCE: Duration = Duration::from_secs(5);
+
+#[derive(Debug, Default)]
+pub(crate) struct Presence {
+    /// Reported joined and still in the roster.
+    online: HashMap<String, PeerInfo>,
+    /// Dropped from the roster at the given time, not yet reported as left.
+    leaving: HashMap<String, (PeerInfo, Instant)>,
+}
+
+impl Presence {
+    /// Compare `roster` with what was reported so far; returns the events
+    /// to send, joins first.
+    pub(crate) fn update(&mut self, roster: Vec<PeerInfo>, now: Instant, debounce: Duration) -> Vec<NetworkEvent> {
+        let mut events = Vec::new();
+        let mut current = HashMap::with_capacity(roster.len());
+        for peer in roster {
+            let known = self.online.remove(&peer.id).is_some() || self.leaving.remove(&peer.id).is_some();
+            if !known {
+                events.push(NetworkEvent::PeerJoined { peer: peer.clone() });
+            }
+            current.insert(peer.id.clone(), peer);
+        }
+        for (id, peer) in std::mem::replace(&mut self.online, current) {
+            self.leaving.insert(id, (peer, now));
+        }
+        let due: Vec<String> = self
+            .leaving
+            .iter()
+            .filter(|(_, (_, since))| now.duration_since(*since) >= debounce)
+            .map(|(id, _)| id.clone())
+            .collect();
+        for id in due {
+            if let Some((peer, _)) = self.leaving.remove(&id) {
+                events.push(NetworkEvent::PeerLeft { peer });
+            }
+        }
+        events
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn peer(id: &str) -> PeerInfo {
+        PeerInfo {
+            id: id.into(),
+            alias: id.into(),
+            pubkey: id.into(),
+            last_seen_ms: 0,
+            connection_type: "UDP".into(),
+            tcp_port: None,
+            away: false,
+        }
+    }
+
+    fn ids(events: &[NetworkEvent]) -> Vec<String> {
+        events
+            .iter()
+            .map(|e| match e {
+                NetworkEvent::PeerJoined { peer } => format!("+{}", peer.id),
+                NetworkEvent::PeerLeft { peer } => format!("-{}", peer.id),
+                other => panic!("unexpected {other:?}"),
+            })
+            .collect()
+    }
+
+    #[test]
+    fn flapping_peers_neither_leave_nor_rejoin() {
+        let mut presence = Presence::default();
+        let t0 = Instant::now();
+        let debounce = Duration::from_secs(5);
+        let at = |secs| t0 + Duration::from_secs(secs);
+
+        assert_eq!(ids(&presence.update(vec![peer("a"), peer("b")], at(0), debounce)), ["+a", "+b"]);
+        assert!(presence.update(vec![peer("a"), peer("b")], at(1), debounce).is_empty());
+
+        // `b` drops out briefly: nothing.
+        assert!(presence.update(vec![peer("a")], at(2), debounce).is_empty());
+        assert!(presence.update(vec![peer("a"), peer("b")], at(4), debounce).is_empty());
+
+        // `b` stays gone past the window.
+        assert!(presence.update(vec![peer("a")], at(5), debounce).is_empty());
+        assert!(presence.update(vec![peer("a")], at(9), debounce).is_empty());
+        assert_eq!(ids(&presence.update(vec![peer("a")], at(10), debounce)), ["-b"]);
+        assert_eq!(ids(&presence.update(vec![peer("a"), peer("b")], at(11), debounce)), ["+b"]);
+    }
+}